@@ -0,0 +1,28 @@
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+
+use crate::{
+  breaker::CircuitBreaker, budget::MemoryBudget, checksum::ChecksumFiles, host::HostLimiter,
+  limiter::RateLimiter, poison::PoisonedMirrors,
+};
+
+/// Resources shared by every download of one batch.
+#[derive(Debug)]
+pub struct Batch {
+  pub client: reqwest::Client,
+  /// 控制并发下载数量
+  pub semaphore: Semaphore,
+  /// 全局限速器由本批次的所有下载共享
+  pub limiter: Option<Arc<RateLimiter>>,
+  /// 限制每个主机的并发连接数
+  pub hosts: Option<Arc<HostLimiter>>,
+  /// 本批次中提供过错误数据的镜像
+  pub poisoned: Arc<PoisonedMirrors>,
+  /// 连续失败次数过多而被放弃的主机
+  pub breaker: Option<Arc<CircuitBreaker>>,
+  /// 所有下载缓冲数据的内存预算
+  pub memory: Option<Arc<MemoryBudget>>,
+  /// 已下载的校验和文件，由本批次的所有下载共享
+  pub checksums: ChecksumFiles,
+}
@@ -0,0 +1,229 @@
+//! `pdl`, a command line downloader built on `robust_downloader`.
+//!
+//! Build it with `cargo install robust_downloader --features cli`.
+
+use std::{error::Error, path::PathBuf, process::ExitCode, sync::Arc, time::Duration};
+
+use clap::{CommandFactory, Parser, error::ErrorKind};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use robust_downloader::{
+  ChecksumFile, DEFAULT_USER_AGENT, DownloadItem, DownloadResult, DownloadStatus, HashAlgorithm,
+  Integrity, Manifest, ProgressBarReporter, ProgressReporter, RetryPolicy, RobustDownloader,
+  ShutdownHandle,
+};
+
+/// Downloads files concurrently, with retries, resumable partial files and progress bars.
+///
+/// Interrupted downloads continue where they stopped when `pdl` is run again with the
+/// same output directory. Ctrl-C stops all downloads and keeps their partial files.
+#[derive(Debug, Parser)]
+#[command(name = "pdl", version)]
+struct Args {
+  /// URLs to download.
+  #[arg(required_unless_present = "manifest")]
+  urls: Vec<String>,
+
+  /// Directory the URLs are saved to, named after the server's file name.
+  #[arg(short, long, value_name = "DIR", default_value = ".")]
+  output_dir: PathBuf,
+
+  /// JSON or TOML manifest listing files to download in addition to the URLs.
+  #[arg(short, long, value_name = "FILE")]
+  manifest: Option<PathBuf>,
+
+  /// Maximum number of concurrent downloads.
+  #[arg(short = 'j', long, value_name = "N", default_value_t = 2)]
+  concurrency: usize,
+
+  /// Number of parallel connections used to download each file.
+  #[arg(short, long, value_name = "N", default_value_t = 1)]
+  segments: usize,
+
+  /// Expected checksum of a URL, e.g. `sha256:e3b0c442...` or `sha384-<base64>`. Given
+  /// once per URL, in the order of the URLs.
+  #[arg(short, long = "checksum", value_name = "ALGO:HEX")]
+  checksums: Vec<Integrity>,
+
+  /// URL of a checksum file like `SHA256SUMS` listing the checksums of the URLs without a
+  /// `--checksum`, found by their file names.
+  #[arg(long, value_name = "URL")]
+  checksum_file: Option<String>,
+
+  /// Hash algorithm of the digest printed for every file, e.g. `sha256`.
+  #[arg(short, long, value_name = "ALGO")]
+  digest: Option<HashAlgorithm>,
+
+  /// Extra HTTP header sent with every request.
+  #[arg(short = 'H', long = "header", value_name = "NAME: VALUE", value_parser = parse_header)]
+  headers: Vec<(HeaderName, HeaderValue)>,
+
+  /// `User-Agent` header sent with every request.
+  #[arg(short = 'A', long, value_name = "AGENT", default_value = DEFAULT_USER_AGENT)]
+  user_agent: String,
+
+  /// Skip files that already exist and are unchanged on the server.
+  #[arg(long)]
+  skip_unchanged: bool,
+
+  /// Maximum number of retries of each file. Retries for up to 2 minutes by default.
+  #[arg(long, value_name = "N")]
+  retries: Option<usize>,
+
+  /// Time to wait for the response headers of each request in seconds.
+  #[arg(long, value_name = "SECS", default_value_t = 60)]
+  timeout: u64,
+
+  /// Maximum combined download speed in bytes per second.
+  #[arg(long, value_name = "BYTES")]
+  limit_rate: Option<u64>,
+
+  /// Hide the progress bars, printing only errors and digests.
+  #[arg(short, long)]
+  quiet: bool,
+
+  /// Print one JSON object per file instead of progress bars.
+  #[arg(long, conflicts_with = "quiet")]
+  json: bool,
+}
+
+/// Reports nothing, for `--quiet` and `--json`.
+struct Silent;
+
+impl ProgressReporter for Silent {}
+
+fn parse_header(value: &str) -> Result<(HeaderName, HeaderValue), Box<dyn Error + Send + Sync>> {
+  let (name, value) = value
+    .split_once(':')
+    .ok_or("expected a header like `Name: value`")?;
+  Ok((name.trim().parse()?, value.trim().parse()?))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+  let args = Args::parse();
+  if args.checksums.len() > args.urls.len() {
+    Args::command()
+      .error(
+        ErrorKind::TooManyValues,
+        "more checksums than URLs were given",
+      )
+      .exit();
+  }
+
+  match run(args).await {
+    Ok(code) => code,
+    Err(err) => {
+      eprintln!("pdl: {err}");
+      ExitCode::from(2)
+    }
+  }
+}
+
+async fn run(args: Args) -> Result<ExitCode, Box<dyn Error>> {
+  let shutdown = ShutdownHandle::new();
+  shutdown.shutdown_on_ctrl_c();
+
+  let reporter: Arc<dyn ProgressReporter> = if args.quiet || args.json {
+    Arc::new(Silent)
+  } else {
+    Arc::new(ProgressBarReporter::default())
+  };
+
+  let mut headers = HeaderMap::new();
+  for (name, value) in args.headers {
+    headers.append(name, value);
+  }
+
+  let downloader = RobustDownloader::builder()
+    .max_concurrent(args.concurrency)
+    .segments_per_file(args.segments)
+    .skip_unchanged(args.skip_unchanged)
+    .timeout(Duration::from_secs(args.timeout))
+    .retry_policy(RetryPolicy {
+      max_attempts: args.retries.map(|retries| retries + 1),
+      ..Default::default()
+    })
+    .max_bytes_per_sec_opt(args.limit_rate)
+    .digest_algorithm_opt(args.digest)
+    .headers(headers)
+    .user_agent(args.user_agent)
+    .reporter(reporter)
+    .shutdown(shutdown)
+    .build();
+
+  let queue = downloader.queue()?;
+  if let Some(path) = &args.manifest {
+    Manifest::from_path(path).await?.push_to(&queue)?;
+  }
+
+  if !args.urls.is_empty() {
+    tokio::fs::create_dir_all(&args.output_dir).await?;
+    let targets = downloader
+      .resolve_targets(&args.urls, &args.output_dir)
+      .await?;
+
+    let checksum_file = args
+      .checksum_file
+      .map(|url| ChecksumFile::builder().url(url).build());
+    let mut checksums = args.checksums.into_iter();
+    for (url, target) in args.urls.into_iter().zip(targets) {
+      let item = DownloadItem::builder().url(url).target(target).build();
+      queue.push(
+        DownloadItem {
+          integrity: checksums.next(),
+          checksum_file: checksum_file.clone(),
+          ..item
+        },
+        0,
+      );
+    }
+  }
+
+  queue.close();
+  let results = queue.run().await;
+
+  for result in &results {
+    print_result(result, args.json);
+  }
+
+  Ok(if results.iter().any(DownloadResult::is_interrupted) {
+    ExitCode::from(130)
+  } else if results.iter().all(DownloadResult::is_success) {
+    ExitCode::SUCCESS
+  } else {
+    ExitCode::FAILURE
+  })
+}
+
+fn print_result(result: &DownloadResult, json: bool) {
+  if json {
+    let (status, error) = match &result.result {
+      Ok(DownloadStatus::Downloaded) => ("downloaded", None),
+      Ok(DownloadStatus::Skipped) => ("skipped", None),
+      Ok(DownloadStatus::Cached) => ("cached", None),
+      Err(_) if result.is_interrupted() => ("interrupted", None),
+      Err(err) => ("failed", Some(err.to_string())),
+    };
+    let line = serde_json::json!({
+      "url": result.url,
+      "target": result.target,
+      "final_url": result.final_url,
+      "http_version": result.http_version.map(|version| format!("{version:?}")),
+      "status": status,
+      "digest": result.digest,
+      "error": error,
+    });
+    println!("{line}");
+    return;
+  }
+
+  match &result.result {
+    Err(err) if !result.is_interrupted() => eprintln!("pdl: {}: {}", result.url, err),
+    // 与 sha256sum 等工具的输出格式一致
+    _ => {
+      if let Some(digest) = &result.digest {
+        println!("{}  {}", digest, result.target.display());
+      }
+    }
+  }
+}
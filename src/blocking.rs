@@ -0,0 +1,102 @@
+//! Blocking variants of the download methods, for callers without an async runtime.
+//!
+//! Each call runs the download on a new single-threaded tokio runtime, so these methods
+//! must not be called from within an async context.
+
+use std::path::{Path, PathBuf};
+
+use reqwest::IntoUrl;
+
+use crate::{BatchSummary, DownloadItem, DownloadResult, ProgressDownloadError, RobustDownloader};
+
+impl RobustDownloader {
+  /// Blocking version of [`download`](Self::download).
+  ///
+  /// # Panics
+  ///
+  /// Panics when called from within an async runtime.
+  ///
+  /// # Example
+  ///
+  /// ```rust,no_run
+  /// use robust_downloader::{DownloadItem, RobustDownloader};
+  ///
+  /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+  ///   let downloader = RobustDownloader::builder().build();
+  ///   downloader.download_blocking(vec![
+  ///     DownloadItem::builder()
+  ///       .url("https://example.com/file.txt")
+  ///       .target("local/file.txt")
+  ///       .build(),
+  ///   ])?;
+  ///   Ok(())
+  /// }
+  /// ```
+  pub fn download_blocking<I, U, P>(
+    &self,
+    downloads: I,
+  ) -> Result<BatchSummary, ProgressDownloadError>
+  where
+    I: IntoIterator,
+    I::Item: Into<DownloadItem<U, P>>,
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    block_on(self.download(downloads))?
+  }
+
+  /// Blocking version of [`download_all`](Self::download_all).
+  ///
+  /// # Panics
+  ///
+  /// Panics when called from within an async runtime.
+  pub fn download_all_blocking<I, U, P>(
+    &self,
+    downloads: I,
+  ) -> Result<Vec<DownloadResult>, ProgressDownloadError>
+  where
+    I: IntoIterator,
+    I::Item: Into<DownloadItem<U, P>>,
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    block_on(self.download_all(downloads))?
+  }
+
+  /// Blocking version of [`download_to_dir`](Self::download_to_dir).
+  ///
+  /// # Panics
+  ///
+  /// Panics when called from within an async runtime.
+  pub fn download_to_dir_blocking<U, D>(
+    &self,
+    urls: impl IntoIterator<Item = U>,
+    dir: D,
+  ) -> Result<Vec<PathBuf>, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    D: AsRef<Path>,
+  {
+    block_on(self.download_to_dir(urls, dir))?
+  }
+
+  /// Blocking version of [`download_to_vec`](Self::download_to_vec).
+  ///
+  /// # Panics
+  ///
+  /// Panics when called from within an async runtime.
+  pub fn download_to_vec_blocking<U>(&self, url: U) -> Result<Vec<u8>, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+  {
+    block_on(self.download_to_vec(url))?
+  }
+}
+
+/// Runs `future` to completion on a new current-thread runtime.
+fn block_on<F: Future>(future: F) -> Result<F::Output, ProgressDownloadError> {
+  let runtime = tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()?;
+  Ok(runtime.block_on(future))
+}
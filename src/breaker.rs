@@ -0,0 +1,76 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::poison::origin;
+
+/// Counts consecutive failed requests to each origin of a batch and trips once an
+/// origin reached the threshold, so the remaining downloads from it fail fast or move
+/// on to their mirrors instead of retrying against a server that is down.
+///
+/// A tripped origin stays tripped for the rest of the batch.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+  threshold: usize,
+  failures: Mutex<HashMap<String, usize>>,
+}
+
+impl CircuitBreaker {
+  pub fn new(threshold: usize) -> Self {
+    Self {
+      threshold: threshold.max(1),
+      failures: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Records a failed request to the origin of `url`.
+  pub fn record_failure(&self, url: &str) {
+    *self
+      .failures
+      .lock()
+      .unwrap()
+      .entry(origin(url))
+      .or_default() += 1;
+  }
+
+  /// Records a successful request to the origin of `url`, resetting its count unless it
+  /// has already tripped.
+  pub fn record_success(&self, url: &str) {
+    let mut failures = self.failures.lock().unwrap();
+    if let Some(count) = failures.get_mut(&origin(url)) {
+      // 已熔断的来源不再恢复，其他下载已经放弃了它
+      if *count < self.threshold {
+        *count = 0;
+      }
+    }
+  }
+
+  /// Whether requests to the origin of `url` failed too often in a row.
+  pub fn is_open(&self, url: &str) -> bool {
+    self
+      .failures
+      .lock()
+      .unwrap()
+      .get(&origin(url))
+      .is_some_and(|count| *count >= self.threshold)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_circuit_breaker() {
+    let breaker = CircuitBreaker::new(2);
+    breaker.record_failure("https://down.example.com/a.zip");
+    breaker.record_success("https://down.example.com/b.zip");
+    breaker.record_failure("https://down.example.com/a.zip");
+    assert!(!breaker.is_open("https://down.example.com/a.zip"));
+
+    breaker.record_failure("https://down.example.com:443/c.zip");
+    assert!(breaker.is_open("https://down.example.com/a.zip"));
+    assert!(!breaker.is_open("https://up.example.com/a.zip"));
+
+    breaker.record_success("https://down.example.com/a.zip");
+    assert!(breaker.is_open("https://down.example.com/a.zip"));
+  }
+}
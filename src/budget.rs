@@ -0,0 +1,132 @@
+use tokio::sync::Semaphore;
+
+/// Limits the data received but not yet written to disk, summed over every download of
+/// a batch.
+///
+/// Each download reserves room for a chunk before buffering it. A download whose buffer
+/// cannot grow writes it out and waits, holding none of the budget, until the others have
+/// released enough for the chunk.
+#[derive(Debug)]
+pub struct MemoryBudget {
+  semaphore: Semaphore,
+  limit: usize,
+}
+
+impl MemoryBudget {
+  pub fn new(limit: usize) -> Self {
+    // acquire_many 最多一次获取 u32::MAX 个许可
+    let limit = limit.clamp(1, u32::MAX as usize);
+    Self {
+      semaphore: Semaphore::new(limit),
+      limit,
+    }
+  }
+}
+
+/// The part of a [`MemoryBudget`] held by one download, released when dropped.
+#[derive(Debug)]
+pub struct Reservation<'a> {
+  budget: Option<&'a MemoryBudget>,
+  bytes: usize,
+}
+
+impl<'a> Reservation<'a> {
+  /// Creates an empty reservation, which never waits without a `budget`.
+  pub fn new(budget: Option<&'a MemoryBudget>) -> Self {
+    Self { budget, bytes: 0 }
+  }
+
+  /// Resizes the reservation to `bytes` if the budget allows it right away. Returns
+  /// `false`, keeping the current size, if that would exceed the budget.
+  pub fn try_resize(&mut self, bytes: usize) -> bool {
+    let Some(budget) = self.budget else {
+      return true;
+    };
+    // 超过整个预算的数据只占用全部预算
+    let bytes = bytes.min(budget.limit);
+    if bytes <= self.bytes {
+      budget.semaphore.add_permits(self.bytes - bytes);
+      self.bytes = bytes;
+      return true;
+    }
+
+    match budget
+      .semaphore
+      .try_acquire_many((bytes - self.bytes) as u32)
+    {
+      Ok(permit) => {
+        permit.forget();
+        self.bytes = bytes;
+        true
+      }
+      Err(_) => false,
+    }
+  }
+
+  /// Resizes the reservation to `bytes`, waiting until other downloads have released
+  /// enough of the budget. The current reservation is released before waiting, so two
+  /// waiting downloads never hold the budget the other one needs.
+  pub async fn resize(&mut self, bytes: usize) {
+    if self.try_resize(bytes) {
+      return;
+    }
+    let Some(budget) = self.budget else {
+      return;
+    };
+    let bytes = bytes.min(budget.limit);
+    budget.semaphore.add_permits(self.bytes);
+    self.bytes = 0;
+    // 信号量不会被关闭
+    if let Ok(permit) = budget.semaphore.acquire_many(bytes as u32).await {
+      permit.forget();
+      self.bytes = bytes;
+    }
+  }
+}
+
+impl Drop for Reservation<'_> {
+  fn drop(&mut self) {
+    if let Some(budget) = self.budget {
+      budget.semaphore.add_permits(self.bytes);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_memory_budget() {
+    let budget = MemoryBudget::new(100);
+    let mut first = Reservation::new(Some(&budget));
+    let mut second = Reservation::new(Some(&budget));
+
+    assert!(first.try_resize(60));
+    assert!(!second.try_resize(50));
+    assert!(second.try_resize(40));
+
+    assert!(first.try_resize(10));
+    assert!(second.try_resize(90));
+    drop(second);
+    // 超过预算的请求占用全部预算
+    first.resize(1000).await;
+    assert_eq!(first.bytes, 100);
+
+    // 等待前释放已有的预算
+    let mut second = Reservation::new(Some(&budget));
+    first.try_resize(70);
+    let waiting = async {
+      second.resize(50).await;
+      second.bytes
+    };
+    let release = async {
+      tokio::task::yield_now().await;
+      first.try_resize(50);
+    };
+    let (bytes, ()) = tokio::join!(waiting, release);
+    assert_eq!(bytes, 50);
+
+    assert!(Reservation::new(None).try_resize(usize::MAX));
+  }
+}
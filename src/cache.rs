@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::{
+  hasher::{HashAlgorithm, Hasher},
+  persist,
+  state::ResumeState,
+};
+
+/// A directory of downloaded files shared between runs and processes.
+///
+/// Files are stored under their digest as `<algorithm>/<hex digest>`, and under their
+/// URL as `url/<hash of the URL>` next to the ETag and Last-Modified date they were
+/// downloaded with, in a `.state` file.
+#[derive(Debug, Clone)]
+pub(crate) struct DownloadCache {
+  dir: PathBuf,
+}
+
+impl DownloadCache {
+  pub fn new(dir: PathBuf) -> Self {
+    Self { dir }
+  }
+
+  fn digest_path(&self, algorithm: HashAlgorithm, digest: &str) -> PathBuf {
+    let mut name = digest.to_string();
+    name.make_ascii_lowercase();
+    self.dir.join(algorithm.name()).join(name)
+  }
+
+  fn url_path(&self, url: &str) -> PathBuf {
+    self.dir.join("url").join(format!("{:016x}", fnv1a(url)))
+  }
+
+  /// The cached file with `digest`, if there is one. The file is hashed again, and
+  /// removed from the cache if it no longer has the digest.
+  pub async fn find_digest(&self, algorithm: HashAlgorithm, digest: &str) -> Option<PathBuf> {
+    let path = self.digest_path(algorithm, digest);
+    let metadata = tokio::fs::metadata(&path).await.ok()?;
+    if !metadata.is_file() {
+      return None;
+    }
+
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update_from_file(&path, metadata.len()).await.ok()?;
+    if !hasher.finalize().eq_ignore_ascii_case(digest) {
+      warn!(path = %path.display(), "cached file was modified, removing it");
+      let _ = tokio::fs::remove_file(&path).await;
+      return None;
+    }
+    Some(path)
+  }
+
+  /// The cached file downloaded from `url`, with the state of the remote file at the
+  /// time, if there is one.
+  pub async fn find_url(&self, url: &str) -> Option<(PathBuf, ResumeState)> {
+    let path = self.url_path(url);
+    // 不同 URL 的哈希值可能相同，以记录的 URL 为准
+    let state = ResumeState::load(&path)
+      .await
+      .filter(|state| state.url == url)?;
+    tokio::fs::metadata(&path)
+      .await
+      .is_ok_and(|metadata| metadata.is_file())
+      .then_some((path, state))
+  }
+
+  /// Adds the downloaded `file` to the cache, under its `digest` if known and under its
+  /// URL if the state of the remote file allows checking whether it changed.
+  pub async fn insert(
+    &self,
+    file: &Path,
+    digest: Option<(HashAlgorithm, &str)>,
+    remote: Option<&ResumeState>,
+  ) -> std::io::Result<()> {
+    if let Some((algorithm, digest)) = digest {
+      let path = self.digest_path(algorithm, digest);
+      if !tokio::fs::try_exists(&path).await? {
+        self.add(file, &path).await?;
+      }
+    }
+
+    if let Some(remote) = remote.filter(|remote| remote.if_range().is_some()) {
+      let path = self.url_path(&remote.url);
+      self.add(file, &path).await?;
+      remote.save(&path).await?;
+    }
+    Ok(())
+  }
+
+  async fn add(&self, file: &Path, path: &Path) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
+    // 硬链接会让目标文件的修改同时改变缓存中的文件
+    persist::place(file, path, false).await
+  }
+}
+
+/// The 64-bit FNV-1a hash of `value`, stable across platforms and releases.
+pub(crate) fn fnv1a(value: &str) -> u64 {
+  value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+    (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// `data` 的 SHA-256 摘要
+  const DATA: &str = "3a6eb0790f39ac87c94f3856b2dd2c5d110e6811602261a9a923d3bb23adc8b7";
+
+  #[tokio::test]
+  async fn test_download_cache() {
+    let dir = std::env::temp_dir().join(format!("robust_downloader_cache_{}", std::process::id()));
+    let cache = DownloadCache::new(dir.join("cache"));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let file = dir.join("a.bin");
+    tokio::fs::write(&file, b"data").await.unwrap();
+
+    let url = "https://example.com/a.bin";
+    let remote = ResumeState {
+      url: url.to_string(),
+      etag: Some("\"1\"".to_string()),
+      ..Default::default()
+    };
+    cache
+      .insert(
+        &file,
+        Some((
+          HashAlgorithm::SHA256,
+          "3A6EB0790F39AC87C94F3856B2DD2C5D110E6811602261A9A923D3BB23ADC8B7",
+        )),
+        Some(&remote),
+      )
+      .await
+      .unwrap();
+
+    let cached = cache.find_digest(HashAlgorithm::SHA256, DATA).await;
+    assert_eq!(tokio::fs::read(cached.unwrap()).await.unwrap(), b"data");
+    // 缓存的文件不是目标文件的硬链接，修改目标文件不影响缓存
+    tokio::fs::write(&file, b"edited").await.unwrap();
+    assert!(
+      cache
+        .find_digest(HashAlgorithm::SHA256, DATA)
+        .await
+        .is_some()
+    );
+    // 被修改的缓存文件不再使用
+    let cached = cache.digest_path(HashAlgorithm::SHA256, DATA);
+    tokio::fs::write(&cached, b"edited").await.unwrap();
+    assert!(
+      cache
+        .find_digest(HashAlgorithm::SHA256, DATA)
+        .await
+        .is_none()
+    );
+    assert!(!tokio::fs::try_exists(&cached).await.unwrap());
+    assert!(
+      cache
+        .find_digest(HashAlgorithm::SHA256, "ef")
+        .await
+        .is_none()
+    );
+
+    let (cached, state) = cache.find_url(url).await.unwrap();
+    assert_eq!(tokio::fs::read(cached).await.unwrap(), b"data");
+    assert_eq!(state, remote);
+    assert!(cache.find_url("https://example.com/b.bin").await.is_none());
+
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+}
@@ -0,0 +1,250 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use tokio::sync::OnceCell;
+use typed_builder::TypedBuilder;
+
+use crate::{err::ProgressDownloadError, hasher::HashAlgorithm, item::Integrity};
+
+/// A checksum file listing the digests of several files, like the `SHA256SUMS` published
+/// next to the artifacts of many releases, see the `checksum_file` of a
+/// [`DownloadItem`](crate::DownloadItem).
+///
+/// Lines are read in the `<hex digest>  <file name>` format written by `sha256sum` and
+/// similar tools, or in the BSD format `SHA256 (<file name>) = <hex digest>`. The file is
+/// downloaded once per batch, however many items refer to it.
+///
+/// # Example
+///
+/// ```rust
+/// use robust_downloader::{ChecksumFile, DownloadItem};
+///
+/// let item = DownloadItem::builder()
+///   .url("https://releases.example.com/v1.2.0/tool-linux-x86_64.tar.gz")
+///   .target("tool.tar.gz")
+///   .checksum_file(
+///     ChecksumFile::builder()
+///       .url("https://releases.example.com/v1.2.0/SHA256SUMS")
+///       .build(),
+///   )
+///   .build();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, TypedBuilder)]
+pub struct ChecksumFile {
+  /// URL of the checksum file.
+  #[builder(setter(into))]
+  pub url: String,
+
+  /// Algorithm of the digests without a BSD style algorithm name. Detected from the name
+  /// of the checksum file by default, e.g. `SHA512SUMS` or `B2SUMS`, or else from the
+  /// digest length.
+  #[builder(default = None, setter(strip_option))]
+  pub algorithm: Option<HashAlgorithm>,
+
+  /// Name of the entry of the file. Defaults to the last segment of the download URL,
+  /// then the file name of the target.
+  #[builder(default = None, setter(into, strip_option))]
+  pub file_name: Option<String>,
+}
+
+impl ChecksumFile {
+  /// The integrity listed in `content` for the first of `names` with an entry. Entries
+  /// in subdirectories, like `./dist/tool.tar.gz`, match by their file name.
+  pub(crate) fn find(&self, content: &str, names: &[&str]) -> Option<Integrity> {
+    let entries = content.lines().filter_map(parse_line).collect::<Vec<_>>();
+
+    names.iter().find_map(|name| {
+      entries
+        .iter()
+        .filter(|entry| {
+          let path = entry.name.trim_start_matches("./");
+          path == *name || path.rsplit('/').next() == Some(name)
+        })
+        .find_map(|entry| {
+          let algorithm = match entry.algorithm {
+            Some(algorithm) => algorithm.parse().ok()?,
+            None => self
+              .algorithm
+              .or_else(|| self.guess_algorithm(entry.digest))?,
+          };
+          // 明确指定算法时忽略其他算法的条目
+          if self.algorithm.is_some_and(|expected| expected != algorithm) {
+            return None;
+          }
+          Some(Integrity::new(algorithm, entry.digest.to_string()))
+        })
+    })
+  }
+
+  /// The algorithm named in the name of the checksum file, or else the common one with
+  /// digests of this length.
+  fn guess_algorithm(&self, digest: &str) -> Option<HashAlgorithm> {
+    let file_name = self.url.rsplit('/').next().unwrap_or_default();
+    let named = HashAlgorithm::ALL
+      .iter()
+      .copied()
+      .filter(|algorithm| contains_ignore_case(file_name, algorithm.name()))
+      .max_by_key(|algorithm| algorithm.name().len());
+    if named.is_some() {
+      return named;
+    }
+    // `b2sum` 默认使用 BLAKE2b，其他 BLAKE2 文件按摘要长度区分
+    if contains_ignore_case(file_name, "b2sum") || contains_ignore_case(file_name, "blake2") {
+      let name = match digest.len() {
+        64 => "blake2s",
+        128 => "blake2b",
+        _ => return None,
+      };
+      return name.parse().ok();
+    }
+
+    let name = match digest.len() {
+      32 => "md5",
+      40 => "sha1",
+      64 => "sha256",
+      96 => "sha384",
+      128 => "sha512",
+      _ => return None,
+    };
+    name.parse().ok()
+  }
+}
+
+/// One line of a checksum file.
+struct Entry<'a> {
+  /// 仅 BSD 格式的行带有算法名称
+  algorithm: Option<&'a str>,
+  digest: &'a str,
+  name: &'a str,
+}
+
+fn parse_line(line: &str) -> Option<Entry<'_>> {
+  let line = line.trim_end();
+  if line.is_empty() || line.starts_with('#') {
+    return None;
+  }
+
+  // BSD 格式：SHA256 (name) = digest
+  if let Some((head, digest)) = line.rsplit_once(") = ") {
+    if let Some((algorithm, name)) = head.split_once(" (") {
+      return Some(Entry {
+        algorithm: Some(algorithm),
+        digest: digest.trim(),
+        name,
+      });
+    }
+  }
+
+  // GNU 格式：digest  name，二进制模式的文件名前是 `*`
+  let (digest, rest) = line.trim_start().split_once(' ')?;
+  let name = rest.strip_prefix([' ', '*']).unwrap_or(rest);
+  if digest.is_empty() || !digest.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+    return None;
+  }
+  Some(Entry {
+    algorithm: None,
+    digest,
+    name,
+  })
+}
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+  haystack
+    .as_bytes()
+    .windows(needle.len())
+    .any(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// The content of the checksum files of a batch, each downloaded once for all items
+/// referring to it.
+#[derive(Debug, Default)]
+pub(crate) struct ChecksumFiles {
+  files: Mutex<HashMap<String, Arc<OnceCell<String>>>>,
+}
+
+impl ChecksumFiles {
+  /// The content of the checksum file at `url`, downloaded with `fetch` unless an earlier
+  /// item already did. A failed download is tried again by the next item.
+  pub async fn get_or_fetch<F, Fut>(
+    &self,
+    url: &str,
+    fetch: F,
+  ) -> Result<String, ProgressDownloadError>
+  where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<String, ProgressDownloadError>>,
+  {
+    let file = self
+      .files
+      .lock()
+      .unwrap()
+      .entry(url.to_string())
+      .or_default()
+      .clone();
+    file.get_or_try_init(fetch).await.cloned()
+  }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+  use super::*;
+
+  const EMPTY: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+  #[test]
+  fn test_find() {
+    let content = format!(
+      "# release v1.2.0\n{EMPTY}  tool-linux.tar.gz\n{EMPTY} *./dist/tool-windows.zip\n\
+       SHA256 (tool-macos.tar.gz) = {EMPTY}\n"
+    );
+    let file = ChecksumFile::builder()
+      .url("https://example.com/v1.2.0/SHA256SUMS")
+      .build();
+
+    for name in ["tool-linux.tar.gz", "tool-windows.zip", "tool-macos.tar.gz"] {
+      assert_eq!(
+        file.find(&content, &[name]),
+        Some(Integrity::SHA256(EMPTY.to_string()))
+      );
+    }
+    assert_eq!(
+      file.find(&content, &["missing.zip", "tool-linux.tar.gz"]),
+      Some(Integrity::SHA256(EMPTY.to_string()))
+    );
+    assert_eq!(file.find(&content, &["tool"]), None);
+
+    let file = ChecksumFile::builder()
+      .url("https://example.com/checksums.txt")
+      .algorithm(HashAlgorithm::SHA512)
+      .build();
+    assert_eq!(
+      file.find(&content, &["tool-linux.tar.gz"]),
+      Some(Integrity::SHA512(EMPTY.to_string()))
+    );
+    assert_eq!(file.find(&content, &["tool-macos.tar.gz"]), None);
+  }
+
+  #[test]
+  #[cfg(feature = "blake2")]
+  fn test_guess_blake2() {
+    let digest = "ab".repeat(64);
+    let content = format!("{digest}  tool.tar.gz\n");
+    let file = ChecksumFile::builder()
+      .url("https://example.com/B2SUMS")
+      .build();
+    assert_eq!(
+      file.find(&content, &["tool.tar.gz"]),
+      Some(Integrity::Blake2b(digest.clone()))
+    );
+
+    let file = ChecksumFile::builder()
+      .url("https://example.com/SUMS")
+      .build();
+    assert_eq!(
+      file.find(&content, &["tool.tar.gz"]),
+      Some(Integrity::SHA512(digest))
+    );
+  }
+}
@@ -0,0 +1,59 @@
+use reqwest::Url;
+#[cfg(not(feature = "cookies"))]
+use tracing::warn;
+
+/// The cookies of a downloader, shared by the clients of all its batches so cookies set
+/// by servers are sent again in later batches.
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+  /// 是否预置了 cookie，预置的 cookie 会启用 cookie 存储
+  seeded: bool,
+  #[cfg(feature = "cookies")]
+  jar: std::sync::Arc<reqwest::cookie::Jar>,
+}
+
+impl CookieJar {
+  /// Creates a jar holding `cookies`, pairs of the URL a cookie belongs to and its
+  /// `Set-Cookie` value.
+  pub(crate) fn new(cookies: Vec<(Url, String)>) -> Self {
+    #[cfg(feature = "cookies")]
+    {
+      let jar = reqwest::cookie::Jar::default();
+      for (url, cookie) in &cookies {
+        jar.add_cookie_str(cookie, url);
+      }
+      Self {
+        seeded: !cookies.is_empty(),
+        jar: std::sync::Arc::new(jar),
+      }
+    }
+
+    #[cfg(not(feature = "cookies"))]
+    Self {
+      seeded: !cookies.is_empty(),
+    }
+  }
+
+  /// Makes `builder` send and store the cookies of this jar if the cookie store is
+  /// `enabled` or cookies were added up front.
+  pub(crate) fn apply(
+    &self,
+    builder: reqwest::ClientBuilder,
+    enabled: bool,
+  ) -> reqwest::ClientBuilder {
+    if !enabled && !self.seeded {
+      return builder;
+    }
+
+    #[cfg(feature = "cookies")]
+    {
+      builder.cookie_provider(self.jar.clone())
+    }
+
+    #[cfg(not(feature = "cookies"))]
+    {
+      warn!("cookies require the `cookies` feature and are ignored");
+      builder
+    }
+  }
+}
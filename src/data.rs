@@ -0,0 +1,87 @@
+use base64::{Engine, prelude::BASE64_STANDARD};
+use bytes::Bytes;
+use futures::StreamExt;
+
+use crate::{err::ProgressDownloadError, filename, source::SourceFile};
+
+/// The content of a `data:` URL, `data:[<media type>][;base64],<data>`. Data without the
+/// `;base64` suffix is percent-encoded.
+pub(crate) fn decode(url: &str) -> Result<Vec<u8>, ProgressDownloadError> {
+  let error = |message: &str| ProgressDownloadError::DataUrl {
+    message: message.to_string(),
+  };
+
+  let rest = url
+    .get(..5)
+    .filter(|scheme| scheme.eq_ignore_ascii_case("data:"))
+    .map(|_| &url[5..])
+    .ok_or_else(|| error("not a data URL"))?;
+  let (media_type, data) = rest
+    .split_once(',')
+    .ok_or_else(|| error("missing `,` before the data"))?;
+  // 片段不属于数据
+  let data = data.split_once('#').map_or(data, |(data, _)| data);
+  let decoded = filename::percent_decode_bytes(data);
+
+  let base64 = media_type
+    .rsplit_once(';')
+    .is_some_and(|(_, parameter)| parameter.trim().eq_ignore_ascii_case("base64"));
+  if !base64 {
+    return Ok(decoded);
+  }
+
+  // 长数据常按行折断，解码前去掉空白
+  let encoded = decoded
+    .into_iter()
+    .filter(|byte| !byte.is_ascii_whitespace())
+    .collect::<Vec<_>>();
+  BASE64_STANDARD
+    .decode(encoded)
+    .map_err(|err| error(&format!("invalid base64 data: {err}")))
+}
+
+/// Opens the content of a `data:` URL for download from `offset` on.
+pub(crate) async fn open(url: &str, offset: u64) -> Result<SourceFile, ProgressDownloadError> {
+  let content = Bytes::from(decode(url)?);
+  let size = content.len() as u64;
+  let resumed = offset > 0 && offset <= size;
+  let rest = match resumed {
+    true => content.slice(offset as usize..),
+    false => content,
+  };
+
+  Ok(SourceFile {
+    size: Some(size),
+    modified: None,
+    etag: None,
+    resumed,
+    stream: futures::stream::iter([Ok(rest)]).boxed(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_decode() {
+    assert_eq!(
+      decode("data:,Hello%2C%20World%21").unwrap(),
+      b"Hello, World!"
+    );
+    assert_eq!(
+      decode("data:text/plain;charset=utf-8,a=1%0Ab=2").unwrap(),
+      b"a=1\nb=2"
+    );
+    assert_eq!(
+      decode("DATA:application/json;base64,eyJhIjog%0A MX0=").unwrap(),
+      br#"{"a": 1}"#
+    );
+    assert_eq!(decode("data:;base64,").unwrap(), b"");
+    assert_eq!(decode("data:,a#fragment").unwrap(), b"a");
+
+    assert!(decode("data:text/plain").is_err());
+    assert!(decode("data:;base64,!!!").is_err());
+    assert!(decode("https://example.com/data:,a").is_err());
+  }
+}
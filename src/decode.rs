@@ -0,0 +1,247 @@
+use std::io;
+#[cfg(any(feature = "gzip", feature = "brotli"))]
+use std::io::Write;
+
+use bytes::Bytes;
+use reqwest::header::{CONTENT_ENCODING, HeaderMap};
+
+use crate::transform::ChunkTransform;
+
+/// Decompresses a response body sent with a `Content-Encoding`, one chunk at a time.
+///
+/// Only the encodings enabled with the `gzip`, `zstd` and `brotli` features are supported.
+pub(crate) enum Decoder {
+  #[cfg(feature = "gzip")]
+  Gzip(flate2::write::GzDecoder<Vec<u8>>),
+  #[cfg(feature = "zstd")]
+  Zstd(ZstdDecoder),
+  #[cfg(feature = "brotli")]
+  Brotli(Box<brotli::DecompressorWriter<Vec<u8>>>),
+}
+
+impl Decoder {
+  /// Creates a decoder for the encoding of a response, or `None` if the body is not
+  /// encoded or its encoding is not supported.
+  pub fn from_headers(headers: &HeaderMap) -> io::Result<Option<Self>> {
+    let Some(encoding) = content_encoding(headers) else {
+      return Ok(None);
+    };
+
+    #[cfg(feature = "gzip")]
+    if encoding.eq_ignore_ascii_case("gzip") || encoding.eq_ignore_ascii_case("x-gzip") {
+      return Ok(Some(Self::Gzip(flate2::write::GzDecoder::new(Vec::new()))));
+    }
+
+    #[cfg(feature = "zstd")]
+    if encoding.eq_ignore_ascii_case("zstd") {
+      return Ok(Some(Self::Zstd(ZstdDecoder::new()?)));
+    }
+
+    #[cfg(feature = "brotli")]
+    if encoding.eq_ignore_ascii_case("br") {
+      return Ok(Some(Self::Brotli(Box::new(
+        brotli::DecompressorWriter::new(Vec::new(), 4096),
+      ))));
+    }
+
+    tracing::debug!(
+      encoding,
+      "unsupported content encoding, saving the body as is"
+    );
+    Ok(None)
+  }
+
+  /// Decompresses the next chunk of the body, returning the decoded bytes available so far.
+  pub fn decode(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+    match *self {
+      #[cfg(feature = "gzip")]
+      Self::Gzip(ref mut decoder) => {
+        decoder.write_all(chunk)?;
+        Ok(Bytes::from(std::mem::take(decoder.get_mut())))
+      }
+      #[cfg(feature = "zstd")]
+      Self::Zstd(ref mut decoder) => decoder.decode(chunk),
+      #[cfg(feature = "brotli")]
+      Self::Brotli(ref mut decoder) => {
+        decoder.write_all(chunk)?;
+        Ok(Bytes::from(std::mem::take(decoder.get_mut())))
+      }
+    }
+  }
+
+  /// Ends the body, returning the remaining decoded bytes.
+  ///
+  /// Fails if the body ended in the middle of the compressed stream.
+  pub fn finish(self) -> io::Result<Bytes> {
+    match self {
+      #[cfg(feature = "gzip")]
+      Self::Gzip(decoder) => decoder.finish().map(Bytes::from),
+      #[cfg(feature = "zstd")]
+      Self::Zstd(decoder) => match decoder.in_frame {
+        true => Err(io::Error::new(
+          io::ErrorKind::UnexpectedEof,
+          "truncated zstd stream",
+        )),
+        false => Ok(Bytes::new()),
+      },
+      #[cfg(feature = "brotli")]
+      Self::Brotli(decoder) => decoder
+        .into_inner()
+        .map(Bytes::from)
+        .map_err(|_| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated brotli stream")),
+    }
+  }
+}
+
+/// Room for decoded data added to the output buffer before each decompression step.
+#[cfg(feature = "zstd")]
+const ZSTD_OUTPUT_LEN: usize = 128 * 1024;
+
+/// Decompresses a zstd body, keeping track of whether it ended in the middle of a frame,
+/// which the streaming decoder of the `zstd` crate does not report.
+#[cfg(feature = "zstd")]
+pub(crate) struct ZstdDecoder {
+  raw: zstd::stream::raw::Decoder<'static>,
+  /// 是否有已开始但未解码完的帧
+  in_frame: bool,
+}
+
+#[cfg(feature = "zstd")]
+impl ZstdDecoder {
+  fn new() -> io::Result<Self> {
+    Ok(Self {
+      raw: zstd::stream::raw::Decoder::new()?,
+      in_frame: false,
+    })
+  }
+
+  fn decode(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+    use zstd::stream::raw::{InBuffer, Operation, OutBuffer};
+
+    if chunk.is_empty() && !self.in_frame {
+      return Ok(Bytes::new());
+    }
+
+    let mut input = InBuffer::around(chunk);
+    let mut decoded = Vec::new();
+    loop {
+      let len = decoded.len();
+      decoded.reserve(ZSTD_OUTPUT_LEN);
+      let mut output = OutBuffer::around_pos(&mut decoded, len);
+      // 返回 0 表示一帧已完整解码并全部输出，之后的数据属于下一帧
+      let hint = self.raw.run(&mut input, &mut output)?;
+      let full = output.pos() == output.capacity();
+      self.in_frame = hint != 0;
+      // 输出缓冲区写满时可能还有未输出的数据
+      if input.pos() == chunk.len() && !full {
+        break;
+      }
+    }
+    Ok(Bytes::from(decoded))
+  }
+}
+
+/// A [`Decoder`] as the first step of a [`TransformChain`](crate::TransformChain).
+pub(crate) struct DecoderTransform(pub Option<Decoder>);
+
+impl ChunkTransform for DecoderTransform {
+  fn transform(&mut self, chunk: Bytes) -> io::Result<Bytes> {
+    match &mut self.0 {
+      Some(decoder) => decoder.decode(&chunk),
+      None => Ok(chunk),
+    }
+  }
+
+  fn finish(&mut self) -> io::Result<Bytes> {
+    match self.0.take() {
+      Some(decoder) => decoder.finish(),
+      None => Ok(Bytes::new()),
+    }
+  }
+}
+
+/// The `Content-Encoding` of a response, `None` when the body is sent as is.
+pub(crate) fn content_encoding(headers: &HeaderMap) -> Option<&str> {
+  headers
+    .get(CONTENT_ENCODING)
+    .and_then(|value| value.to_str().ok())
+    .map(str::trim)
+    .filter(|encoding| !encoding.is_empty() && !encoding.eq_ignore_ascii_case("identity"))
+}
+
+#[cfg(all(test, any(feature = "gzip", feature = "zstd", feature = "brotli")))]
+mod tests {
+  use reqwest::header::HeaderValue;
+
+  use super::*;
+
+  /// Decodes `encoded` sent with `encoding` a few bytes at a time.
+  fn decode_all(encoding: &'static str, encoded: &[u8]) -> io::Result<Vec<u8>> {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+    let mut decoder = Decoder::from_headers(&headers)?.unwrap();
+
+    let mut decoded = Vec::new();
+    for chunk in encoded.chunks(7) {
+      decoded.extend_from_slice(&decoder.decode(chunk)?);
+    }
+    decoded.extend_from_slice(&decoder.finish()?);
+    Ok(decoded)
+  }
+
+  #[cfg(feature = "zstd")]
+  #[test]
+  fn test_decode_zstd() {
+    let content = b"hello, compressed world ".repeat(10_000);
+    let encoded = zstd::encode_all(&content[..], 3).unwrap();
+    assert_eq!(decode_all("zstd", &encoded).unwrap(), content);
+
+    // 连续的多个帧
+    let twice = [encoded.clone(), encoded.clone()].concat();
+    assert_eq!(decode_all("zstd", &twice).unwrap(), content.repeat(2));
+
+    let truncated = decode_all("zstd", &encoded[..encoded.len() - 1]);
+    assert_eq!(truncated.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+  }
+
+  #[cfg(feature = "brotli")]
+  #[test]
+  fn test_decode_brotli() {
+    let content = b"hello, compressed world ".repeat(10_000);
+    let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+    encoder.write_all(&content).unwrap();
+    let encoded = encoder.into_inner();
+    assert_eq!(decode_all("br", &encoded).unwrap(), content);
+
+    let truncated = decode_all("br", &encoded[..encoded.len() / 2]);
+    assert_eq!(truncated.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+  }
+
+  #[cfg(feature = "gzip")]
+  #[test]
+  fn test_decode_gzip() {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(b"hello, compressed world").unwrap();
+    let encoded = encoder.finish().unwrap();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    let mut decoder = Decoder::from_headers(&headers).unwrap().unwrap();
+
+    let mut decoded = Vec::new();
+    for chunk in encoded.chunks(4) {
+      decoded.extend_from_slice(&decoder.decode(chunk).unwrap());
+    }
+    decoded.extend_from_slice(&decoder.finish().unwrap());
+    assert_eq!(decoded, b"hello, compressed world");
+
+    let mut decoder = Decoder::from_headers(&headers).unwrap().unwrap();
+    decoder.decode(&encoded[..encoded.len() / 2]).unwrap();
+    assert!(decoder.finish().is_err());
+    // 缺少结尾的校验和与长度
+    assert!(decode_all("gzip", &encoded[..encoded.len() - 4]).is_err());
+
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("identity"));
+    assert!(Decoder::from_headers(&headers).unwrap().is_none());
+  }
+}
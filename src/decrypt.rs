@@ -0,0 +1,231 @@
+use std::{fmt, io};
+
+use aes::{
+  Aes256,
+  cipher::{BlockEncrypt, KeyInit, KeyIvInit, StreamCipher, generic_array::GenericArray},
+};
+use bytes::{Bytes, BytesMut};
+use ghash::{GHash, universal_hash::UniversalHash};
+
+use crate::transform::ChunkTransform;
+
+/// Length of the authentication tag at the end of an encrypted body.
+const TAG_LEN: usize = 16;
+
+const BLOCK_LEN: usize = 16;
+
+/// Decrypts an AES-256-GCM encrypted body while it downloads, see [`ChunkTransform`].
+///
+/// The body is the ciphertext followed by its 16 byte authentication tag, as produced by
+/// most AES-GCM implementations, encrypted with a 96 bit nonce and no associated data.
+/// Plaintext is written as it arrives and the tag is checked once the body ends; a body
+/// that does not authenticate fails the download with an
+/// [`InvalidData`](io::ErrorKind::InvalidData) error without retrying, and the file is
+/// never moved to its target. Requires the `aes-gcm` feature.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use robust_downloader::{Aes256GcmDecryptor, DownloadInfo, DownloadItem, TransformChain};
+///
+/// let (key, nonce) = ([7; 32], [1; 12]);
+/// let item = DownloadItem::builder()
+///   .url("https://example.com/model.bin.enc")
+///   .target("model.bin")
+///   .transforms(Arc::new(move |_: &DownloadInfo| {
+///     TransformChain::new().then(Aes256GcmDecryptor::new(&key, &nonce))
+///   }))
+///   .build();
+/// ```
+pub struct Aes256GcmDecryptor {
+  cipher: ctr::Ctr32BE<Aes256>,
+  ghash: GHash,
+  /// 加密后的初始计数块，与 GHASH 结果异或得到认证标签
+  tag_mask: [u8; BLOCK_LEN],
+  /// 尚未确定是否属于认证标签的最后 16 个字节
+  tail: BytesMut,
+  /// 不足一个分组、尚未计入 GHASH 的密文
+  partial: Vec<u8>,
+  ciphertext_len: u64,
+}
+
+impl Aes256GcmDecryptor {
+  pub fn new(key: &[u8; 32], nonce: &[u8; 12]) -> Self {
+    let aes = Aes256::new(GenericArray::from_slice(key));
+
+    let mut hash_key = GenericArray::default();
+    aes.encrypt_block(&mut hash_key);
+
+    // 96 位 nonce 的初始计数块为 nonce || 1，数据从计数 2 开始加密
+    let mut counter = [0; BLOCK_LEN];
+    counter[..12].copy_from_slice(nonce);
+    counter[15] = 1;
+    let mut tag_mask = GenericArray::from(counter);
+    aes.encrypt_block(&mut tag_mask);
+    counter[15] = 2;
+
+    Self {
+      cipher: ctr::Ctr32BE::<Aes256>::new(GenericArray::from_slice(key), &counter.into()),
+      ghash: GHash::new(&hash_key),
+      tag_mask: tag_mask.into(),
+      tail: BytesMut::new(),
+      partial: Vec::with_capacity(BLOCK_LEN),
+      ciphertext_len: 0,
+    }
+  }
+
+  /// Adds `ciphertext` to the authentication hash, in whole blocks.
+  fn hash(&mut self, mut ciphertext: &[u8]) {
+    if !self.partial.is_empty() {
+      let needed = (BLOCK_LEN - self.partial.len()).min(ciphertext.len());
+      self.partial.extend_from_slice(&ciphertext[..needed]);
+      ciphertext = &ciphertext[needed..];
+      if self.partial.len() < BLOCK_LEN {
+        return;
+      }
+      self
+        .ghash
+        .update(&[GenericArray::clone_from_slice(&self.partial)]);
+      self.partial.clear();
+    }
+
+    let blocks = ciphertext.len() / BLOCK_LEN * BLOCK_LEN;
+    for block in ciphertext[..blocks].chunks_exact(BLOCK_LEN) {
+      self.ghash.update(&[GenericArray::clone_from_slice(block)]);
+    }
+    self.partial.extend_from_slice(&ciphertext[blocks..]);
+  }
+}
+
+impl fmt::Debug for Aes256GcmDecryptor {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // 不输出密钥相关的状态
+    f.debug_struct("Aes256GcmDecryptor")
+      .field("ciphertext_len", &self.ciphertext_len)
+      .finish_non_exhaustive()
+  }
+}
+
+impl ChunkTransform for Aes256GcmDecryptor {
+  fn transform(&mut self, chunk: Bytes) -> io::Result<Bytes> {
+    self.tail.extend_from_slice(&chunk);
+    if self.tail.len() <= TAG_LEN {
+      return Ok(Bytes::new());
+    }
+
+    // 最后 16 个字节可能是认证标签，留到数据结束时再处理
+    let mut ciphertext = self.tail.split_to(self.tail.len() - TAG_LEN);
+    self.hash(&ciphertext);
+    self.ciphertext_len += ciphertext.len() as u64;
+    // 32 位计数器用尽后无法继续解密，约 64 GiB
+    self
+      .cipher
+      .try_apply_keystream(&mut ciphertext)
+      .map_err(|_| {
+        io::Error::new(
+          io::ErrorKind::InvalidData,
+          "encrypted body is longer than AES-GCM allows",
+        )
+      })?;
+    Ok(ciphertext.freeze())
+  }
+
+  fn finish(&mut self) -> io::Result<Bytes> {
+    if self.tail.len() < TAG_LEN {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "encrypted body is shorter than its authentication tag",
+      ));
+    }
+
+    self.ghash.update_padded(&self.partial);
+    let mut lengths = [0; BLOCK_LEN];
+    // 没有附加数据，前 8 个字节为 0
+    lengths[8..].copy_from_slice(&(self.ciphertext_len * 8).to_be_bytes());
+    self.ghash.update(&[lengths.into()]);
+    let hash = self.ghash.clone().finalize();
+
+    // 逐字节比较全部标签，耗时与不一致的位置无关
+    let mismatch = hash
+      .iter()
+      .zip(self.tag_mask)
+      .zip(self.tail.iter())
+      .fold(0, |mismatch, ((hash, mask), tag)| {
+        mismatch | (hash ^ mask ^ tag)
+      });
+    if mismatch != 0 {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "encrypted body failed authentication",
+      ));
+    }
+    Ok(Bytes::new())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use aes::cipher::StreamCipherSeek;
+
+  use super::*;
+
+  fn hex(text: &str) -> Vec<u8> {
+    (0..text.len())
+      .step_by(2)
+      .map(|index| u8::from_str_radix(&text[index..index + 2], 16).unwrap())
+      .collect()
+  }
+
+  /// 使用 chunk_size 大小的数据块解密 body
+  fn decrypt(body: &[u8], chunk_size: usize) -> io::Result<Vec<u8>> {
+    let key: [u8; 32] = hex("feffe9928665731c6d6a8f9467308308feffe9928665731c6d6a8f9467308308")
+      .try_into()
+      .unwrap();
+    let nonce: [u8; 12] = hex("cafebabefacedbaddecaf888").try_into().unwrap();
+    let mut decryptor = Aes256GcmDecryptor::new(&key, &nonce);
+
+    let mut plaintext = Vec::new();
+    for chunk in body.chunks(chunk_size) {
+      plaintext.extend_from_slice(&decryptor.transform(Bytes::copy_from_slice(chunk))?);
+    }
+    plaintext.extend_from_slice(&decryptor.finish()?);
+    Ok(plaintext)
+  }
+
+  #[test]
+  fn test_decrypt() {
+    // GCM 规范的测试用例 15
+    let body = hex(concat!(
+      "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa",
+      "8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662898015ad",
+      "b094dac5d93471bdec1a502270e3cc6c",
+    ));
+    let plaintext = hex(concat!(
+      "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72",
+      "1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b391aafd255",
+    ));
+    for chunk_size in [1, 5, 16, 17, body.len()] {
+      assert_eq!(decrypt(&body, chunk_size).unwrap(), plaintext);
+    }
+
+    let mut tampered = body.clone();
+    tampered[3] ^= 1;
+    assert_eq!(
+      decrypt(&tampered, 7).unwrap_err().kind(),
+      io::ErrorKind::InvalidData
+    );
+    assert!(decrypt(&body[..10], 4).is_err());
+
+    // 计数器用尽时返回错误而不是 panic
+    let mut decryptor = Aes256GcmDecryptor::new(&[0; 32], &[0; 12]);
+    decryptor
+      .cipher
+      .seek((u32::MAX as u64 - 3) * BLOCK_LEN as u64);
+    let err = decryptor
+      .transform(Bytes::from(vec![0; 8 * BLOCK_LEN]))
+      .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+  }
+}
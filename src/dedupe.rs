@@ -0,0 +1,141 @@
+use std::{collections::HashMap, path::Path};
+
+use reqwest::IntoUrl;
+
+use crate::{item::DownloadItem, stream::StreamTarget};
+
+/// What to do with items of a batch that download the same URL as an earlier item.
+///
+/// Items are duplicates if their URL and integrity are the same and neither extracts
+/// the file, streams it to stdout or a pipe nor has a [`DownloadHandle`](crate::DownloadHandle). The file is downloaded
+/// once with the settings of the first item, then placed at the other targets. If that
+/// download fails, the duplicates are downloaded on their own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+  /// Download every item, even if its URL appears more than once.
+  DownloadEach,
+  /// Copy the downloaded file to the targets of the duplicates, as a copy-on-write clone
+  /// on file systems supporting it.
+  #[default]
+  Copy,
+  /// Hard-link the targets of the duplicates to the downloaded file, so they share its
+  /// disk space. Falls back to copying where hard links are not supported, e.g. across
+  /// file systems.
+  HardLink,
+}
+
+/// An item downloading the same file as the earlier item at `original`.
+pub(crate) struct Duplicate<U, P> {
+  pub index: usize,
+  pub original: usize,
+  pub item: DownloadItem<U, P>,
+}
+
+/// The items of a batch, split into the items to download, with their index in the
+/// batch, and the duplicates of those items.
+pub(crate) struct Split<U, P> {
+  pub unique: Vec<(usize, DownloadItem<U, P>)>,
+  pub duplicates: Vec<Duplicate<U, P>>,
+}
+
+/// Finds the duplicates in `downloads`.
+pub(crate) fn split<U, P>(
+  downloads: Vec<DownloadItem<U, P>>,
+  policy: DuplicatePolicy,
+) -> Split<U, P>
+where
+  U: IntoUrl + Clone,
+  P: AsRef<Path>,
+{
+  let mut unique = Vec::with_capacity(downloads.len());
+  let mut duplicates = Vec::new();
+  // 每个 URL 第一次出现的下标
+  let mut first: HashMap<String, usize> = HashMap::new();
+
+  for (index, item) in downloads.into_iter().enumerate() {
+    if policy == DuplicatePolicy::DownloadEach || !deduplicable(&item) {
+      unique.push((index, item));
+      continue;
+    }
+
+    let url = item.url.as_str().to_string();
+    match first.get(&url) {
+      Some(&original) if same_file(&unique, original, &item) => duplicates.push(Duplicate {
+        index,
+        original,
+        item,
+      }),
+      Some(_) => unique.push((index, item)),
+      None => {
+        first.insert(url, index);
+        unique.push((index, item));
+      }
+    }
+  }
+
+  Split { unique, duplicates }
+}
+
+/// Whether the file of `item` can be shared with other items.
+fn deduplicable<U, P: AsRef<Path>>(item: &DownloadItem<U, P>) -> bool {
+  item.extract_to.is_none()
+    && item.handle.is_none()
+    && item.transforms.is_none()
+    && StreamTarget::of(item.target.as_ref()).is_none()
+}
+
+/// Whether `item` expects the same file, or part of it, as the item at index `original`,
+/// verified the same way.
+fn same_file<U, P>(
+  unique: &[(usize, DownloadItem<U, P>)],
+  original: usize,
+  item: &DownloadItem<U, P>,
+) -> bool {
+  unique
+    .iter()
+    .find(|(index, _)| *index == original)
+    .is_some_and(|(_, first)| {
+      first.part == item.part
+        && first.integrity == item.integrity
+        && first.checksum_file == item.checksum_file
+        && first.signature == item.signature
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_split() {
+    let items = vec![
+      DownloadItem::builder()
+        .url("https://example.com/a.zip")
+        .target("a.zip")
+        .build(),
+      DownloadItem::builder()
+        .url("https://example.com/b.zip")
+        .target("b.zip")
+        .build(),
+      DownloadItem::builder()
+        .url("https://example.com/a.zip")
+        .target("copy/a.zip")
+        .build(),
+      DownloadItem::builder()
+        .url("https://example.com/a.zip")
+        .target("extracted/a.zip")
+        .extract_to("extracted")
+        .build(),
+    ];
+
+    let Split { unique, duplicates } = split(items.clone(), DuplicatePolicy::Copy);
+    let unique: Vec<_> = unique.iter().map(|(index, _)| *index).collect();
+    assert_eq!(unique, [0, 1, 3]);
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!((duplicates[0].index, duplicates[0].original), (2, 0));
+
+    let Split { unique, duplicates } = split(items, DuplicatePolicy::DownloadEach);
+    assert_eq!(unique.len(), 4);
+    assert!(duplicates.is_empty());
+  }
+}
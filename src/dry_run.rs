@@ -0,0 +1,225 @@
+//! Checking the items of a batch without downloading them.
+
+use std::path::{Path, PathBuf};
+
+use futures::StreamExt;
+use reqwest::{
+  IntoUrl, Method, StatusCode,
+  header::{ACCEPT_RANGES, CONTENT_LENGTH, RANGE},
+};
+
+use crate::{
+  DownloadItem, ProgressDownloadError, RobustDownloader,
+  batch::Batch,
+  middleware,
+  remote::{self, Scheme},
+  source,
+  task::content_range_total,
+};
+
+/// What a dry run found out about one item, see [`RobustDownloader::dry_run`].
+#[derive(Debug)]
+pub struct DryRunResult {
+  /// The URL that was checked.
+  pub url: String,
+  /// The local path the file would be saved to.
+  pub target: PathBuf,
+  /// The URL the file would be downloaded from after following redirects.
+  pub final_url: Option<String>,
+  /// The HTTP status of the response, `None` for other protocols or if no response
+  /// was received.
+  pub status: Option<StatusCode>,
+  /// Size of the file, if the server reports it.
+  pub size: Option<u64>,
+  /// Whether the server supports range requests, so interrupted downloads can resume
+  /// and files can be downloaded in segments.
+  pub accepts_ranges: bool,
+  /// Whether the file is reachable, or the error that would fail its download.
+  pub result: Result<(), ProgressDownloadError>,
+}
+
+impl DryRunResult {
+  /// Returns `true` if the file can be downloaded.
+  pub fn is_reachable(&self) -> bool {
+    self.result.is_ok()
+  }
+
+  /// Returns `true` if the server rejected the credentials, or asked for some.
+  pub fn is_auth_failure(&self) -> bool {
+    matches!(
+      self.status,
+      Some(
+        StatusCode::UNAUTHORIZED
+          | StatusCode::FORBIDDEN
+          | StatusCode::PROXY_AUTHENTICATION_REQUIRED
+      )
+    )
+  }
+}
+
+impl RobustDownloader {
+  /// Checks every item without transferring any file, e.g. to validate a manifest before
+  /// a long batch.
+  ///
+  /// HTTP URLs are checked with a HEAD request, falling back to a GET request of the first
+  /// byte for servers rejecting HEAD. FTP, SFTP and S3 files are opened and closed before
+  /// any data is read. The item's headers, bearer token and timeout are used like for the
+  /// download itself, with up to `max_concurrent` checks running at a time.
+  ///
+  /// # Returns
+  ///
+  /// Returns one [`DryRunResult`] per item, in the same order as `downloads`. Fails only
+  /// if the HTTP client cannot be created.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use robust_downloader::{DownloadItem, RobustDownloader};
+  /// async fn example() -> Result<(), Box<dyn std::error::Error>> {
+  /// let downloader = RobustDownloader::builder().build();
+  /// let files = vec![
+  ///     DownloadItem::builder()
+  ///         .url("https://example.com/file1.txt")
+  ///         .target("local/file1.txt")
+  ///         .build(),
+  /// ];
+  /// for result in downloader.dry_run(&files).await? {
+  ///     match &result.result {
+  ///         Ok(()) => println!("{}: {:?} bytes", result.url, result.size),
+  ///         Err(err) => eprintln!("{} unreachable: {}", result.url, err),
+  ///     }
+  /// }
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub async fn dry_run<U, P>(
+    &self,
+    downloads: &[DownloadItem<U, P>],
+  ) -> Result<Vec<DryRunResult>, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    let batch = self.prepare_batch()?;
+
+    Ok(
+      futures::stream::iter(downloads)
+        .map(|item| self.check_item(&batch, item))
+        .buffered(self.max_concurrent.max(1))
+        .collect()
+        .await,
+    )
+  }
+
+  async fn check_item<U, P>(&self, batch: &Batch, item: &DownloadItem<U, P>) -> DryRunResult
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    let mut result = DryRunResult {
+      url: item.url.as_str().to_string(),
+      target: item.target.as_ref().to_path_buf(),
+      final_url: None,
+      status: None,
+      size: None,
+      accepts_ranges: false,
+      result: Ok(()),
+    };
+
+    let url = item.url.as_str();
+    let source = source::find(&self.sources, url);
+    if source.is_some() || Scheme::of(url) != Scheme::Http {
+      // 打开后立即关闭，不读取数据
+      let file = match source {
+        Some(source) => source.open(url, 0).await,
+        None => remote::open(url, 0, self.connect_timeout, &self.sftp, &self.s3).await,
+      };
+      match file {
+        Ok(file) => {
+          result.size = file.size;
+          result.accepts_ranges = true;
+        }
+        Err(err) => result.result = Err(err),
+      }
+      return result;
+    }
+
+    let _connection = match &batch.hosts {
+      Some(hosts) => match hosts.acquire(item.url.as_str()).await {
+        Ok(connection) => connection,
+        Err(err) => {
+          result.result = Err(err.into());
+          return result;
+        }
+      },
+      None => None,
+    };
+
+    let mut response = self.probe(batch, item, Method::HEAD).await;
+    // 部分服务端不支持 HEAD，或只为 GET 请求签名
+    if response
+      .as_ref()
+      .is_ok_and(|response| !response.status().is_success())
+    {
+      response = self.probe(batch, item, Method::GET).await;
+    }
+
+    let response = match response {
+      Ok(response) => response,
+      Err(err) => {
+        result.result = Err(err);
+        return result;
+      }
+    };
+
+    result.final_url = Some(response.url().to_string());
+    result.status = Some(response.status());
+    if let Err(err) = response.error_for_status_ref() {
+      result.result = Err(err.into());
+      return result;
+    }
+
+    let headers = response.headers();
+    let partial = response.status() == StatusCode::PARTIAL_CONTENT;
+    result.size = if partial {
+      content_range_total(headers)
+    } else {
+      headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+    };
+    result.accepts_ranges = partial
+      || headers
+        .get(ACCEPT_RANGES)
+        .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"bytes"));
+    result
+  }
+
+  /// Sends a HEAD request, or a GET request of the first byte, for `item`. The response
+  /// body is never read.
+  async fn probe<U, P>(
+    &self,
+    batch: &Batch,
+    item: &DownloadItem<U, P>,
+    method: Method,
+  ) -> Result<reqwest::Response, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    let timeout = item.timeout.unwrap_or(self.timeout);
+    let mut request = item
+      .apply_headers(batch.client.request(method.clone(), item.url.as_str()))
+      .timeout(timeout);
+    if method == Method::GET {
+      request = request.header(RANGE, "bytes=0-0");
+    }
+    if let Some(token) = item.bearer_token.as_ref().or(self.bearer_token.as_ref()) {
+      request = request.bearer_auth(token);
+    }
+
+    let request = middleware::apply(&self.request_middleware, request).await;
+    self.redirect_policy.send(&batch.client, request).await
+  }
+}
@@ -1,24 +1,190 @@
-use log::debug;
-use std::path::PathBuf;
+use std::{
+  fmt,
+  path::{Path, PathBuf},
+  time::Duration,
+};
 use thiserror::Error;
+use tracing::debug;
 
 #[derive(Debug, Error)]
 pub enum ProgressDownloadError {
-  #[error("IO error: {0}")]
-  Io(#[from] std::io::Error),
+  /// An I/O operation failed, on the file or directory at `path` if it is known.
+  #[error("IO error: {}{source}", path.as_ref().map(|path| format!("{}: ", path.display())).unwrap_or_default())]
+  Io {
+    source: std::io::Error,
+    path: Option<PathBuf>,
+  },
 
+  /// A request failed without a response, e.g. because the connection could not be
+  /// established or was lost while reading the body.
   #[error("Reqwest error: {0}")]
-  Reqwest(#[from] reqwest::Error),
+  Reqwest(#[source] reqwest::Error),
+
+  /// The server answered `url` with the error status `status`.
+  #[error("HTTP error: {status} for {url}")]
+  HttpStatus {
+    status: reqwest::StatusCode,
+    url: String,
+    source: reqwest::Error,
+  },
+
+  /// The server answered 429 or 503 and asked to wait `delay` before retrying with a
+  /// `Retry-After` header.
+  #[error("Reqwest error: {source}, retry after {}s", delay.as_secs())]
+  RetryAfter {
+    source: reqwest::Error,
+    delay: Duration,
+  },
+
+  /// The `phase` of an attempt did not complete within `timeout`.
+  /// Retried like other transient errors.
+  #[error("Timeout error: {phase} took longer than {}s", timeout.as_secs_f64())]
+  Timeout {
+    phase: TimeoutPhase,
+    timeout: Duration,
+  },
+
+  /// No data was received for `timeout`, see the `stall_timeout` option.
+  /// Retried like other transient errors.
+  #[error("Connection stalled: no data received for {}s", timeout.as_secs_f64())]
+  Stalled { timeout: Duration },
+
+  /// The average speed of a connection fell below the `min_speed` option.
+  /// Retried like other transient errors.
+  #[error("Transfer too slow: {speed} B/s, expected at least {min_speed} B/s")]
+  TooSlow { speed: u64, min_speed: u64 },
 
-  #[error("Timeout error: {0}")]
-  Timeout(#[from] tokio::time::error::Elapsed),
+  /// The download did not complete within the `deadline` option. Not retried, the data
+  /// received so far is kept to resume the download next time.
+  #[error("Download not completed within {}s", deadline.as_secs_f64())]
+  DeadlineExceeded { deadline: Duration },
+
+  /// Requests to `origin` failed too often in a row during this batch, see the
+  /// `circuit_breaker` option, and the item has no mirror left to switch to. Not retried.
+  #[error("Circuit breaker open: {origin} failed too often in a row")]
+  CircuitOpen { origin: String },
+
+  #[cfg(feature = "ftp")]
+  #[error("FTP error: {0}")]
+  Ftp(#[from] suppaftp::FtpError),
 
   #[error("Semaphore error: {0}")]
   Semaphore(#[from] tokio::sync::AcquireError),
 
+  /// The download was paused through its [`DownloadHandle`](crate::DownloadHandle).
+  /// Handled internally and never returned to callers.
+  #[error("Download paused")]
+  Paused,
+
+  /// The download was stopped or not started because a shutdown was requested through
+  /// the [`ShutdownHandle`](crate::ShutdownHandle). The received data is kept to resume
+  /// the download next time.
+  #[error("Download interrupted by shutdown")]
+  Interrupted,
+
+  /// The connection was closed before the whole response body was received.
+  /// Retried like other transient errors, resuming after the bytes already written.
+  #[error("Incomplete response: expected {expected} bytes, received {received}")]
+  Incomplete { expected: u64, received: u64 },
+
+  /// The file is larger than the `max_download_size` option. Not retried.
+  #[error("File too large: {size} bytes, the limit is {limit} bytes")]
+  TooLarge { size: u64, limit: u64 },
+
+  /// The size of the file differs from the item's `expected_size`. Not retried.
+  #[error("File size mismatch: expected {expected} bytes, got {actual}")]
+  SizeMismatch { expected: u64, actual: u64 },
+
+  /// A [`ResponseValidator`](crate::ResponseValidator) rejected the response. Not retried.
+  #[error("Invalid response: {url}: {message}")]
+  InvalidResponse { url: String, message: String },
+
+  /// The downloaded file does not start with the item's `expected_magic`, or is an HTML
+  /// page while `reject_html` is set. Not retried.
+  #[error("Unexpected content: {url}: {message}")]
+  UnexpectedContent { url: String, message: String },
+
+  /// The target file already exists and the [`OverwritePolicy`](crate::OverwritePolicy)
+  /// is `Error`.
+  #[error("Target file already exists: {}", path.display())]
+  TargetExists { path: PathBuf },
+
   #[error("Path error: {path}")]
   Path { path: String },
 
+  /// The [`TlsOptions`](crate::TlsOptions) cannot be used with the enabled TLS backend.
+  #[error("TLS error: {message}")]
+  Tls { message: String },
+
+  /// An option was set that needs a crate feature which is not enabled.
+  #[error("`{option}` requires the `{feature}` feature")]
+  MissingFeature {
+    option: &'static str,
+    feature: &'static str,
+  },
+
+  /// The downloader was configured with options that cannot work together, see
+  /// [`RobustDownloader::validate`](crate::RobustDownloader::validate).
+  #[error("Invalid configuration: {0}")]
+  Config(#[from] ConfigError),
+
+  /// An option was set that is not available on this platform.
+  #[error("`{option}` is not supported on this platform")]
+  UnsupportedPlatform { option: &'static str },
+
+  /// A redirect could not be followed according to the
+  /// [`RedirectPolicy`](crate::RedirectPolicy).
+  #[error("Redirect error: {url}: {message}")]
+  Redirect { url: String, message: String },
+
+  /// An SFTP server refused the login or the requested file.
+  #[error("SFTP error: {url}: {message}")]
+  Sftp { url: String, message: String },
+
+  /// An S3-compatible service refused the request for an object.
+  #[error("S3 error: {url}: {message}")]
+  S3 { url: String, message: String },
+
+  /// A torrent could not be added or downloaded.
+  #[error("Torrent error: {torrent}: {message}")]
+  Torrent { torrent: String, message: String },
+
+  /// The downloaded archive could not be unpacked.
+  #[error("Extract error: {archive}: {message}")]
+  Extract { archive: PathBuf, message: String },
+
+  /// A checksum could not be parsed, see [`Integrity`](crate::Integrity)'s `FromStr`.
+  #[error("Invalid checksum: {checksum}")]
+  Checksum { checksum: String },
+
+  /// The checksum file of an item, see [`ChecksumFile`](crate::ChecksumFile), has no
+  /// entry for the file.
+  #[error("No checksum for {file_name} in {checksum_file}")]
+  ChecksumNotFound {
+    checksum_file: String,
+    file_name: String,
+  },
+
+  /// The server ignored the `Range` header of a request for a [`RemotePart`](crate::RemotePart)
+  /// of a file, answered a range request for a part or a resumed download with a different
+  /// range or a compressed body, or the URL of the part is not an HTTP URL. Not retried.
+  #[error("Server does not support range requests: {url}")]
+  RangeNotSupported { url: String },
+
+  /// The remote archive of a [`RemotePart::ZipMember`](crate::RemotePart::ZipMember) is
+  /// not a valid ZIP archive or has no usable member at the path. Not retried.
+  #[error("Remote ZIP error: {url}: {message}")]
+  RemoteZip { url: String, message: String },
+
+  /// A `data:` URL is malformed, e.g. it has no `,` before the data or invalid base64
+  /// data. Not retried.
+  #[error("Invalid data URL: {message}")]
+  DataUrl { message: String },
+
+  /// A download manifest could not be read or contains an invalid entry.
+  #[error("Manifest error: {message}")]
+  Manifest { message: String },
+
   #[error(
     "Integrity hash mismatch - expected: {expect}, actual: {actual} , actual_file:{actual_file} , target_file: {target_file}"
   )]
@@ -28,6 +194,92 @@ pub enum ProgressDownloadError {
     actual_file: PathBuf,
     target_file: PathBuf,
   },
+
+  /// The detached signature of the downloaded file is missing, malformed or was not made
+  /// with the `signature_key`. The file is not moved to its target.
+  #[error("Invalid signature of {target}: {message}")]
+  SignatureInvalid { target: PathBuf, message: String },
+}
+
+/// The part of an attempt a [`ProgressDownloadError::Timeout`] happened in. Timeouts
+/// while the body is received are reported as [`ProgressDownloadError::Stalled`] and
+/// [`ProgressDownloadError::DeadlineExceeded`] instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutPhase {
+  /// Connecting and logging in to the server, see the `connect_timeout` option.
+  Connect,
+  /// Waiting for the response to a request, see the `timeout` option.
+  Response,
+}
+
+impl fmt::Display for TimeoutPhase {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Connect => "connecting",
+      Self::Response => "waiting for the response",
+    })
+  }
+}
+
+impl From<std::io::Error> for ProgressDownloadError {
+  fn from(source: std::io::Error) -> Self {
+    Self::Io { source, path: None }
+  }
+}
+
+/// Errors with a status come from a response, all others from the connection.
+impl From<reqwest::Error> for ProgressDownloadError {
+  fn from(source: reqwest::Error) -> Self {
+    match (source.status(), source.url()) {
+      (Some(status), Some(url)) => Self::HttpStatus {
+        status,
+        url: url.to_string(),
+        source,
+      },
+      _ => Self::Reqwest(source),
+    }
+  }
+}
+
+/// An option of a [`RobustDownloader`](crate::RobustDownloader) with a value downloads
+/// cannot work with, returned by its `validate` method.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ConfigError {
+  /// A limit on concurrent downloads or connections is zero, so no download would start.
+  #[error("`{option}` is 0, no download could start")]
+  ZeroConcurrency { option: &'static str },
+
+  /// A timeout is zero, so every attempt would fail at once.
+  #[error("`{option}` is 0s, every attempt would time out")]
+  ZeroTimeout { option: &'static str },
+
+  /// A size or speed limit is zero, so no data could be transferred.
+  #[error("`{option}` is 0, no data could be transferred")]
+  ZeroLimit { option: &'static str },
+
+  /// The `flush_threshold` is larger than the `memory_budget`, so the write buffer is
+  /// always written out before reaching it.
+  #[error(
+    "`flush_threshold` of {flush_threshold} bytes exceeds the `memory_budget` of {memory_budget} bytes"
+  )]
+  FlushThresholdExceedsBudget {
+    flush_threshold: usize,
+    memory_budget: usize,
+  },
+
+  /// The `flush_threshold` of the downloader or an item is larger than the
+  /// `write_buffer_size`, so the buffer is written out whenever it is full instead.
+  #[error(
+    "`flush_threshold` of {flush_threshold} bytes exceeds the `write_buffer_size` of {write_buffer_size} bytes"
+  )]
+  FlushThresholdExceedsBuffer {
+    flush_threshold: usize,
+    write_buffer_size: usize,
+  },
+
+  /// The `flush_threshold` of the downloader or an item is zero, so it is never reached.
+  #[error("`flush_threshold` is 0, it must be at least one byte")]
+  ZeroFlushThreshold,
 }
 
 impl ProgressDownloadError {
@@ -52,9 +304,96 @@ impl ProgressDownloadError {
     e.is_body() // 响应体错误
   }
 
-  pub fn into_backoff_err(self) -> backoff::Error<Self> {
-    match &self {
-      Self::Io(err) => match err.kind() {
+  /// Maps an I/O error on the file or directory at `path` to an [`Io`](Self::Io) error
+  /// naming it.
+  pub(crate) fn io_at(path: &Path) -> impl FnOnce(std::io::Error) -> Self + '_ {
+    move |source| Self::Io {
+      source,
+      path: Some(path.to_path_buf()),
+    }
+  }
+
+  /// Maps the expiry of `timeout` during `phase` to a [`Timeout`](Self::Timeout) error.
+  pub(crate) fn timed_out(
+    phase: TimeoutPhase,
+    timeout: Duration,
+  ) -> impl FnOnce(tokio::time::error::Elapsed) -> Self {
+    move |_| Self::Timeout { phase, timeout }
+  }
+
+  /// Whether the error means the host could not be reached or stopped responding.
+  pub(crate) fn is_connection_error(&self) -> bool {
+    match self {
+      Self::Reqwest(e) => e.is_connect() || e.is_timeout(),
+      Self::Timeout { .. } | Self::Stalled { .. } | Self::TooSlow { .. } => true,
+      #[cfg(feature = "ftp")]
+      Self::Ftp(suppaftp::FtpError::ConnectionError(_)) => true,
+      _ => false,
+    }
+  }
+
+  /// The HTTP status code of the response that caused the error, if any.
+  pub fn status(&self) -> Option<reqwest::StatusCode> {
+    match self {
+      Self::HttpStatus { status, .. } => Some(*status),
+      Self::RetryAfter { source, .. } => source.status(),
+      _ => None,
+    }
+  }
+
+  /// How long the server asked to wait before retrying, from its `Retry-After` header.
+  pub fn retry_after(&self) -> Option<Duration> {
+    match self {
+      Self::RetryAfter { delay, .. } => Some(*delay),
+      _ => None,
+    }
+  }
+
+  /// A short description of the error for a progress bar, e.g. `connection reset` or
+  /// `HTTP 503`, falling back to the full message.
+  pub fn reason(&self) -> String {
+    use std::io::ErrorKind;
+
+    let io_reason = |kind: ErrorKind| match kind {
+      ErrorKind::ConnectionReset => Some("connection reset"),
+      ErrorKind::ConnectionAborted => Some("connection aborted"),
+      ErrorKind::ConnectionRefused => Some("connection refused"),
+      ErrorKind::BrokenPipe => Some("broken pipe"),
+      ErrorKind::TimedOut => Some("timed out"),
+      ErrorKind::UnexpectedEof => Some("connection closed"),
+      _ => None,
+    };
+
+    let reason = match self {
+      Self::Io { source, .. } => io_reason(source.kind()),
+      Self::Reqwest(err)
+      | Self::HttpStatus { source: err, .. }
+      | Self::RetryAfter { source: err, .. } => match err.status() {
+        Some(status) => return format!("HTTP {}", status.as_u16()),
+        None if err.is_timeout() => Some("timed out"),
+        None if err.is_connect() => Some("connection failed"),
+        None if err.is_body() || err.is_decode() => Some("connection lost"),
+        None => None,
+      },
+      Self::Timeout { .. } => Some("timed out"),
+      Self::Stalled { .. } => Some("stalled"),
+      Self::TooSlow { .. } => Some("too slow"),
+      Self::Incomplete { .. } => Some("incomplete response"),
+      _ => None,
+    };
+    reason.map_or_else(|| self.to_string(), str::to_string)
+  }
+
+  /// The default classification of errors worth retrying, used by
+  /// [`DefaultRetryClassifier`](crate::DefaultRetryClassifier).
+  ///
+  /// Client errors (4xx) other than 408, 425, 429 and 449, integrity mismatches and
+  /// invalid paths are permanent. Server errors (5xx), timeouts, connection failures,
+  /// truncated responses and transient I/O errors are retried.
+  pub fn is_retryable(&self) -> bool {
+    match self {
+      Self::Io { source, .. } => matches!(
+        source.kind(),
         // 1. 资源暂时不可用
         std::io::ErrorKind::WouldBlock |     // 操作会阻塞
         std::io::ErrorKind::Interrupted |    // 操作被中断
@@ -66,39 +405,90 @@ impl ProgressDownloadError {
         std::io::ErrorKind::TimedOut |          // 超时
         // 3. 系统资源相关
         std::io::ErrorKind::OutOfMemory |    // 内存不足（可能是临时的）
-        std::io::ErrorKind::Other            // 其他未知错误（保守重试）
-              => {
-                debug!("transient error: {:?}", self);
-                return backoff::Error::transient(self);
-              },
-        // 其他都是永久性错误
-        _ => backoff::Error::permanent(self),
-      },
-      Self::Reqwest(error) => {
-        if self.is_retry_error(error) {
-          debug!("transient error: {:?}", self);
-          backoff::Error::transient(self)
-        } else {
-          debug!("permanent error: {:?}", self);
-          backoff::Error::permanent(self)
+        std::io::ErrorKind::Other // 其他未知错误（保守重试）
+      ),
+      Self::Reqwest(error)
+      | Self::HttpStatus { source: error, .. }
+      | Self::RetryAfter { source: error, .. } => self.is_retry_error(error),
+      Self::Timeout { .. } | Self::Stalled { .. } | Self::TooSlow { .. } => true,
+      #[cfg(feature = "ftp")]
+      Self::Ftp(error) => match error {
+        suppaftp::FtpError::ConnectionError(_) => true,
+        // 4xx 是 FTP 的临时性否定回复，5xx 是永久性错误
+        suppaftp::FtpError::UnexpectedResponse(response) => {
+          (400..500).contains(&response.status.code())
         }
-      }
-      Self::Timeout(_) => {
-        debug!("transient error: {:?}", self);
-        backoff::Error::transient(self)
-      }
-      Self::Semaphore(_) => {
-        debug!("transient error: {:?}", self);
-        backoff::Error::transient(self)
-      }
-      Self::Path { .. } => {
-        debug!("permanent error: {:?}", self);
-        backoff::Error::permanent(self)
-      }
-      Self::IntegrityHash { .. } => {
-        debug!("permanent error: {:?}", self);
-        backoff::Error::permanent(self)
-      }
+        _ => false,
+      },
+      Self::Semaphore(_) => true,
+      Self::Incomplete { .. } => true,
+      // 其他都是永久性错误
+      Self::Paused
+      | Self::Interrupted
+      | Self::DeadlineExceeded { .. }
+      | Self::CircuitOpen { .. }
+      | Self::Path { .. }
+      | Self::TooLarge { .. }
+      | Self::SizeMismatch { .. }
+      | Self::InvalidResponse { .. }
+      | Self::UnexpectedContent { .. }
+      | Self::TargetExists { .. }
+      | Self::Tls { .. }
+      | Self::Config(_)
+      | Self::MissingFeature { .. }
+      | Self::UnsupportedPlatform { .. }
+      | Self::Redirect { .. }
+      | Self::Sftp { .. }
+      | Self::S3 { .. }
+      | Self::Torrent { .. }
+      | Self::Extract { .. }
+      | Self::Checksum { .. }
+      | Self::ChecksumNotFound { .. }
+      | Self::RangeNotSupported { .. }
+      | Self::RemoteZip { .. }
+      | Self::DataUrl { .. }
+      | Self::Manifest { .. }
+      | Self::IntegrityHash { .. }
+      | Self::SignatureInvalid { .. } => false,
+    }
+  }
+
+  /// Wraps the error for the backoff crate according to [`is_retryable`](Self::is_retryable).
+  pub fn into_backoff_err(self) -> backoff::Error<Self> {
+    if self.is_retryable() {
+      debug!("transient error: {:?}", self);
+      backoff::Error::transient(self)
+    } else {
+      debug!("permanent error: {:?}", self);
+      backoff::Error::permanent(self)
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::error::Error;
+
+  use super::*;
+
+  #[test]
+  fn test_source() {
+    let source = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+    let error = ProgressDownloadError::io_at(Path::new("out/file.part"))(source);
+    assert_eq!(
+      error.to_string(),
+      "IO error: out/file.part: permission denied"
+    );
+    assert!(error.source().is_some());
+
+    let error = ProgressDownloadError::Timeout {
+      phase: TimeoutPhase::Connect,
+      timeout: Duration::from_secs(2),
+    };
+    assert_eq!(
+      error.to_string(),
+      "Timeout error: connecting took longer than 2s"
+    );
+    assert!(error.is_retryable());
+  }
+}
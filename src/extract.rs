@@ -0,0 +1,173 @@
+use std::{
+  cell::Cell,
+  fs::File,
+  io::{self, Read},
+  path::Path,
+  rc::Rc,
+};
+
+/// Archive formats that can be unpacked after a download, detected from the file name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ArchiveFormat {
+  Tar,
+  TarGz,
+  #[cfg(feature = "zstd")]
+  TarZst,
+  Zip,
+}
+
+impl ArchiveFormat {
+  pub fn from_file_name(name: &str) -> Option<Self> {
+    let has_suffix = |suffix: &str| {
+      name.len() >= suffix.len()
+        && name.as_bytes()[name.len() - suffix.len()..].eq_ignore_ascii_case(suffix.as_bytes())
+    };
+
+    if has_suffix(".tar.gz") || has_suffix(".tgz") {
+      Some(Self::TarGz)
+    } else if has_suffix(".tar") {
+      Some(Self::Tar)
+    } else if has_suffix(".zip") {
+      Some(Self::Zip)
+    } else {
+      #[cfg(feature = "zstd")]
+      if has_suffix(".tar.zst") || has_suffix(".tzst") {
+        return Some(Self::TarZst);
+      }
+      None
+    }
+  }
+}
+
+/// Unpacks `archive` into `dir`, calling `on_progress` with the archive bytes processed
+/// so far and the archive size.
+///
+/// Entries whose path would end up outside `dir` are skipped. This is blocking and must
+/// run on a blocking thread.
+pub(crate) fn extract(
+  archive: &Path,
+  format: ArchiveFormat,
+  dir: &Path,
+  mut on_progress: impl FnMut(u64, u64),
+) -> io::Result<()> {
+  let file = File::open(archive)?;
+  let total = file.metadata()?.len();
+
+  std::fs::create_dir_all(dir)?;
+
+  if format == ArchiveFormat::Zip {
+    return extract_zip(file, dir, total, on_progress);
+  }
+
+  let reader = CountingReader::new(file);
+  let read = reader.read.clone();
+
+  let reader: Box<dyn Read> = match format {
+    ArchiveFormat::TarGz => Box::new(flate2::read::GzDecoder::new(reader)),
+    #[cfg(feature = "zstd")]
+    ArchiveFormat::TarZst => Box::new(zstd::stream::read::Decoder::new(reader)?),
+    _ => Box::new(reader),
+  };
+
+  let mut archive = tar::Archive::new(reader);
+
+  for entry in archive.entries()? {
+    let mut entry = entry?;
+    // unpack_in 会拒绝解压到目标目录之外的路径
+    entry.unpack_in(dir)?;
+    on_progress(read.get(), total);
+  }
+
+  on_progress(total, total);
+  Ok(())
+}
+
+fn extract_zip(
+  file: File,
+  dir: &Path,
+  total: u64,
+  mut on_progress: impl FnMut(u64, u64),
+) -> io::Result<()> {
+  let mut archive = zip::ZipArchive::new(file).map_err(io::Error::other)?;
+  let mut processed = 0;
+
+  for index in 0..archive.len() {
+    let mut entry = archive.by_index(index).map_err(io::Error::other)?;
+    processed += entry.compressed_size();
+
+    // 跳过会解压到目标目录之外的路径
+    let Some(path) = entry.enclosed_name().map(|name| dir.join(name)) else {
+      continue;
+    };
+
+    if entry.is_dir() {
+      std::fs::create_dir_all(&path)?;
+    } else {
+      if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+      }
+      let mut output = File::create(&path)?;
+      io::copy(&mut entry, &mut output)?;
+
+      #[cfg(unix)]
+      if let Some(mode) = entry.unix_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))?;
+      }
+    }
+
+    on_progress(processed.min(total), total);
+  }
+
+  on_progress(total, total);
+  Ok(())
+}
+
+/// Counts the bytes read from the archive file to report extraction progress.
+struct CountingReader<R> {
+  inner: R,
+  read: Rc<Cell<u64>>,
+}
+
+impl<R> CountingReader<R> {
+  fn new(inner: R) -> Self {
+    Self {
+      inner,
+      read: Default::default(),
+    }
+  }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let read = self.inner.read(buf)?;
+    self.read.set(self.read.get() + read as u64);
+    Ok(read)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_from_file_name() {
+    assert_eq!(
+      ArchiveFormat::from_file_name("node-v23.tar.gz"),
+      Some(ArchiveFormat::TarGz)
+    );
+    assert_eq!(
+      ArchiveFormat::from_file_name("pkg.TGZ"),
+      Some(ArchiveFormat::TarGz)
+    );
+    assert_eq!(
+      ArchiveFormat::from_file_name("src.tar"),
+      Some(ArchiveFormat::Tar)
+    );
+    assert_eq!(
+      ArchiveFormat::from_file_name("win.zip"),
+      Some(ArchiveFormat::Zip)
+    );
+    assert_eq!(ArchiveFormat::from_file_name("notes.txt"), None);
+  }
+}
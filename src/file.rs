@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use bytes::BytesMut;
+use futures::{Stream, StreamExt};
+use reqwest::Url;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::{err::ProgressDownloadError, source::SourceFile};
+
+const READ_BUFFER_SIZE: usize = 256 * 1024;
+
+/// The local path of a `file://` URL.
+pub(crate) fn path_of(url: &str) -> Result<PathBuf, ProgressDownloadError> {
+  Url::parse(url)
+    .ok()
+    .and_then(|url| url.to_file_path().ok())
+    .ok_or_else(|| ProgressDownloadError::Path {
+      path: url.to_string(),
+    })
+}
+
+/// Opens the local file of a `file://` URL for download from `offset` on.
+pub(crate) async fn open(url: &str, offset: u64) -> Result<SourceFile, ProgressDownloadError> {
+  let path = path_of(url)?;
+  let mut file = tokio::fs::File::open(&path)
+    .await
+    .map_err(ProgressDownloadError::io_at(&path))?;
+  let metadata = file.metadata().await?;
+  if !metadata.is_file() {
+    return Err(ProgressDownloadError::Path {
+      path: path.display().to_string(),
+    });
+  }
+
+  let resumed = offset > 0 && offset <= metadata.len();
+  if resumed {
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+  }
+
+  Ok(SourceFile {
+    size: Some(metadata.len()),
+    modified: metadata.modified().ok().map(httpdate::fmt_http_date),
+    etag: None,
+    resumed,
+    stream: into_stream(file).boxed(),
+  })
+}
+
+/// Reads the file as a stream of chunks.
+fn into_stream(
+  file: tokio::fs::File,
+) -> impl Stream<Item = Result<bytes::Bytes, ProgressDownloadError>> {
+  futures::stream::try_unfold(file, |mut file| async move {
+    let mut buffer = BytesMut::zeroed(READ_BUFFER_SIZE);
+    let read = file.read(&mut buffer).await?;
+    if read == 0 {
+      return Ok(None);
+    }
+    buffer.truncate(read);
+    Ok(Some((buffer.freeze(), file)))
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_open() {
+    let dir = std::env::temp_dir().join(format!("robust_downloader_file_{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let path = dir.join("local file.txt");
+    tokio::fs::write(&path, b"hello, world").await.unwrap();
+    let url = Url::from_file_path(&path).unwrap();
+    assert!(url.as_str().ends_with("local%20file.txt"));
+
+    let file = open(url.as_str(), 7).await.unwrap();
+    assert_eq!(file.size, Some(12));
+    assert!(file.resumed);
+    let chunks = file.stream.map(|chunk| chunk.unwrap()).collect::<Vec<_>>();
+    assert_eq!(chunks.await.concat(), b"world");
+
+    assert!(open(&format!("{}.missing", url), 0).await.is_err());
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+  }
+}
@@ -0,0 +1,227 @@
+use std::{ffi::OsString, path::Path};
+
+use reqwest::{Url, header::HeaderValue};
+
+use crate::{cache::fnv1a, winpath};
+
+/// File name used when neither the response nor the URL provides one.
+pub const DEFAULT_FILE_NAME: &str = "download";
+
+/// Longest part of the target's file name kept in the name of a temp file in a shared
+/// temp directory, leaving room for the hash within the usual 255-byte limit.
+const MAX_TEMP_STEM: usize = 200;
+
+/// The name of the temp file `target` is downloaded to from `url`, or `None` if the
+/// target has no file name, e.g. `..` or `/`. Without a `url` the name depends on the
+/// target only, so a partial download is found again from any URL.
+///
+/// Next to the target it is `<file name>.part`. Only the file name of the target is used,
+/// so `a/b/file` and `../file` cannot reach outside the temp directory. In a `shared` temp
+/// directory, targets in different directories or downloaded from different URLs may
+/// have the same file name, so the name is `<file name>.<hash of the URL and the
+/// target>.part` instead, with the file name shortened and stripped of characters that
+/// are not portable.
+pub(crate) fn temp_name(target: &Path, url: Option<&str>, shared: bool) -> Option<OsString> {
+  let file_name = target.file_name()?;
+  if !shared {
+    let mut name = file_name.to_owned();
+    name.push(".part");
+    return Some(name);
+  }
+
+  let mut stem = String::new();
+  for char in file_name.to_string_lossy().chars() {
+    if stem.len() + char.len_utf8() > MAX_TEMP_STEM {
+      break;
+    }
+    stem.push(match char {
+      '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+      char if char.is_control() => '_',
+      char => char,
+    });
+  }
+  let key = match url {
+    Some(url) => format!("{}\0{}", url, target.to_string_lossy()),
+    None => target.to_string_lossy().into_owned(),
+  };
+  Some(format!("{}.{:016x}.part", stem, fnv1a(&key)).into())
+}
+
+/// Splits `file_name` into its stem and all of its extensions at the first dot, e.g.
+/// `a.tar.gz` into `a` and `.tar.gz`. A leading dot belongs to the stem of a hidden file
+/// such as `.bashrc`.
+pub(crate) fn split_extensions(file_name: &str) -> (&str, &str) {
+  match file_name
+    .char_indices()
+    .skip(1)
+    .find(|(_, char)| *char == '.')
+  {
+    Some((index, _)) => file_name.split_at(index),
+    None => (file_name, ""),
+  }
+}
+
+/// Extracts the file name from a `Content-Disposition` header value.
+///
+/// The RFC 5987 `filename*` parameter takes precedence over the plain `filename` parameter.
+pub fn from_content_disposition(value: &HeaderValue) -> Option<String> {
+  let value = String::from_utf8_lossy(value.as_bytes());
+
+  let mut plain = None;
+  let mut extended = None;
+
+  for param in value.split(';').skip(1) {
+    let Some((key, value)) = param.split_once('=') else {
+      continue;
+    };
+    let value = value.trim();
+    match key.trim() {
+      "filename*" => {
+        // 格式为 charset'language'percent-encoded-name
+        extended = value
+          .splitn(3, '\'')
+          .nth(2)
+          .map(|encoded| percent_decode(encoded.trim_matches('"')));
+      }
+      "filename" => plain = Some(value.trim_matches('"').to_string()),
+      _ => {}
+    }
+  }
+
+  extended.or(plain).and_then(|name| sanitize(&name))
+}
+
+/// Extracts the file name from the last segment of the URL path.
+pub fn from_url(url: &str) -> Option<String> {
+  let url = Url::parse(url).ok()?;
+  let segment = url.path_segments()?.next_back()?;
+  sanitize(&percent_decode(segment))
+}
+
+/// Keeps only the final path component so a server cannot write outside the target
+/// directory, made valid on every platform with [`winpath::sanitize`].
+fn sanitize(name: &str) -> Option<String> {
+  let name = name.rsplit(['/', '\\']).next()?.trim();
+  match winpath::sanitize(name).as_str() {
+    "" | "." | ".." => None,
+    name => Some(name.to_string()),
+  }
+}
+
+fn percent_decode(value: &str) -> String {
+  String::from_utf8_lossy(&percent_decode_bytes(value)).into_owned()
+}
+
+/// Decodes the `%XX` escapes of `value`, keeping malformed escapes as they are.
+pub(crate) fn percent_decode_bytes(value: &str) -> Vec<u8> {
+  let bytes = value.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+
+  while i < bytes.len() {
+    let hex = bytes
+      .get(i + 1..i + 3)
+      .and_then(|hex| std::str::from_utf8(hex).ok())
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+    match (bytes[i], hex) {
+      (b'%', Some(byte)) => {
+        decoded.push(byte);
+        i += 3;
+      }
+      (byte, _) => {
+        decoded.push(byte);
+        i += 1;
+      }
+    }
+  }
+
+  decoded
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_from_content_disposition() {
+    let value = HeaderValue::from_static("attachment; filename=\"report.pdf\"");
+    assert_eq!(
+      from_content_disposition(&value).as_deref(),
+      Some("report.pdf")
+    );
+
+    let value = HeaderValue::from_static(
+      "attachment; filename=\"fallback.txt\"; filename*=UTF-8''na%C3%AFve%20file.txt",
+    );
+    assert_eq!(
+      from_content_disposition(&value).as_deref(),
+      Some("naïve file.txt")
+    );
+
+    let value = HeaderValue::from_static("attachment; filename=\"../../etc/passwd\"");
+    assert_eq!(from_content_disposition(&value).as_deref(), Some("passwd"));
+
+    let value = HeaderValue::from_static("inline");
+    assert_eq!(from_content_disposition(&value), None);
+  }
+
+  #[test]
+  fn test_temp_name() {
+    let url = "https://example.com/file.bin";
+    let name = |target: &str, shared| {
+      temp_name(Path::new(target), Some(url), shared)
+        .map(|name| name.to_string_lossy().into_owned())
+    };
+
+    assert_eq!(
+      name("a/b/file.bin", false).as_deref(),
+      Some("file.bin.part")
+    );
+    assert_eq!(name("../file.bin", false).as_deref(), Some("file.bin.part"));
+    assert_eq!(
+      name("/abs/file.bin", false).as_deref(),
+      Some("file.bin.part")
+    );
+    assert_eq!(name("..", false), None);
+    assert_eq!(name("/", true), None);
+
+    // 共享目录中同名的目标文件使用不同的临时文件
+    let nested = name("a/b/file.bin", true).unwrap();
+    let parent = name("../file.bin", true).unwrap();
+    let absolute = name("/abs/file.bin", true).unwrap();
+    assert!(nested.starts_with("file.bin.") && nested.ends_with(".part"));
+    assert!(!nested.contains('/'));
+    assert_ne!(nested, parent);
+    assert_ne!(nested, absolute);
+    assert_ne!(
+      temp_name(
+        Path::new("file.bin"),
+        Some("https://example.com/other.bin"),
+        true
+      ),
+      temp_name(Path::new("file.bin"), Some(url), true)
+    );
+    // 不区分 URL 时只按目标文件区分
+    assert_ne!(
+      temp_name(Path::new("a/file.bin"), None, true),
+      temp_name(Path::new("b/file.bin"), None, true)
+    );
+
+    let long = "x".repeat(300);
+    assert!(name(&long, true).unwrap().len() <= 255);
+    assert_eq!(
+      name("a\u{7}b.bin", true).unwrap().split('.').next(),
+      Some("a_b")
+    );
+  }
+
+  #[test]
+  fn test_from_url() {
+    assert_eq!(
+      from_url("https://example.com/dist/node%20v23.tar.gz?x=1").as_deref(),
+      Some("node v23.tar.gz")
+    );
+    assert_eq!(from_url("https://example.com/"), None);
+  }
+}
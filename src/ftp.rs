@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use reqwest::Url;
+use suppaftp::{
+  async_native_tls::TlsConnector,
+  tokio::{
+    AsyncDataStream, AsyncNativeTlsConnector, AsyncNativeTlsFtpStream, AsyncNativeTlsStream,
+  },
+  types::FileType,
+};
+use tokio::io::AsyncReadExt;
+use tracing::debug;
+
+use crate::{err::ProgressDownloadError, remote::percent_decode, source::SourceFile};
+
+/// Size of the buffer the data connection is read into.
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Logs in to the server of `url` and starts downloading its file from `offset`.
+///
+/// `ftps://` URLs upgrade the control and data connections with explicit TLS
+/// (`AUTH TLS`). Credentials are taken from the URL, defaulting to an anonymous login.
+pub(crate) async fn open(
+  url: &str,
+  offset: u64,
+  connect_timeout: Duration,
+) -> Result<SourceFile, ProgressDownloadError> {
+  let url = Url::parse(url).map_err(|err| ProgressDownloadError::Path {
+    path: format!("{url}: {err}"),
+  })?;
+  let host = url.host_str().unwrap_or_default().to_string();
+  let port = url.port().unwrap_or(21);
+  let path = percent_decode(url.path());
+
+  let addr = tokio::net::lookup_host((host.as_str(), port))
+    .await?
+    .next()
+    .ok_or_else(|| std::io::Error::other(format!("{host}: no address found")))?;
+
+  let mut ftp = AsyncNativeTlsFtpStream::connect_timeout(addr, connect_timeout).await?;
+  if url.scheme().eq_ignore_ascii_case("ftps") {
+    let connector = AsyncNativeTlsConnector::from(TlsConnector::new());
+    ftp = ftp.into_secure(connector, &host).await?;
+  }
+
+  let (user, password) = match url.username() {
+    "" => ("anonymous".to_string(), "anonymous@".to_string()),
+    user => (
+      percent_decode(user),
+      percent_decode(url.password().unwrap_or_default()),
+    ),
+  };
+  ftp.login(user.as_str(), password.as_str()).await?;
+  ftp.transfer_type(FileType::Binary).await?;
+
+  // SIZE 和 MDTM 是扩展命令，服务端不支持时忽略
+  let size = ftp.size(&path).await.ok().map(|size| size as u64);
+  let modified = ftp
+    .mdtm(&path)
+    .await
+    .ok()
+    .map(|modified| httpdate::fmt_http_date(modified.and_utc().into()));
+
+  let resumed = offset > 0 && ftp.resume_transfer(offset as usize).await.is_ok();
+  if offset > 0 && !resumed {
+    debug!("FTP server does not support REST, starting over: {}", url);
+  }
+
+  let transfer = ftp.retr_as_stream(&path).await?;
+
+  Ok(SourceFile {
+    size,
+    modified,
+    etag: None,
+    resumed,
+    stream: into_stream(ftp, transfer).boxed(),
+  })
+}
+
+/// Reads the data connection as a stream of chunks, completing the transfer and closing
+/// the control connection once the file has been received.
+fn into_stream(
+  ftp: AsyncNativeTlsFtpStream,
+  transfer: AsyncDataStream<AsyncNativeTlsStream>,
+) -> impl Stream<Item = Result<Bytes, ProgressDownloadError>> {
+  futures::stream::try_unfold(Some((ftp, transfer)), |state| async move {
+    let Some((mut ftp, mut transfer)) = state else {
+      return Ok(None);
+    };
+
+    let mut buffer = BytesMut::zeroed(READ_BUFFER_SIZE);
+    let read = transfer.read(&mut buffer).await?;
+    if read > 0 {
+      buffer.truncate(read);
+      return Ok(Some((buffer.freeze(), Some((ftp, transfer)))));
+    }
+
+    // 读取服务端的传输完成回复，确认文件完整
+    ftp.finalize_retr_stream(transfer).await?;
+    let _ = ftp.quit().await;
+    Ok(None)
+  })
+}
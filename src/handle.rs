@@ -0,0 +1,277 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::watch;
+
+use crate::{
+  err::ProgressDownloadError,
+  reporter::{DownloadInfo, ProgressReporter, RetryAttempt},
+};
+
+/// Controls a running download from outside the downloader.
+///
+/// Create a handle, pass a clone of it to [`DownloadItem`](crate::DownloadItem) and keep
+/// the other one to pause or resume the download at any time. Pausing drops the
+/// connection but keeps the partially downloaded file; resuming continues where it stopped.
+///
+/// [`progress`](Self::progress) observes the download from other tasks, e.g. a web UI,
+/// independently of the [`ProgressReporter`] of the downloader.
+///
+/// # Example
+///
+/// ```rust
+/// use robust_downloader::{DownloadHandle, DownloadItem};
+///
+/// let handle = DownloadHandle::new();
+/// let item = DownloadItem::builder()
+///   .url("https://example.com/file.zip")
+///   .target("local/file.zip")
+///   .handle(handle.clone())
+///   .build();
+///
+/// handle.pause();
+/// assert!(handle.is_paused());
+/// handle.resume();
+/// ```
+#[derive(Debug, Clone)]
+pub struct DownloadHandle {
+  paused: Arc<watch::Sender<bool>>,
+  progress: Arc<watch::Sender<ProgressSnapshot>>,
+}
+
+/// The state of a download, see [`ProgressSnapshot`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DownloadState {
+  /// The download has not started yet.
+  #[default]
+  Pending,
+  /// Data is being received.
+  Downloading,
+  /// The download was paused with [`DownloadHandle::pause`].
+  Paused,
+  /// An attempt failed and the next one starts after a delay.
+  Retrying,
+  /// The downloaded archive is being unpacked.
+  Extracting,
+  /// The file was saved to its target path.
+  Finished,
+  /// The target file was already up to date.
+  Skipped,
+  /// The download failed permanently.
+  Failed,
+}
+
+/// The progress of one download, published by [`DownloadHandle::progress`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProgressSnapshot {
+  pub state: DownloadState,
+  /// Bytes downloaded so far, including bytes resumed from a previous run.
+  pub downloaded: u64,
+  /// Total size, if the server reported it.
+  pub total: Option<u64>,
+  /// Number of failed attempts that were retried.
+  pub retries: usize,
+  /// Message of the error of the last failed attempt.
+  pub error: Option<String>,
+}
+
+impl Default for DownloadHandle {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl DownloadHandle {
+  pub fn new() -> Self {
+    Self {
+      paused: Arc::new(watch::Sender::new(false)),
+      progress: Arc::new(watch::Sender::new(ProgressSnapshot::default())),
+    }
+  }
+
+  /// Pauses the download, closing its connection after the current chunk.
+  pub fn pause(&self) {
+    self.paused.send_replace(true);
+    self.set_state(DownloadState::Downloading, DownloadState::Paused);
+  }
+
+  /// Resumes a paused download.
+  pub fn resume(&self) {
+    self.paused.send_replace(false);
+    self.set_state(DownloadState::Paused, DownloadState::Downloading);
+  }
+
+  /// Changes the state of the download from `from` to `to`, if it is in `from`.
+  fn set_state(&self, from: DownloadState, to: DownloadState) {
+    self.progress.send_if_modified(|progress| {
+      let modified = progress.state == from;
+      if modified {
+        progress.state = to;
+      }
+      modified
+    });
+  }
+
+  /// Receives the progress of the download whenever it changes.
+  pub fn progress(&self) -> watch::Receiver<ProgressSnapshot> {
+    self.progress.subscribe()
+  }
+
+  /// The current progress of the download.
+  pub fn snapshot(&self) -> ProgressSnapshot {
+    self.progress.borrow().clone()
+  }
+
+  pub fn is_paused(&self) -> bool {
+    *self.paused.borrow()
+  }
+
+  /// Completes once the download is paused.
+  pub(crate) async fn paused(&self) {
+    let _ = self.paused.subscribe().wait_for(|paused| *paused).await;
+  }
+
+  /// Completes once the download is not paused.
+  pub(crate) async fn resumed(&self) {
+    let _ = self.paused.subscribe().wait_for(|paused| !*paused).await;
+  }
+}
+
+/// Forwards the events of an item with a [`DownloadHandle`] to the reporter of the
+/// downloader, publishing them as [`ProgressSnapshot`]s of the handle.
+pub(crate) struct HandleReporter {
+  pub inner: Arc<dyn ProgressReporter>,
+  pub handle: DownloadHandle,
+}
+
+impl HandleReporter {
+  fn update(&self, update: impl FnOnce(&mut ProgressSnapshot)) {
+    self.handle.progress.send_modify(update);
+  }
+
+  /// The state of a running download, which stays paused while the handle is paused.
+  fn running(&self) -> DownloadState {
+    if self.handle.is_paused() {
+      DownloadState::Paused
+    } else {
+      DownloadState::Downloading
+    }
+  }
+
+  fn record_retry(&self, error: &ProgressDownloadError) {
+    self.update(|progress| {
+      progress.state = DownloadState::Retrying;
+      progress.retries += 1;
+      progress.error = Some(error.to_string());
+    });
+  }
+}
+
+impl ProgressReporter for HandleReporter {
+  fn on_batch_started(&self, files: usize, total_size: Option<u64>) {
+    self.inner.on_batch_started(files, total_size);
+  }
+
+  fn on_started(&self, info: &DownloadInfo, downloaded: u64, total: Option<u64>) {
+    let state = self.running();
+    self.update(|progress| {
+      progress.state = state;
+      progress.downloaded = downloaded;
+      progress.total = total;
+    });
+    self.inner.on_started(info, downloaded, total);
+  }
+
+  fn on_bytes_received(&self, info: &DownloadInfo, downloaded: u64, total: Option<u64>) {
+    self.update(|progress| {
+      progress.downloaded = downloaded;
+      // 大小未知的下载结束时才报告总大小
+      progress.total = total.or(progress.total);
+    });
+    self.inner.on_bytes_received(info, downloaded, total);
+  }
+
+  fn on_bytes_decoded(&self, info: &DownloadInfo, decoded: u64) {
+    self.inner.on_bytes_decoded(info, decoded);
+  }
+
+  fn on_peers(&self, info: &DownloadInfo, peers: usize) {
+    self.inner.on_peers(info, peers);
+  }
+
+  fn on_retrying(&self, info: &DownloadInfo, error: &ProgressDownloadError, delay: Duration) {
+    self.record_retry(error);
+    self.inner.on_retrying(info, error, delay);
+  }
+
+  fn on_retry_scheduled(&self, info: &DownloadInfo, retry: &RetryAttempt<'_>) {
+    self.record_retry(retry.error);
+    self.inner.on_retry_scheduled(info, retry);
+  }
+
+  fn on_extracting(&self, info: &DownloadInfo, extracted: u64, total: u64) {
+    self.update(|progress| progress.state = DownloadState::Extracting);
+    self.inner.on_extracting(info, extracted, total);
+  }
+
+  fn on_finished(&self, info: &DownloadInfo) {
+    self.update(|progress| progress.state = DownloadState::Finished);
+    self.inner.on_finished(info);
+  }
+
+  fn on_skipped(&self, info: &DownloadInfo) {
+    self.update(|progress| progress.state = DownloadState::Skipped);
+    self.inner.on_skipped(info);
+  }
+
+  fn on_failed(&self, info: &DownloadInfo, error: &ProgressDownloadError) {
+    self.update(|progress| {
+      progress.state = DownloadState::Failed;
+      progress.error = Some(error.to_string());
+    });
+    self.inner.on_failed(info, error);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_handle_progress() {
+    struct Silent;
+    impl ProgressReporter for Silent {}
+
+    let handle = DownloadHandle::new();
+    let reporter = HandleReporter {
+      inner: Arc::new(Silent),
+      handle: handle.clone(),
+    };
+    let info = DownloadInfo {
+      index: 0,
+      url: "https://example.com/file".to_string(),
+      target: "file".into(),
+    };
+    let mut progress = handle.progress();
+    assert_eq!(progress.borrow_and_update().state, DownloadState::Pending);
+
+    reporter.on_started(&info, 10, Some(100));
+    reporter.on_bytes_received(&info, 50, None);
+    assert!(progress.has_changed().unwrap());
+    let snapshot = handle.snapshot();
+    assert_eq!(snapshot.state, DownloadState::Downloading);
+    assert_eq!((snapshot.downloaded, snapshot.total), (50, Some(100)));
+
+    handle.pause();
+    assert_eq!(handle.snapshot().state, DownloadState::Paused);
+    handle.resume();
+    assert_eq!(handle.snapshot().state, DownloadState::Downloading);
+
+    let error = ProgressDownloadError::Interrupted;
+    reporter.on_retrying(&info, &error, Duration::from_secs(1));
+    reporter.on_finished(&info);
+    let snapshot = handle.snapshot();
+    assert_eq!(snapshot.state, DownloadState::Finished);
+    assert_eq!(snapshot.retries, 1);
+    assert_eq!(snapshot.error, Some(error.to_string()));
+  }
+}
@@ -0,0 +1,211 @@
+use std::{fmt, io::SeekFrom, path::Path, str::FromStr};
+
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::err::ProgressDownloadError;
+
+/// A hash algorithm for the digest computed while downloading.
+///
+/// Each algorithm is available with the crate feature of the same name, like the
+/// variants of [`Integrity`](crate::Integrity).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+  #[cfg(feature = "md5")]
+  MD5,
+  #[cfg(feature = "sha1")]
+  SHA1,
+  #[cfg(feature = "sha2")]
+  SHA256,
+  #[cfg(feature = "sha2")]
+  SHA384,
+  #[cfg(feature = "sha2")]
+  SHA512,
+  #[cfg(feature = "sha3")]
+  SHA3_256,
+  #[cfg(feature = "blake2")]
+  Blake2b,
+  #[cfg(feature = "blake2")]
+  Blake2s,
+  #[cfg(feature = "blake3")]
+  Blake3,
+}
+
+impl FromStr for HashAlgorithm {
+  type Err = ProgressDownloadError;
+
+  /// Parses `md5`, `sha1`, `sha256`, `sha384`, `sha512`, `sha3-256`, `blake2b`, `blake2s`
+  /// or `blake3`, in any case. Fails for algorithms whose feature is disabled.
+  fn from_str(name: &str) -> Result<Self, Self::Err> {
+    Self::ALL
+      .iter()
+      .copied()
+      .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name.trim()))
+      .ok_or_else(|| ProgressDownloadError::Checksum {
+        checksum: name.to_string(),
+      })
+  }
+}
+
+impl HashAlgorithm {
+  /// The algorithms enabled by the crate features.
+  pub(crate) const ALL: &[Self] = &[
+    #[cfg(feature = "md5")]
+    Self::MD5,
+    #[cfg(feature = "sha1")]
+    Self::SHA1,
+    #[cfg(feature = "sha2")]
+    Self::SHA256,
+    #[cfg(feature = "sha2")]
+    Self::SHA384,
+    #[cfg(feature = "sha2")]
+    Self::SHA512,
+    #[cfg(feature = "sha3")]
+    Self::SHA3_256,
+    #[cfg(feature = "blake2")]
+    Self::Blake2b,
+    #[cfg(feature = "blake2")]
+    Self::Blake2s,
+    #[cfg(feature = "blake3")]
+    Self::Blake3,
+  ];
+
+  /// The lowercase name the algorithm is parsed from, e.g. `sha256`.
+  pub(crate) fn name(self) -> &'static str {
+    match self {
+      #[cfg(feature = "md5")]
+      Self::MD5 => "md5",
+      #[cfg(feature = "sha1")]
+      Self::SHA1 => "sha1",
+      #[cfg(feature = "sha2")]
+      Self::SHA256 => "sha256",
+      #[cfg(feature = "sha2")]
+      Self::SHA384 => "sha384",
+      #[cfg(feature = "sha2")]
+      Self::SHA512 => "sha512",
+      #[cfg(feature = "sha3")]
+      Self::SHA3_256 => "sha3-256",
+      #[cfg(feature = "blake2")]
+      Self::Blake2b => "blake2b",
+      #[cfg(feature = "blake2")]
+      Self::Blake2s => "blake2s",
+      #[cfg(feature = "blake3")]
+      Self::Blake3 => "blake3",
+    }
+  }
+}
+
+/// Hashes the downloaded bytes as they are written, so the digest is known once the
+/// last chunk arrives without reading the file again.
+pub(crate) struct Hasher {
+  state: State,
+  /// Number of bytes hashed so far.
+  position: u64,
+}
+
+impl fmt::Debug for Hasher {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Hasher")
+      .field("position", &self.position)
+      .finish_non_exhaustive()
+  }
+}
+
+enum State {
+  Digest(Box<dyn digest::DynDigest + Send>),
+  #[cfg(feature = "blake3")]
+  Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+  pub fn new(algorithm: HashAlgorithm) -> Self {
+    let state = match algorithm {
+      #[cfg(feature = "md5")]
+      HashAlgorithm::MD5 => State::Digest(Box::new(md5::Md5::default())),
+      #[cfg(feature = "sha1")]
+      HashAlgorithm::SHA1 => State::Digest(Box::new(sha1::Sha1::default())),
+      #[cfg(feature = "sha2")]
+      HashAlgorithm::SHA256 => State::Digest(Box::new(sha2::Sha256::default())),
+      #[cfg(feature = "sha2")]
+      HashAlgorithm::SHA384 => State::Digest(Box::new(sha2::Sha384::default())),
+      #[cfg(feature = "sha2")]
+      HashAlgorithm::SHA512 => State::Digest(Box::new(sha2::Sha512::default())),
+      #[cfg(feature = "sha3")]
+      HashAlgorithm::SHA3_256 => State::Digest(Box::new(sha3::Sha3_256::default())),
+      #[cfg(feature = "blake2")]
+      HashAlgorithm::Blake2b => State::Digest(Box::new(blake2::Blake2b512::default())),
+      #[cfg(feature = "blake2")]
+      HashAlgorithm::Blake2s => State::Digest(Box::new(blake2::Blake2s256::default())),
+      #[cfg(feature = "blake3")]
+      HashAlgorithm::Blake3 => State::Blake3(Box::default()),
+    };
+
+    Self { state, position: 0 }
+  }
+
+  /// Number of bytes hashed so far.
+  pub fn position(&self) -> u64 {
+    self.position
+  }
+
+  pub fn update(&mut self, data: &[u8]) {
+    match &mut self.state {
+      State::Digest(digest) => digest.update(data),
+      #[cfg(feature = "blake3")]
+      State::Blake3(hasher) => {
+        hasher.update(data);
+      }
+    }
+    self.position += data.len() as u64;
+  }
+
+  /// Hashes the bytes of `path` after the ones already hashed, up to `len` bytes.
+  pub async fn update_from_file(&mut self, path: &Path, len: u64) -> std::io::Result<()> {
+    if self.position >= len {
+      return Ok(());
+    }
+
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(self.position)).await?;
+
+    let mut reader = file.take(len.saturating_sub(self.position));
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+      let n = reader.read(&mut buffer).await?;
+      if n == 0 {
+        return Ok(());
+      }
+      self.update(&buffer[..n]);
+    }
+  }
+
+  /// The lowercase hex digest of all bytes hashed.
+  pub fn finalize(self) -> String {
+    match self.state {
+      State::Digest(digest) => digest
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect(),
+      #[cfg(feature = "blake3")]
+      State::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(feature = "sha2")]
+  #[test]
+  fn test_sha256() {
+    let mut hasher = Hasher::new(HashAlgorithm::SHA256);
+    hasher.update(b"hello ");
+    hasher.update(b"world");
+    assert_eq!(hasher.position(), 11);
+    assert_eq!(
+      hasher.finalize(),
+      "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+    );
+  }
+}
@@ -0,0 +1,81 @@
+use std::{fmt, future::Future, path::PathBuf, time::Duration};
+
+use futures::{FutureExt, future::BoxFuture};
+
+use crate::{err::ProgressDownloadError, reporter::DownloadInfo};
+
+/// What a [`DownloadHook`] is called with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HookEvent {
+  /// The URL being downloaded.
+  pub url: String,
+  /// The local path the file will be saved to, empty when downloading into a writer.
+  pub target: PathBuf,
+  /// Number of the attempt: the first one for `on_start`, the one that failed for
+  /// `on_retry` and the last one for `on_complete` and `on_error`.
+  pub attempt: usize,
+  /// Message of the error, for `on_retry` and `on_error`.
+  pub error: Option<String>,
+  /// Time until the next attempt, for `on_retry`.
+  pub delay: Option<Duration>,
+}
+
+impl HookEvent {
+  pub(crate) fn new(info: &DownloadInfo, attempt: usize) -> Self {
+    Self {
+      url: info.url.clone(),
+      target: info.target.clone(),
+      attempt,
+      error: None,
+      delay: None,
+    }
+  }
+
+  pub(crate) fn with_error(mut self, error: &ProgressDownloadError) -> Self {
+    self.error = Some(error.to_string());
+    self
+  }
+}
+
+/// An async callback at a lifecycle point of every download, see the `on_start`,
+/// `on_retry`, `on_complete` and `on_error` options.
+///
+/// Closures taking a [`HookEvent`] and returning a future implement this trait. Hooks
+/// are awaited before the download continues, except `on_retry`, which runs in its own
+/// task so the delay before the next attempt is not extended.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use robust_downloader::{HookEvent, RobustDownloader};
+///
+/// // 连续失败多次时发送通知
+/// let downloader = RobustDownloader::builder()
+///   .on_retry(Arc::new(|event: HookEvent| async move {
+///     if event.attempt >= 3 {
+///       eprintln!("{} failed {} times: {:?}", event.url, event.attempt, event.error);
+///     }
+///   }))
+///   .build();
+/// ```
+pub trait DownloadHook: Send + Sync {
+  fn call(&self, event: HookEvent) -> BoxFuture<'static, ()>;
+}
+
+impl<F, Fut> DownloadHook for F
+where
+  F: Fn(HookEvent) -> Fut + Send + Sync,
+  Fut: Future<Output = ()> + Send + 'static,
+{
+  fn call(&self, event: HookEvent) -> BoxFuture<'static, ()> {
+    self(event).boxed()
+  }
+}
+
+impl fmt::Debug for dyn DownloadHook + '_ {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("DownloadHook")
+  }
+}
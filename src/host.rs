@@ -0,0 +1,110 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use reqwest::Url;
+use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore};
+
+/// Limits the number of open connections to each host.
+///
+/// Hosts are told apart by their host name and port, so every connection to the same
+/// server waits for the same permits, whichever file or mirror it downloads.
+#[derive(Debug)]
+pub struct HostLimiter {
+  max_per_host: usize,
+  hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimiter {
+  pub fn new(max_per_host: usize) -> Self {
+    Self {
+      max_per_host: max_per_host.max(1),
+      hosts: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Waits until a connection to the host of `url` may be opened.
+  ///
+  /// The connection counts against the limit until the returned permit is dropped.
+  /// URLs without a host are not limited.
+  pub async fn acquire(&self, url: &str) -> Result<Option<OwnedSemaphorePermit>, AcquireError> {
+    let Some(host) = host_key(url) else {
+      return Ok(None);
+    };
+
+    let semaphore = self
+      .hosts
+      .lock()
+      .unwrap()
+      .entry(host)
+      .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_host)))
+      .clone();
+
+    semaphore.acquire_owned().await.map(Some)
+  }
+}
+
+/// The host name and port of `url`, e.g. `example.com:443`.
+fn host_key(url: &str) -> Option<String> {
+  let url = Url::parse(url).ok()?;
+  let host = url.host_str().filter(|host| !host.is_empty())?;
+
+  // 主机名不区分大小写，Url 已将其转为小写
+  Some(match url.port_or_known_default() {
+    Some(port) => format!("{host}:{port}"),
+    None => host.to_string(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_host_key() {
+    assert_eq!(
+      host_key("https://Example.com/a.bin").as_deref(),
+      Some("example.com:443")
+    );
+    assert_eq!(
+      host_key("http://example.com:8080/a.bin").as_deref(),
+      Some("example.com:8080")
+    );
+    assert_eq!(
+      host_key("sftp://example.com/a.bin").as_deref(),
+      Some("example.com")
+    );
+    assert_eq!(host_key("not a url"), None);
+  }
+
+  #[tokio::test]
+  async fn test_limit_per_host() {
+    let limiter = HostLimiter::new(1);
+
+    let first = limiter.acquire("https://example.com/a.bin").await.unwrap();
+    assert!(first.is_some());
+
+    // 同一主机的第二个连接需要等待，其他主机不受影响
+    let other = limiter
+      .acquire("https://mirror.example.org/a.bin")
+      .await
+      .unwrap();
+    assert!(other.is_some());
+    let blocked = tokio::time::timeout(
+      std::time::Duration::from_millis(10),
+      limiter.acquire("https://example.com/b.bin"),
+    )
+    .await;
+    assert!(blocked.is_err());
+
+    drop(first);
+    assert!(
+      limiter
+        .acquire("https://example.com/b.bin")
+        .await
+        .unwrap()
+        .is_some()
+    );
+  }
+}
@@ -0,0 +1,98 @@
+use std::{io, net::SocketAddr, sync::Arc};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+/// Which IP versions connections use, see the `ip_preference` option.
+///
+/// Connections to hosts with both IPv4 and IPv6 addresses start with the preferred
+/// version and race the other one if no connection is established within 300ms
+/// (Happy Eyeballs, RFC 6555). Addresses from the `resolve` option are used as given.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpPreference {
+  /// The order of the system resolver, racing both versions.
+  #[default]
+  HappyEyeballs,
+  /// IPv4 addresses first, racing IPv6.
+  PreferIpv4,
+  /// IPv6 addresses first, racing IPv4.
+  PreferIpv6,
+  /// Only IPv4 addresses, e.g. for networks with broken IPv6.
+  Ipv4Only,
+  /// Only IPv6 addresses.
+  Ipv6Only,
+}
+
+impl IpPreference {
+  /// Applies the preference to `builder` by resolving host names with a resolver that
+  /// orders or filters the addresses.
+  pub(crate) fn apply(self, builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+    match self {
+      Self::HappyEyeballs => builder,
+      _ => builder.dns_resolver(Arc::new(OrderedResolver(self))),
+    }
+  }
+
+  /// `addrs` in the order connections try them, without the excluded IP version.
+  fn order(self, mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    match self {
+      Self::HappyEyeballs => {}
+      // 排序是稳定的，同一版本的地址保持系统的顺序
+      Self::PreferIpv4 => addrs.sort_by_key(SocketAddr::is_ipv6),
+      Self::PreferIpv6 => addrs.sort_by_key(SocketAddr::is_ipv4),
+      Self::Ipv4Only => addrs.retain(SocketAddr::is_ipv4),
+      Self::Ipv6Only => addrs.retain(SocketAddr::is_ipv6),
+    }
+    addrs
+  }
+}
+
+/// Resolves host names with the system resolver and orders the addresses according to
+/// an [`IpPreference`].
+struct OrderedResolver(IpPreference);
+
+impl Resolve for OrderedResolver {
+  fn resolve(&self, name: Name) -> Resolving {
+    let preference = self.0;
+    Box::pin(async move {
+      let host = name.as_str();
+      // 端口 0 会被替换为 URL 的端口
+      let addrs = preference.order(tokio::net::lookup_host((host, 0)).await?.collect());
+      if addrs.is_empty() {
+        let message = format!("{host} has no address for {preference:?}");
+        return Err(io::Error::new(io::ErrorKind::AddrNotAvailable, message).into());
+      }
+      Ok(Box::new(addrs.into_iter()) as Addrs)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_order() {
+    let addrs: Vec<SocketAddr> = ["[::1]:0", "127.0.0.1:0", "[::2]:0", "127.0.0.2:0"]
+      .iter()
+      .map(|addr| addr.parse().unwrap())
+      .collect();
+    let order = |preference: IpPreference| {
+      preference
+        .order(addrs.clone())
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+      order(IpPreference::PreferIpv4),
+      ["127.0.0.1:0", "127.0.0.2:0", "[::1]:0", "[::2]:0"]
+    );
+    assert_eq!(
+      order(IpPreference::PreferIpv6),
+      ["[::1]:0", "[::2]:0", "127.0.0.1:0", "127.0.0.2:0"]
+    );
+    assert_eq!(order(IpPreference::Ipv6Only), ["[::1]:0", "[::2]:0"]);
+    assert_eq!(order(IpPreference::HappyEyeballs).len(), 4);
+  }
+}
@@ -1,6 +1,19 @@
+use std::{path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use reqwest::{
+  RequestBuilder, Url,
+  header::{HeaderMap, USER_AGENT},
+};
 use typed_builder::TypedBuilder;
 
-#[derive(Debug, Clone)]
+use crate::{
+  checksum::ChecksumFile, err::ProgressDownloadError, filename, handle::DownloadHandle,
+  hasher::HashAlgorithm, part::RemotePart, retry::RetryPolicy, signature::Signature,
+  transform::TransformFactory, validator::ResponseValidator,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Integrity {
   #[cfg(feature = "md5")]
   MD5(String),
@@ -9,6 +22,8 @@ pub enum Integrity {
   #[cfg(feature = "sha2")]
   SHA256(String),
   #[cfg(feature = "sha2")]
+  SHA384(String),
+  #[cfg(feature = "sha2")]
   SHA512(String),
   #[cfg(feature = "sha3")]
   SHA3_256(String),
@@ -21,6 +36,30 @@ pub enum Integrity {
 }
 
 impl Integrity {
+  /// The expected `digest` of a file hashed with `algorithm`.
+  pub fn new(algorithm: HashAlgorithm, digest: String) -> Self {
+    match algorithm {
+      #[cfg(feature = "md5")]
+      HashAlgorithm::MD5 => Integrity::MD5(digest),
+      #[cfg(feature = "sha1")]
+      HashAlgorithm::SHA1 => Integrity::SHA1(digest),
+      #[cfg(feature = "sha2")]
+      HashAlgorithm::SHA256 => Integrity::SHA256(digest),
+      #[cfg(feature = "sha2")]
+      HashAlgorithm::SHA384 => Integrity::SHA384(digest),
+      #[cfg(feature = "sha2")]
+      HashAlgorithm::SHA512 => Integrity::SHA512(digest),
+      #[cfg(feature = "sha3")]
+      HashAlgorithm::SHA3_256 => Integrity::SHA3_256(digest),
+      #[cfg(feature = "blake2")]
+      HashAlgorithm::Blake2b => Integrity::Blake2b(digest),
+      #[cfg(feature = "blake2")]
+      HashAlgorithm::Blake2s => Integrity::Blake2s(digest),
+      #[cfg(feature = "blake3")]
+      HashAlgorithm::Blake3 => Integrity::Blake3(digest),
+    }
+  }
+
   pub fn value(&self) -> &str {
     match self {
       #[cfg(feature = "md5")]
@@ -30,6 +69,8 @@ impl Integrity {
       #[cfg(feature = "sha2")]
       Integrity::SHA256(value) => value,
       #[cfg(feature = "sha2")]
+      Integrity::SHA384(value) => value,
+      #[cfg(feature = "sha2")]
       Integrity::SHA512(value) => value,
       #[cfg(feature = "sha3")]
       Integrity::SHA3_256(value) => value,
@@ -42,8 +83,49 @@ impl Integrity {
     }
   }
 
-  pub fn algorithm(&self) -> hashery::Algorithm {
+  /// The algorithm of the digest computed while downloading.
+  pub fn hash_algorithm(&self) -> HashAlgorithm {
     match self {
+      #[cfg(feature = "md5")]
+      Integrity::MD5(_) => HashAlgorithm::MD5,
+      #[cfg(feature = "sha1")]
+      Integrity::SHA1(_) => HashAlgorithm::SHA1,
+      #[cfg(feature = "sha2")]
+      Integrity::SHA256(_) => HashAlgorithm::SHA256,
+      #[cfg(feature = "sha2")]
+      Integrity::SHA384(_) => HashAlgorithm::SHA384,
+      #[cfg(feature = "sha2")]
+      Integrity::SHA512(_) => HashAlgorithm::SHA512,
+      #[cfg(feature = "sha3")]
+      Integrity::SHA3_256(_) => HashAlgorithm::SHA3_256,
+      #[cfg(feature = "blake2")]
+      Integrity::Blake2b(_) => HashAlgorithm::Blake2b,
+      #[cfg(feature = "blake2")]
+      Integrity::Blake2s(_) => HashAlgorithm::Blake2s,
+      #[cfg(feature = "blake3")]
+      Integrity::Blake3(_) => HashAlgorithm::Blake3,
+    }
+  }
+
+  /// The matching [`hashery`] algorithm.
+  ///
+  /// Deprecated since [`hashery`] has no SHA-384, which SRI metadata commonly uses. Use
+  /// [`hash_algorithm`](Self::hash_algorithm), which covers every variant, or
+  /// [`try_algorithm`](Self::try_algorithm) instead.
+  ///
+  /// # Panics
+  ///
+  /// Panics for SHA-384.
+  #[deprecated(note = "use `hash_algorithm` or `try_algorithm` instead")]
+  pub fn algorithm(&self) -> hashery::Algorithm {
+    self
+      .try_algorithm()
+      .expect("hashery does not support SHA-384")
+  }
+
+  /// The matching [`hashery`] algorithm, `None` for SHA-384 which it does not support.
+  pub fn try_algorithm(&self) -> Option<hashery::Algorithm> {
+    let algorithm = match self {
       #[cfg(feature = "md5")]
       Integrity::MD5(_) => hashery::Algorithm::MD5,
       #[cfg(feature = "sha1")]
@@ -51,6 +133,8 @@ impl Integrity {
       #[cfg(feature = "sha2")]
       Integrity::SHA256(_) => hashery::Algorithm::SHA256,
       #[cfg(feature = "sha2")]
+      Integrity::SHA384(_) => return None,
+      #[cfg(feature = "sha2")]
       Integrity::SHA512(_) => hashery::Algorithm::SHA512,
       #[cfg(feature = "sha3")]
       Integrity::SHA3_256(_) => hashery::Algorithm::SHA3_256,
@@ -60,15 +144,279 @@ impl Integrity {
       Integrity::Blake2s(_) => hashery::Algorithm::Blake2s,
       #[cfg(feature = "blake3")]
       Integrity::Blake3(_) => hashery::Algorithm::Blake3,
+    };
+    Some(algorithm)
+  }
+
+  /// Parses subresource integrity metadata, keeping the longest digest among the hashes
+  /// with an enabled algorithm. Hashes that cannot be parsed are ignored, like browsers do.
+  fn from_sri(value: &str) -> Option<Self> {
+    value
+      .split_ascii_whitespace()
+      .filter_map(|hash| {
+        // `?` 之后是保留给将来使用的选项
+        let hash = hash.split('?').next()?;
+        // base64 中没有 `-`，算法名称中可能有
+        let (algorithm, digest) = hash.rsplit_once('-')?;
+        let algorithm = algorithm.parse::<HashAlgorithm>().ok()?;
+        let digest = BASE64_STANDARD.decode(digest).ok()?;
+        Some((algorithm, digest))
+      })
+      .max_by_key(|(_, digest)| digest.len())
+      .map(|(algorithm, digest)| {
+        let digest = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+        Integrity::new(algorithm, digest)
+      })
+  }
+}
+
+impl FromStr for Integrity {
+  type Err = ProgressDownloadError;
+
+  /// Parses a checksum written as `<algorithm>:<hex digest>`, e.g. `sha256:e3b0c442...`,
+  /// or as [subresource integrity](https://www.w3.org/TR/SRI/) metadata
+  /// `<algorithm>-<base64 digest>`, e.g. `sha384-OLBgp1GsljhM2TJ+sbHjaiH9...`.
+  /// Of several space separated SRI hashes the strongest one is used.
+  ///
+  /// Algorithm names are parsed like [`HashAlgorithm`]s. Fails for algorithms whose
+  /// feature is disabled.
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    let invalid = || ProgressDownloadError::Checksum {
+      checksum: value.to_string(),
+    };
+
+    let Some((algorithm, digest)) = value.split_once(':') else {
+      return Self::from_sri(value).ok_or_else(invalid);
+    };
+    let digest = digest.trim().to_string();
+    if digest.is_empty() || !digest.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+      return Err(invalid());
     }
+
+    let algorithm = algorithm.parse().map_err(|_| invalid())?;
+    Ok(Integrity::new(algorithm, digest))
   }
 }
 
+/// A single file to download, together with optional per-item overrides of the
+/// [`RobustDownloader`](crate::RobustDownloader) settings.
+///
+/// Any override left unset falls back to the value configured on the downloader.
 #[derive(Debug, Clone, TypedBuilder)]
 pub struct DownloadItem<U, P> {
+  /// The URL to download from.
   pub url: U,
+  /// The local path where the file should be saved.
+  ///
+  /// `-` streams the body to stdout, and the path of an existing named pipe streams it
+  /// into the pipe, as it arrives and without a temporary file. Progress meant for
+  /// stdout is shown on stderr instead. Streamed downloads resume after the bytes
+  /// already written, but cannot be checked against an integrity, extracted or
+  /// skipped when unchanged.
   pub target: P,
 
+  /// Fallback URLs serving the same file.
+  /// On connection errors the next attempt switches to the next mirror. A file failing
+  /// its integrity check is downloaded again right away from the next mirror, and the
+  /// mirror that served it is tried last by the other downloads of the batch.
+  #[builder(default)]
+  pub mirrors: Vec<U>,
+
+  /// Part of the remote file to download instead of the whole file, a byte range or a
+  /// member of a ZIP archive. `integrity`, `expected_size` and the other checks apply to
+  /// the downloaded part. Only supported for HTTP URLs.
+  #[builder(default = None, setter(strip_option))]
+  pub part: Option<RemotePart>,
+
+  /// Expected hash of the downloaded file.
   #[builder(default = None, setter(strip_option))]
   pub integrity: Option<Integrity>,
+
+  /// Checksum file listing the expected hash of the downloaded file, fetched before the
+  /// download starts. Ignored if `integrity` is set.
+  #[builder(default = None, setter(strip_option))]
+  pub checksum_file: Option<ChecksumFile>,
+
+  /// Bytes the downloaded file must start with, such as the magic number of its format
+  /// (`b"PK\x03\x04"` for zip archives). Other content fails the download with
+  /// [`ProgressDownloadError::UnexpectedContent`].
+  #[builder(default = None, setter(into, strip_option))]
+  pub expected_magic: Option<Vec<u8>>,
+
+  /// Expected size of the downloaded file in bytes. The download fails as soon as the
+  /// server reports or sends a different size.
+  #[builder(default = None, setter(strip_option))]
+  pub expected_size: Option<u64>,
+
+  /// Time to wait for the response headers of each request of this item.
+  #[builder(default = None, setter(strip_option))]
+  pub timeout: Option<Duration>,
+
+  /// Total time the download of this item may take, including all retries.
+  #[builder(default = None, setter(strip_option))]
+  pub deadline: Option<Duration>,
+
+  /// Time without receiving any data after which the connection counts as stalled.
+  #[builder(default = None, setter(strip_option))]
+  pub stall_timeout: Option<Duration>,
+
+  /// Buffer size threshold for flushing downloaded data to disk.
+  #[builder(default = None, setter(strip_option))]
+  pub flush_threshold: Option<usize>,
+
+  /// Policy for retrying failed attempts of this item.
+  #[builder(default = None, setter(strip_option))]
+  pub retry_policy: Option<RetryPolicy>,
+
+  /// Maximum download speed of this item in bytes per second.
+  #[builder(default = None, setter(strip_option))]
+  pub max_bytes_per_sec: Option<u64>,
+
+  /// Extra HTTP headers sent with every request of this item.
+  /// They take precedence over the downloader's default headers.
+  #[builder(default)]
+  pub headers: HeaderMap,
+
+  /// Checks the headers of this item's HTTP responses instead of the downloader's
+  /// `response_validator`.
+  #[builder(default = None, setter(strip_option))]
+  pub response_validator: Option<Arc<dyn ResponseValidator>>,
+
+  /// Creates the transforms this item's body passes through instead of the downloader's
+  /// `transforms`.
+  #[builder(default = None, setter(strip_option))]
+  pub transforms: Option<Arc<dyn TransformFactory>>,
+
+  /// Bearer token sent in the `Authorization` header of this item's requests.
+  #[builder(default = None, setter(into, strip_option))]
+  pub bearer_token: Option<String>,
+
+  /// `User-Agent` header of this item's requests instead of the downloader's
+  /// `user_agent`. A `User-Agent` in `headers` takes precedence.
+  #[builder(default = None, setter(into, strip_option))]
+  pub user_agent: Option<String>,
+
+  /// Detached minisign signature of the file, verified with the downloader's
+  /// `signature_key` before the file is moved to its target. Requires the `minisign`
+  /// feature.
+  #[builder(default = None, setter(strip_option))]
+  pub signature: Option<Signature>,
+
+  /// URL of the [zsync](http://zsync.moria.org.uk/) control file of the file, absolute or
+  /// relative to `url`. If the target already exists, e.g. as an older version of the
+  /// file, only the blocks that changed are downloaded with Range requests and the rest
+  /// is copied from the target. Requires the `zsync` feature.
+  #[builder(default = None, setter(into, strip_option))]
+  pub zsync: Option<String>,
+
+  /// Directory to unpack the downloaded archive into once its integrity has been verified.
+  /// The format is detected from the target file name: `.tar`, `.tar.gz`/`.tgz`, `.zip`,
+  /// and `.tar.zst` with the `zstd` feature. Requires the `extract` feature.
+  #[builder(default = None, setter(into, strip_option))]
+  pub extract_to: Option<PathBuf>,
+
+  /// Unix permission bits applied to the downloaded file, e.g. `0o755` for executables.
+  #[builder(default = None, setter(strip_option))]
+  pub file_mode: Option<u32>,
+
+  /// Handle to pause and resume this download while it runs.
+  #[builder(default = None, setter(strip_option))]
+  pub handle: Option<DownloadHandle>,
+}
+
+impl<U, P> DownloadItem<U, P> {
+  /// Adds the item's `user_agent` and `headers` to `request`.
+  pub(crate) fn apply_headers(&self, request: RequestBuilder) -> RequestBuilder {
+    // 之后设置的 headers 覆盖 user_agent
+    let request = match &self.user_agent {
+      Some(user_agent) => request.header(USER_AGENT, user_agent.as_str()),
+      None => request,
+    };
+    request.headers(self.headers.clone())
+  }
+}
+
+/// A URL and the local path to save it to, with every other setting left unset.
+impl<U, P> From<(U, P)> for DownloadItem<U, P> {
+  fn from((url, target): (U, P)) -> Self {
+    Self::builder().url(url).target(target).build()
+  }
+}
+
+/// A URL saved in the current directory under the last segment of its path, or
+/// `download` if it has none.
+impl From<Url> for DownloadItem<Url, PathBuf> {
+  fn from(url: Url) -> Self {
+    let name =
+      filename::from_url(url.as_str()).unwrap_or_else(|| filename::DEFAULT_FILE_NAME.to_string());
+    Self::builder().url(url).target(PathBuf::from(name)).build()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_from() {
+    let item = DownloadItem::from(("https://example.com/a.zip", "out/a.zip"));
+    assert_eq!(
+      (item.url, item.target),
+      ("https://example.com/a.zip", "out/a.zip")
+    );
+
+    let url = Url::parse("https://example.com/dir/b.tar.gz?x=1").unwrap();
+    assert_eq!(DownloadItem::from(url).target, PathBuf::from("b.tar.gz"));
+    let url = Url::parse("https://example.com/").unwrap();
+    assert_eq!(DownloadItem::from(url).target, PathBuf::from("download"));
+  }
+
+  #[cfg(feature = "sha2")]
+  #[test]
+  fn test_parse_sri() {
+    // "abc" 的 SHA-256 和 SHA-384 摘要
+    let sha256 = "sha256-ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=";
+    let sha384 = "sha384-ywB1P0WjXou1oD1pmsZQBycsMqsO3tFjGotgWkP/W+2AhgcroefMI1i67KE0yCWn";
+
+    assert_eq!(
+      sha256.parse::<Integrity>().unwrap(),
+      Integrity::SHA256(
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string()
+      )
+    );
+    let integrity = format!("{sha256} {sha384}?ct=application/gzip md5-invalid")
+      .parse::<Integrity>()
+      .unwrap();
+    assert_eq!(integrity.hash_algorithm(), HashAlgorithm::SHA384);
+    assert!(integrity.value().starts_with("cb00753f45a35e8b"));
+
+    assert!("sha256-not base64!".parse::<Integrity>().is_err());
+    assert!("crc32-AAAA".parse::<Integrity>().is_err());
+  }
+
+  #[test]
+  fn test_apply_headers() {
+    let client = reqwest::Client::new();
+    let user_agent = |item: DownloadItem<&str, &str>| {
+      let request = item.apply_headers(client.get(item.url)).build().unwrap();
+      request.headers().get(USER_AGENT).cloned()
+    };
+
+    let item = DownloadItem::builder()
+      .url("https://example.com/a.zip")
+      .target("a.zip")
+      .user_agent("agent/1.0")
+      .build();
+    assert_eq!(user_agent(item).unwrap(), "agent/1.0");
+
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, "header/2.0".parse().unwrap());
+    let item = DownloadItem::builder()
+      .url("https://example.com/a.zip")
+      .target("a.zip")
+      .user_agent("agent/1.0")
+      .headers(headers)
+      .build();
+    assert_eq!(user_agent(item).unwrap(), "header/2.0");
+  }
 }
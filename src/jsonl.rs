@@ -0,0 +1,205 @@
+use std::{
+  collections::HashMap,
+  fmt::Write as _,
+  io::Write as _,
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+use crate::{
+  err::ProgressDownloadError,
+  reporter::{DownloadInfo, ProgressReporter},
+  stream,
+};
+
+/// Reports progress as one JSON object per line on stdout, for CI logs and other
+/// machine readers.
+///
+/// Progress lines are printed at most once per `interval` for each download:
+///
+/// ```json
+/// {"event":"progress","index":0,"url":"https://example.com/a.bin","target":"a.bin","downloaded":1048576,"total":4194304,"speed":524288}
+/// ```
+///
+/// `speed` is in bytes per second since the previous line of the download, `total` is
+/// `null` if the server did not report the size. `started`, `retrying`, `finished`,
+/// `skipped` and `failed` events are printed as they happen, `retrying` and `failed`
+/// with an `error` message. Selected as the default reporter with
+/// [`ProgressMode::JsonLines`](crate::ProgressMode::JsonLines).
+/// While a download streams to stdout, the lines are printed on stderr instead.
+#[derive(Debug)]
+pub struct JsonLinesReporter {
+  interval: Duration,
+  downloads: Mutex<HashMap<usize, Sample>>,
+}
+
+/// 上一次输出进度时的状态
+#[derive(Debug)]
+struct Sample {
+  at: Instant,
+  downloaded: u64,
+}
+
+impl Default for JsonLinesReporter {
+  fn default() -> Self {
+    Self::new(Duration::from_secs(1))
+  }
+}
+
+impl JsonLinesReporter {
+  /// Creates a reporter printing the progress of each download every `interval`.
+  pub fn new(interval: Duration) -> Self {
+    Self {
+      interval,
+      downloads: Mutex::new(HashMap::new()),
+    }
+  }
+
+  fn print(&self, line: String) {
+    // 管道关闭时忽略写入错误，不影响下载
+    let _ = if stream::stdout_taken() {
+      writeln!(std::io::stderr().lock(), "{line}")
+    } else {
+      writeln!(std::io::stdout().lock(), "{line}")
+    };
+  }
+}
+
+/// Starts the JSON object of an event, without the closing brace.
+fn event(name: &str, info: &DownloadInfo) -> String {
+  format!(
+    "{{\"event\":\"{}\",\"index\":{},\"url\":{},\"target\":{}",
+    name,
+    info.index,
+    json_string(&info.url),
+    json_string(&info.target.to_string_lossy())
+  )
+}
+
+fn json_number(value: Option<u64>) -> String {
+  value.map_or_else(|| "null".to_string(), |value| value.to_string())
+}
+
+/// Quotes and escapes `value` as a JSON string.
+fn json_string(value: &str) -> String {
+  let mut quoted = String::with_capacity(value.len() + 2);
+  quoted.push('"');
+  for char in value.chars() {
+    match char {
+      '"' => quoted.push_str("\\\""),
+      '\\' => quoted.push_str("\\\\"),
+      '\n' => quoted.push_str("\\n"),
+      '\r' => quoted.push_str("\\r"),
+      '\t' => quoted.push_str("\\t"),
+      char if char.is_control() => {
+        let _ = write!(quoted, "\\u{:04x}", char as u32);
+      }
+      char => quoted.push(char),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+impl ProgressReporter for JsonLinesReporter {
+  fn on_started(&self, info: &DownloadInfo, downloaded: u64, total: Option<u64>) {
+    self.downloads.lock().unwrap().insert(
+      info.index,
+      Sample {
+        at: Instant::now(),
+        downloaded,
+      },
+    );
+    self.print(format!(
+      "{},\"downloaded\":{},\"total\":{}}}",
+      event("started", info),
+      downloaded,
+      json_number(total)
+    ));
+  }
+
+  fn on_bytes_received(&self, info: &DownloadInfo, downloaded: u64, total: Option<u64>) {
+    let now = Instant::now();
+    let speed = {
+      let mut downloads = self.downloads.lock().unwrap();
+      let sample = downloads.entry(info.index).or_insert(Sample {
+        at: now,
+        downloaded,
+      });
+      let elapsed = now.duration_since(sample.at);
+      // 下载完成时的进度总是输出，大小未知的下载由此报告最终大小
+      if elapsed < self.interval && total != Some(downloaded) {
+        return;
+      }
+
+      let speed = if elapsed.is_zero() {
+        0.0
+      } else {
+        downloaded.saturating_sub(sample.downloaded) as f64 / elapsed.as_secs_f64()
+      };
+      *sample = Sample {
+        at: now,
+        downloaded,
+      };
+      speed as u64
+    };
+
+    self.print(format!(
+      "{},\"downloaded\":{},\"total\":{},\"speed\":{}}}",
+      event("progress", info),
+      downloaded,
+      json_number(total),
+      speed
+    ));
+  }
+
+  fn on_retrying(&self, info: &DownloadInfo, error: &ProgressDownloadError, delay: Duration) {
+    self.print(format!(
+      "{},\"error\":{},\"delay_ms\":{}}}",
+      event("retrying", info),
+      json_string(&error.to_string()),
+      delay.as_millis()
+    ));
+  }
+
+  fn on_finished(&self, info: &DownloadInfo) {
+    self.downloads.lock().unwrap().remove(&info.index);
+    self.print(format!("{}}}", event("finished", info)));
+  }
+
+  fn on_skipped(&self, info: &DownloadInfo) {
+    self.downloads.lock().unwrap().remove(&info.index);
+    self.print(format!("{}}}", event("skipped", info)));
+  }
+
+  fn on_failed(&self, info: &DownloadInfo, error: &ProgressDownloadError) {
+    self.downloads.lock().unwrap().remove(&info.index);
+    self.print(format!(
+      "{},\"error\":{}}}",
+      event("failed", info),
+      json_string(&error.to_string())
+    ));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use super::*;
+
+  #[test]
+  fn test_event() {
+    let info = DownloadInfo {
+      index: 1,
+      url: "https://example.com/a \"b\".bin".to_string(),
+      target: PathBuf::from("out\\a\n.bin"),
+    };
+    assert_eq!(
+      format!("{}}}", event("finished", &info)),
+      r#"{"event":"finished","index":1,"url":"https://example.com/a \"b\".bin","target":"out\\a\n.bin"}"#
+    );
+    assert_eq!(json_string("\u{1}"), r#""\u0001""#);
+    assert_eq!(json_number(None), "null");
+  }
+}
@@ -0,0 +1,55 @@
+use std::{
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+/// A token-bucket bandwidth limiter.
+///
+/// The bucket holds at most one second worth of bytes. A chunk larger than the
+/// available tokens is still accepted, and the caller waits until the debt is repaid.
+#[derive(Debug)]
+pub struct RateLimiter {
+  bytes_per_sec: f64,
+  bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+  tokens: f64,
+  last_refill: Instant,
+}
+
+impl RateLimiter {
+  pub fn new(bytes_per_sec: u64) -> Self {
+    let bytes_per_sec = bytes_per_sec.max(1) as f64;
+    Self {
+      bytes_per_sec,
+      bucket: Mutex::new(Bucket {
+        tokens: bytes_per_sec,
+        last_refill: Instant::now(),
+      }),
+    }
+  }
+
+  /// Consumes `bytes` tokens, waiting as long as needed to stay under the limit.
+  pub async fn acquire(&self, bytes: usize) {
+    let wait = {
+      let mut bucket = self.bucket.lock().unwrap();
+      let now = Instant::now();
+      let refill = now.duration_since(bucket.last_refill).as_secs_f64() * self.bytes_per_sec;
+      bucket.tokens = (bucket.tokens + refill).min(self.bytes_per_sec);
+      bucket.last_refill = now;
+      bucket.tokens -= bytes as f64;
+
+      if bucket.tokens < 0.0 {
+        Duration::from_secs_f64(-bucket.tokens / self.bytes_per_sec)
+      } else {
+        Duration::ZERO
+      }
+    };
+
+    if !wait.is_zero() {
+      tokio::time::sleep(wait).await;
+    }
+  }
+}
@@ -0,0 +1,247 @@
+use std::{
+  collections::BTreeMap,
+  path::{Path, PathBuf},
+};
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+
+use crate::{
+  ChecksumFile, DownloadItem, DownloadQueue, DownloadResult, Integrity, ProgressDownloadError,
+  RobustDownloader,
+};
+
+/// The format of a download manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+  Json,
+  Toml,
+}
+
+impl ManifestFormat {
+  /// Detects the format from the file extension, `.toml` for TOML and JSON otherwise.
+  pub fn of(path: &Path) -> Self {
+    match path.extension() {
+      Some(extension) if extension.eq_ignore_ascii_case("toml") => Self::Toml,
+      _ => Self::Json,
+    }
+  }
+}
+
+/// A list of files to download, read from a JSON or TOML file.
+///
+/// Manifests list their entries under `downloads`. Each entry needs a `url` and a `dest`,
+/// and may give a `checksum` in the form accepted by [`Integrity`]'s `FromStr` or the URL
+/// of a `checksum_file` listing it, see [`ChecksumFile`], the expected `size` in bytes,
+/// extra HTTP `headers` and a queue `priority`.
+///
+/// ```toml
+/// [[downloads]]
+/// url = "https://example.com/model.bin"
+/// dest = "models/model.bin"
+/// checksum = "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+/// size = 4194304
+/// priority = 10
+///
+/// [[downloads]]
+/// url = "https://example.com/releases/tool.tar.gz"
+/// dest = "tool.tar.gz"
+/// checksum_file = "https://example.com/releases/SHA256SUMS"
+///
+/// [[downloads]]
+/// url = "https://example.com/private/data.csv"
+/// dest = "data/data.csv"
+/// headers = { Authorization = "Bearer secret" }
+/// ```
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use robust_downloader::{Manifest, RobustDownloader};
+/// async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let manifest = Manifest::from_path("artifacts.toml").await?;
+/// let downloader = RobustDownloader::builder().build();
+/// for result in downloader.download_manifest(&manifest).await? {
+///   println!("{}: {}", result.url, result.is_success());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Manifest {
+  pub downloads: Vec<ManifestEntry>,
+}
+
+/// One file of a [`Manifest`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManifestEntry {
+  pub url: String,
+  pub dest: PathBuf,
+  #[serde(default)]
+  pub checksum: Option<String>,
+  /// URL of a checksum file with the checksum of this entry, used without a `checksum`.
+  #[serde(default)]
+  pub checksum_file: Option<String>,
+  /// Expected size of the file in bytes, see [`DownloadItem::expected_size`].
+  #[serde(default)]
+  pub size: Option<u64>,
+  #[serde(default)]
+  pub headers: BTreeMap<String, String>,
+  /// Entries with a higher priority start first. Defaults to 0.
+  #[serde(default)]
+  pub priority: i32,
+}
+
+impl Manifest {
+  /// Reads a manifest file, detecting its format from the extension.
+  pub async fn from_path(path: impl AsRef<Path>) -> Result<Self, ProgressDownloadError> {
+    let path = path.as_ref();
+    let error = |message: String| ProgressDownloadError::Manifest {
+      message: format!("{}: {}", path.display(), message),
+    };
+
+    let content = tokio::fs::read_to_string(path)
+      .await
+      .map_err(|err| error(err.to_string()))?;
+
+    Self::parse(&content, ManifestFormat::of(path)).map_err(|err| match err {
+      ProgressDownloadError::Manifest { message } => error(message),
+      err => err,
+    })
+  }
+
+  /// Parses the content of a manifest.
+  pub fn parse(content: &str, format: ManifestFormat) -> Result<Self, ProgressDownloadError> {
+    let manifest = match format {
+      ManifestFormat::Json => serde_json::from_str(content).map_err(|err| err.to_string()),
+      ManifestFormat::Toml => toml::from_str(content).map_err(|err| err.to_string()),
+    };
+    manifest.map_err(|message| ProgressDownloadError::Manifest { message })
+  }
+
+  /// The download items of all entries, in the order they are listed.
+  pub fn items(&self) -> Result<Vec<DownloadItem<String, PathBuf>>, ProgressDownloadError> {
+    self.downloads.iter().map(ManifestEntry::to_item).collect()
+  }
+
+  /// Adds all entries to `queue` with their priority.
+  pub fn push_to(
+    &self,
+    queue: &DownloadQueue<String, PathBuf>,
+  ) -> Result<(), ProgressDownloadError> {
+    // 先检查所有条目，避免只加入一部分
+    let items = self.items()?;
+    for (item, entry) in items.into_iter().zip(&self.downloads) {
+      queue.push(item, entry.priority);
+    }
+    Ok(())
+  }
+}
+
+impl ManifestEntry {
+  /// The download item described by this entry.
+  pub fn to_item(&self) -> Result<DownloadItem<String, PathBuf>, ProgressDownloadError> {
+    let error = |message: String| ProgressDownloadError::Manifest {
+      message: format!("{}: {}", self.url, message),
+    };
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in &self.headers {
+      let name = HeaderName::from_bytes(name.as_bytes()).map_err(|err| error(err.to_string()))?;
+      let value = HeaderValue::from_str(value).map_err(|err| error(err.to_string()))?;
+      headers.insert(name, value);
+    }
+
+    let integrity = self
+      .checksum
+      .as_deref()
+      .map(str::parse::<Integrity>)
+      .transpose()?;
+
+    let checksum_file = self
+      .checksum_file
+      .as_ref()
+      .map(|url| ChecksumFile::builder().url(url.clone()).build());
+
+    Ok(DownloadItem {
+      integrity,
+      checksum_file,
+      expected_size: self.size,
+      headers,
+      ..DownloadItem::builder()
+        .url(self.url.clone())
+        .target(self.dest.clone())
+        .build()
+    })
+  }
+}
+
+impl RobustDownloader {
+  /// Downloads all entries of `manifest` in the order of their priority.
+  ///
+  /// # Returns
+  ///
+  /// Returns one [`DownloadResult`] per entry, in the order the entries are listed.
+  /// Fails if an entry is invalid or the HTTP client cannot be created.
+  pub async fn download_manifest(
+    &self,
+    manifest: &Manifest,
+  ) -> Result<Vec<DownloadResult>, ProgressDownloadError> {
+    let queue = self.queue()?;
+    manifest.push_to(&queue)?;
+    queue.close();
+    Ok(queue.run().await)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse() {
+    let json = Manifest::parse(
+      r#"{"downloads": [{"url": "https://example.com/a.bin", "dest": "a.bin", "priority": 3}]}"#,
+      ManifestFormat::Json,
+    )
+    .unwrap();
+    assert_eq!(json.downloads[0].priority, 3);
+
+    let toml = Manifest::parse(
+      r#"
+        [[downloads]]
+        url = "https://example.com/b.bin"
+        dest = "out/b.bin"
+        checksum = "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        size = 1024
+        headers = { Authorization = "Bearer secret" }
+      "#,
+      ManifestFormat::Toml,
+    )
+    .unwrap();
+    let item = toml.downloads[0].to_item().unwrap();
+    assert_eq!(item.target, PathBuf::from("out/b.bin"));
+    assert_eq!(item.headers["authorization"], "Bearer secret");
+    assert!(matches!(item.integrity, Some(Integrity::SHA256(_))));
+    assert_eq!(item.expected_size, Some(1024));
+
+    assert!(Manifest::parse(r#"{"downloads": [{"url": "x"}]}"#, ManifestFormat::Json).is_err());
+  }
+
+  #[test]
+  fn test_invalid_checksum() {
+    let entry = ManifestEntry {
+      url: "https://example.com/a.bin".to_string(),
+      dest: PathBuf::from("a.bin"),
+      checksum: Some("crc32:1234".to_string()),
+      checksum_file: None,
+      size: None,
+      headers: BTreeMap::new(),
+      priority: 0,
+    };
+    assert!(matches!(
+      entry.to_item(),
+      Err(ProgressDownloadError::Checksum { .. })
+    ));
+  }
+}
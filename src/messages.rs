@@ -0,0 +1,167 @@
+use std::fmt;
+
+use indicatif::HumanBytes;
+
+use crate::{
+  err::ProgressDownloadError,
+  reporter::{DownloadInfo, RetryAttempt},
+};
+
+/// The status lines a [`ProgressBarReporter`](crate::ProgressBarReporter) shows, see
+/// [`ProgressBarOptions::messages`](crate::ProgressBarOptions::messages).
+///
+/// Every method returns the English text of [`DefaultMessages`] unless overridden, so
+/// applications only need to implement the lines they want to translate or re-brand.
+/// Bar messages end with a space to separate them from the rest of the template.
+///
+/// # Example
+///
+/// ```rust
+/// use robust_downloader::{DownloadInfo, ProgressMessages};
+///
+/// struct German;
+///
+/// impl ProgressMessages for German {
+///   fn finished(&self, info: &DownloadInfo) -> String {
+///     format!("{} gespeichert", info.target.display())
+///   }
+///
+///   fn files(&self, completed: usize, files: usize) -> String {
+///     format!("{completed}/{files} Dateien")
+///   }
+/// }
+/// ```
+pub trait ProgressMessages: Send + Sync {
+  /// Message of the bar of a running download, with its progress in percent if the
+  /// size is known.
+  fn downloading(&self, info: &DownloadInfo, percent: Option<u64>) -> String {
+    match percent {
+      Some(percent) => format!("{}% {} ", percent, info.url),
+      None => format!("{} ", info.url),
+    }
+  }
+
+  /// Message of the bar of a BitTorrent download, with the number of connected peers.
+  fn peers(&self, info: &DownloadInfo, percent: u64, peers: usize) -> String {
+    format!("{}% {} peers {} ", percent, peers, info.url)
+  }
+
+  /// Message of the bar while a failed attempt waits to be retried, also printed as a
+  /// line without a terminal.
+  fn retrying(&self, info: &DownloadInfo, retry: &RetryAttempt<'_>) -> String {
+    let max_retries = retry
+      .max_retries
+      .map_or_else(|| "∞".to_string(), |max_retries| max_retries.to_string());
+    format!(
+      "retry {}/{}: {}, waiting {:.1}s {} ",
+      retry.retry,
+      max_retries,
+      retry.error.reason(),
+      retry.delay.as_secs_f64(),
+      info.url
+    )
+  }
+
+  /// Message of the bar while a downloaded archive is unpacked.
+  fn extracting(&self, info: &DownloadInfo) -> String {
+    format!("extracting {} ", info.target.display())
+  }
+
+  /// Message of the overall bar of a batch.
+  fn files(&self, completed: usize, files: usize) -> String {
+    format!("{}/{} files", completed, files)
+  }
+
+  /// Line printed without a terminal when a download starts.
+  fn started(&self, info: &DownloadInfo, total: Option<u64>) -> String {
+    let size = total.map(|total| format!(" ({})", HumanBytes(total)));
+    format!(
+      "downloading {} to {}{}",
+      info.url,
+      info.target.display(),
+      size.unwrap_or_default()
+    )
+  }
+
+  /// Line printed periodically without a terminal while a download runs.
+  fn progress(&self, info: &DownloadInfo, downloaded: u64, total: Option<u64>) -> String {
+    match total.filter(|total| *total > 0) {
+      Some(total) => format!(
+        "{}: {}% {}/{}",
+        info.url,
+        downloaded * 100 / total,
+        HumanBytes(downloaded),
+        HumanBytes(total)
+      ),
+      None => format!("{}: {}", info.url, HumanBytes(downloaded)),
+    }
+  }
+
+  /// Line printed without a terminal once a file has been saved.
+  fn finished(&self, info: &DownloadInfo) -> String {
+    format!("saved {}", info.target.display())
+  }
+
+  /// Line printed without a terminal when the target file is already up to date.
+  fn skipped(&self, info: &DownloadInfo) -> String {
+    format!("{} is up to date", info.target.display())
+  }
+
+  /// Line printed without a terminal when a download failed permanently.
+  fn failed(&self, info: &DownloadInfo, error: &ProgressDownloadError) -> String {
+    format!("{} failed: {}", info.url, error)
+  }
+}
+
+impl fmt::Debug for dyn ProgressMessages + '_ {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("ProgressMessages")
+  }
+}
+
+/// The English status lines of [`ProgressMessages`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultMessages;
+
+impl ProgressMessages for DefaultMessages {}
+
+#[cfg(test)]
+mod tests {
+  use std::{path::PathBuf, time::Duration};
+
+  use super::*;
+
+  #[test]
+  fn test_default_messages() {
+    let info = DownloadInfo {
+      index: 0,
+      url: "https://example.com/a.zip".to_string(),
+      target: PathBuf::from("a.zip"),
+    };
+    let messages = DefaultMessages;
+    assert_eq!(
+      messages.downloading(&info, Some(42)),
+      "42% https://example.com/a.zip "
+    );
+    assert_eq!(
+      messages.progress(&info, 512, Some(1024)),
+      "https://example.com/a.zip: 50% 512 B/1.00 KiB"
+    );
+    assert_eq!(messages.files(1, 3), "1/3 files");
+
+    let error = ProgressDownloadError::Incomplete {
+      expected: 10,
+      received: 5,
+    };
+    let retry = RetryAttempt {
+      retry: 2,
+      max_retries: None,
+      error: &error,
+      delay: Duration::from_millis(1500),
+    };
+    assert_eq!(
+      messages.retrying(&info, &retry),
+      "retry 2/∞: incomplete response, waiting 1.5s https://example.com/a.zip "
+    );
+  }
+}
@@ -0,0 +1,47 @@
+use metrics::{counter, gauge, histogram};
+
+use crate::{DownloadResult, DownloadStatus};
+
+/// Counter of the bytes received by downloads, added once a download ends.
+const BYTES_DOWNLOADED: &str = "bytes_downloaded_total";
+/// Histogram of how long downloads took in seconds, labeled with their `outcome`.
+const DOWNLOAD_DURATION: &str = "download_duration_seconds";
+/// Counter of the retries of failed attempts.
+const RETRIES: &str = "retries_total";
+/// Gauge of the downloads running right now.
+const ACTIVE_DOWNLOADS: &str = "active_downloads";
+
+/// Counts a download as active until it is dropped.
+pub(crate) struct ActiveDownload(());
+
+impl ActiveDownload {
+  pub(crate) fn start() -> Self {
+    gauge!(ACTIVE_DOWNLOADS).increment(1.0);
+    Self(())
+  }
+}
+
+impl Drop for ActiveDownload {
+  fn drop(&mut self) {
+    gauge!(ACTIVE_DOWNLOADS).decrement(1.0);
+  }
+}
+
+pub(crate) fn record_retry() {
+  counter!(RETRIES).increment(1);
+}
+
+/// Records the bytes and the duration of a download that ended.
+pub(crate) fn record_result(result: &DownloadResult) {
+  counter!(BYTES_DOWNLOADED).increment(result.bytes);
+  histogram!(DOWNLOAD_DURATION, "outcome" => outcome(result)).record(result.elapsed.as_secs_f64());
+}
+
+fn outcome(result: &DownloadResult) -> &'static str {
+  match result.result {
+    Ok(DownloadStatus::Downloaded) => "downloaded",
+    Ok(DownloadStatus::Skipped) => "skipped",
+    Ok(DownloadStatus::Cached) => "cached",
+    Err(_) => "failed",
+  }
+}
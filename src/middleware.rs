@@ -0,0 +1,67 @@
+use std::{fmt, future::Future, sync::Arc};
+
+use futures::{FutureExt, future::BoxFuture};
+use reqwest::RequestBuilder;
+
+/// Adjusts every HTTP request right before it is sent, see the `request_middleware`
+/// option.
+///
+/// Runs again for every attempt, so it can add credentials that change between
+/// retries, such as freshly refreshed OAuth tokens, HMAC signatures or tracing headers.
+/// Closures taking a [`RequestBuilder`] and returning a future of it implement this
+/// trait. To sign a request, split it with [`RequestBuilder::build_split`] and put it
+/// back together with [`RequestBuilder::from_parts`].
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use reqwest::RequestBuilder;
+/// use robust_downloader::RobustDownloader;
+///
+/// async fn access_token() -> String {
+///   // 过期时向授权服务器刷新令牌
+///   "token".to_string()
+/// }
+///
+/// let downloader = RobustDownloader::builder()
+///   .request_middleware(Arc::new(|request: RequestBuilder| async move {
+///     request.bearer_auth(access_token().await)
+///   }))
+///   .build();
+/// ```
+pub trait RequestMiddleware: Send + Sync {
+  fn prepare(&self, request: RequestBuilder) -> BoxFuture<'static, RequestBuilder>;
+}
+
+impl<F, Fut> RequestMiddleware for F
+where
+  F: Fn(RequestBuilder) -> Fut + Send + Sync,
+  Fut: Future<Output = RequestBuilder> + Send + 'static,
+{
+  fn prepare(&self, request: RequestBuilder) -> BoxFuture<'static, RequestBuilder> {
+    self(request).boxed()
+  }
+}
+
+impl fmt::Debug for dyn RequestMiddleware + '_ {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("RequestMiddleware")
+  }
+}
+
+/// `request` prepared by `middleware`, if there is one. With the `opentelemetry`
+/// feature the trace context of the current span is added first, so the middleware
+/// sees every header that will be sent.
+pub(crate) async fn apply(
+  middleware: &Option<Arc<dyn RequestMiddleware>>,
+  request: RequestBuilder,
+) -> RequestBuilder {
+  #[cfg(feature = "opentelemetry")]
+  let request = crate::otel::inject(request);
+  match middleware {
+    Some(middleware) => middleware.prepare(request).await,
+    None => request,
+  }
+}
@@ -0,0 +1,31 @@
+use opentelemetry::{global, propagation::Injector};
+use reqwest::{
+  RequestBuilder,
+  header::{HeaderMap, HeaderName, HeaderValue},
+};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Writes the fields of a propagator into request headers.
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+  fn set(&mut self, key: &str, value: String) {
+    if let (Ok(name), Ok(value)) = (
+      HeaderName::from_bytes(key.as_bytes()),
+      HeaderValue::from_str(&value),
+    ) {
+      self.0.insert(name, value);
+    }
+  }
+}
+
+/// `request` with the trace context of the current span, e.g. a `traceparent` header,
+/// as written by the global propagator.
+pub(crate) fn inject(request: RequestBuilder) -> RequestBuilder {
+  let context = tracing::Span::current().context();
+  let mut headers = HeaderMap::new();
+  global::get_text_map_propagator(|propagator| {
+    propagator.inject_context(&context, &mut HeaderInjector(&mut headers));
+  });
+  request.headers(headers)
+}
@@ -0,0 +1,32 @@
+/// The part of a remote file to download instead of the whole file, see the `part` of a
+/// [`DownloadItem`](crate::DownloadItem).
+///
+/// Parts are requested with `Range` headers, so the server has to support range requests;
+/// otherwise the download fails with
+/// [`RangeNotSupported`](crate::ProgressDownloadError::RangeNotSupported).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemotePart {
+  /// The bytes from `start` up to but not including `end`, or to the end of the file.
+  Range { start: u64, end: Option<u64> },
+  /// The file at this path in a remote ZIP archive, decompressed. Only the end of the
+  /// archive, its central directory and the data of the member are downloaded.
+  ///
+  /// Stored and deflated members are supported, encrypted ones are not. Requires the
+  /// `remote-zip` feature.
+  ZipMember(String),
+}
+
+impl RemotePart {
+  /// Identifies the part in the resume state of its temp file, so a partial download of
+  /// another part or of the whole file is not resumed as this one.
+  pub(crate) fn key(&self) -> String {
+    match self {
+      Self::Range {
+        start,
+        end: Some(end),
+      } => format!("bytes={start}-{end}"),
+      Self::Range { start, end: None } => format!("bytes={start}-"),
+      Self::ZipMember(path) => format!("zip:{path}"),
+    }
+  }
+}
@@ -0,0 +1,270 @@
+use std::{
+  io::ErrorKind,
+  path::{Path, PathBuf},
+};
+
+use crate::filename;
+
+/// What to do when the target file of a download already exists.
+///
+/// The policy is checked before the download starts, so existing files are skipped or
+/// rejected without downloading them, and applied again when the finished file is moved
+/// into place.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+  /// Replace the existing file.
+  #[default]
+  Overwrite,
+  /// Keep the existing file and report the download as skipped.
+  Skip,
+  /// Fail with [`ProgressDownloadError::TargetExists`](crate::ProgressDownloadError::TargetExists).
+  Error,
+  /// Save the download next to the existing file with a numeric suffix, e.g.
+  /// `file-1.tar.gz`. [`DownloadResult::target`](crate::DownloadResult::target) reports the
+  /// path it was saved to.
+  RenameWithSuffix,
+}
+
+/// The first path `<stem>-<n>.<extensions>` next to `target` that does not exist yet,
+/// keeping all extensions together, e.g. `a-1.tar.gz`.
+pub(crate) async fn suffixed_path(target: &Path) -> PathBuf {
+  let file_name = target
+    .file_name()
+    .map(|name| name.to_string_lossy().into_owned())
+    .unwrap_or_default();
+  let (stem, extension) = filename::split_extensions(&file_name);
+
+  for index in 1.. {
+    let candidate = target.with_file_name(format!("{stem}-{index}{extension}"));
+    if !tokio::fs::try_exists(&candidate).await.unwrap_or(false) {
+      return candidate;
+    }
+  }
+  unreachable!()
+}
+
+/// Moves the complete `temp_file` to `target`, replacing an existing file unless
+/// `no_clobber` is set, and syncs the parent directory so the new entry survives a crash.
+///
+/// Returns `Ok(false)` without moving the file if `no_clobber` is set and `target`
+/// already exists.
+pub(crate) async fn persist(
+  temp_file: &Path,
+  target: &Path,
+  no_clobber: bool,
+) -> std::io::Result<bool> {
+  match rename(temp_file, target, no_clobber).await {
+    Ok(false) => return Ok(false),
+    Ok(true) => {}
+    Err(err) if err.kind() == ErrorKind::CrossesDevices => {
+      // 跨设备重命名失败，先复制到目标目录中的临时文件再重命名，目标不会只写入一部分
+      let staged = staging_path(target).await?;
+      let moved = match copy_file(temp_file, &staged).await {
+        Ok(()) => rename(&staged, target, no_clobber).await,
+        Err(err) => Err(err),
+      };
+      if !matches!(moved, Ok(true)) {
+        let _ = tokio::fs::remove_file(&staged).await;
+      }
+      if !moved? {
+        return Ok(false);
+      }
+      tokio::fs::remove_file(temp_file).await?;
+    }
+    Err(err) => return Err(err),
+  }
+
+  sync_parent(target).await?;
+  Ok(true)
+}
+
+/// Renames `source` to `target` on the same file system, replacing an existing file
+/// unless `no_clobber` is set.
+///
+/// Returns `Ok(false)` without renaming the file if `no_clobber` is set and `target`
+/// already exists.
+async fn rename(source: &Path, target: &Path, no_clobber: bool) -> std::io::Result<bool> {
+  if !no_clobber {
+    return tokio::fs::rename(source, target).await.map(|()| true);
+  }
+
+  // 硬链接在目标已存在时失败，可以原子地避免覆盖
+  match tokio::fs::hard_link(source, target).await {
+    Ok(()) => {
+      tokio::fs::remove_file(source).await?;
+      Ok(true)
+    }
+    Err(err) if err.kind() == ErrorKind::AlreadyExists => Ok(false),
+    Err(_) if tokio::fs::try_exists(target).await? => Ok(false),
+    // 文件系统不支持硬链接时退回到重命名
+    Err(_) => tokio::fs::rename(source, target).await.map(|()| true),
+  }
+}
+
+/// A path next to `target` to write a copy of it to before renaming it into place,
+/// removing a stale file left there. The process ID keeps several processes from
+/// writing to the same file.
+async fn staging_path(target: &Path) -> std::io::Result<PathBuf> {
+  let mut name = target.file_name().unwrap_or_default().to_owned();
+  name.push(format!(".{}.part", std::process::id()));
+  let path = target.with_file_name(name);
+  match tokio::fs::remove_file(&path).await {
+    Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+    _ => Ok(path),
+  }
+}
+
+/// Places a copy of `source` at `target`, replacing an existing file. The copy is a
+/// copy-on-write clone on file systems supporting it, and a hard link if `link` is set
+/// and clones are not supported. Falls back to copying the data.
+pub(crate) async fn place(source: &Path, target: &Path, link: bool) -> std::io::Result<()> {
+  // 先写入临时文件再重命名，避免目标文件只写入一部分
+  let temp_file = staging_path(target).await?;
+
+  let placed = match reflink(source, &temp_file).await {
+    Ok(()) => true,
+    Err(_) => link && tokio::fs::hard_link(source, &temp_file).await.is_ok(),
+  };
+  if !placed {
+    copy_file(source, &temp_file).await?;
+  }
+
+  persist(&temp_file, target, false).await?;
+  Ok(())
+}
+
+/// Copies `source` to `target` with its permissions and modification time, and syncs
+/// the copy to disk.
+async fn copy_file(source: &Path, target: &Path) -> std::io::Result<()> {
+  let source = source.to_path_buf();
+  let target = target.to_path_buf();
+  tokio::task::spawn_blocking(move || {
+    let mut source = std::fs::File::open(&source)?;
+    let metadata = source.metadata()?;
+    let mut copied = std::fs::File::create(&target)?;
+    std::io::copy(&mut source, &mut copied)?;
+    // 通过已打开的文件设置修改时间后再设置权限，只读的权限不会导致无法写入
+    copied.set_modified(metadata.modified()?)?;
+    copied.set_permissions(metadata.permissions())?;
+    copied.sync_all()
+  })
+  .await
+  .map_err(std::io::Error::other)?
+}
+
+/// Creates `target` as a copy-on-write clone of `source`, sharing its data blocks.
+pub(crate) async fn reflink(source: &Path, target: &Path) -> std::io::Result<()> {
+  #[cfg(target_os = "linux")]
+  {
+    use std::os::fd::AsRawFd;
+
+    let source = source.to_path_buf();
+    let target = target.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+      let source = std::fs::File::open(&source)?;
+      let metadata = source.metadata()?;
+      let cloned = std::fs::File::create(&target)?;
+      // SAFETY: 两个文件描述符在调用期间一直有效
+      let result = unsafe { libc::ioctl(cloned.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) };
+      if result != 0 {
+        let err = std::io::Error::last_os_error();
+        drop(cloned);
+        let _ = std::fs::remove_file(&target);
+        return Err(err);
+      }
+      cloned.set_permissions(metadata.permissions())?;
+      cloned.set_modified(metadata.modified()?)?;
+      Ok(())
+    })
+    .await
+    .map_err(std::io::Error::other)?
+  }
+
+  #[cfg(not(target_os = "linux"))]
+  {
+    let _ = (source, target);
+    Err(std::io::Error::from(ErrorKind::Unsupported))
+  }
+}
+
+/// Flushes the directory entry of `path` to disk.
+async fn sync_parent(path: &Path) -> std::io::Result<()> {
+  #[cfg(unix)]
+  {
+    let parent = match path.parent() {
+      Some(parent) if !parent.as_os_str().is_empty() => parent,
+      _ => Path::new("."),
+    };
+    tokio::fs::File::open(parent).await?.sync_all().await?;
+  }
+
+  // Windows 上无法打开目录进行同步
+  #[cfg(not(unix))]
+  let _ = path;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_persist_no_clobber() {
+    let dir =
+      std::env::temp_dir().join(format!("robust_downloader_persist_{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let temp_file = dir.join("a.bin.part");
+    let target = dir.join("a.bin");
+
+    tokio::fs::write(&temp_file, b"new").await.unwrap();
+    tokio::fs::write(&target, b"old").await.unwrap();
+    assert!(!persist(&temp_file, &target, true).await.unwrap());
+    assert_eq!(tokio::fs::read(&target).await.unwrap(), b"old");
+
+    let suffixed = suffixed_path(&target).await;
+    assert_eq!(suffixed, dir.join("a-1.bin"));
+    assert!(persist(&temp_file, &suffixed, true).await.unwrap());
+    assert_eq!(tokio::fs::read(&suffixed).await.unwrap(), b"new");
+    assert!(!tokio::fs::try_exists(&temp_file).await.unwrap());
+
+    for (name, suffixed) in [
+      ("a.tar.gz", "a-1.tar.gz"),
+      ("README", "README-1"),
+      (".bashrc", ".bashrc-1"),
+      (".config.toml", ".config-1.toml"),
+      ("é.txt", "é-1.txt"),
+    ] {
+      assert_eq!(suffixed_path(&dir.join(name)).await, dir.join(suffixed));
+    }
+
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn test_copy_read_only() {
+    use std::{os::unix::fs::PermissionsExt, time::SystemTime};
+
+    let dir = std::env::temp_dir().join(format!("robust_downloader_copy_{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let source = dir.join("source.bin");
+    let target = dir.join("target.bin");
+
+    tokio::fs::write(&source, b"data").await.unwrap();
+    let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    let file = std::fs::File::open(&source).unwrap();
+    file.set_modified(modified).unwrap();
+    file
+      .set_permissions(std::fs::Permissions::from_mode(0o444))
+      .unwrap();
+
+    copy_file(&source, &target).await.unwrap();
+    let metadata = tokio::fs::metadata(&target).await.unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o444);
+    assert_eq!(metadata.modified().unwrap(), modified);
+    assert_eq!(tokio::fs::read(&target).await.unwrap(), b"data");
+
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+}
@@ -0,0 +1,54 @@
+use std::{collections::HashSet, sync::Mutex};
+
+use reqwest::Url;
+
+/// Origins of mirrors that served files failing their integrity check, remembered for
+/// the rest of a batch so later downloads try other mirrors first.
+#[derive(Debug, Default)]
+pub struct PoisonedMirrors {
+  origins: Mutex<HashSet<String>>,
+}
+
+impl PoisonedMirrors {
+  /// Remembers that the origin of `url` served bad data.
+  pub fn poison(&self, url: &str) {
+    self.origins.lock().unwrap().insert(origin(url));
+  }
+
+  /// Whether the origin of `url` has served bad data in this batch.
+  pub fn is_poisoned(&self, url: &str) -> bool {
+    self.origins.lock().unwrap().contains(&origin(url))
+  }
+}
+
+/// The scheme, host and port of `url`, or the whole URL if it cannot be parsed.
+pub(crate) fn origin(url: &str) -> String {
+  // 不使用 Url::origin，它对 s3:// 等非特殊协议返回不透明的来源
+  match Url::parse(url) {
+    Ok(parsed) => match (parsed.host_str(), parsed.port_or_known_default()) {
+      (Some(host), Some(port)) => format!("{}://{host}:{port}", parsed.scheme()),
+      (Some(host), None) => format!("{}://{host}", parsed.scheme()),
+      (None, _) => url.to_string(),
+    },
+    Err(_) => url.to_string(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_poisoned_mirrors() {
+    let poisoned = PoisonedMirrors::default();
+    poisoned.poison("https://bad.example.com/a.zip");
+
+    assert!(poisoned.is_poisoned("https://bad.example.com:443/other/b.zip"));
+    assert!(!poisoned.is_poisoned("http://bad.example.com/a.zip"));
+    assert!(!poisoned.is_poisoned("https://good.example.com/a.zip"));
+
+    poisoned.poison("s3://bucket/a.zip");
+    assert!(poisoned.is_poisoned("s3://bucket/b.zip"));
+    assert!(!poisoned.is_poisoned("s3://other/a.zip"));
+  }
+}
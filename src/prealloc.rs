@@ -0,0 +1,48 @@
+use std::io;
+
+use tokio::fs::File;
+
+/// Reserves disk space for the first `len` bytes of `file`, so a full disk fails the
+/// download before any data is transferred and the file is stored without fragments.
+///
+/// With `keep_size` the length of the file is left unchanged, so the size of a partial
+/// download still tells how much of it was written. Space is only reserved on Linux,
+/// elsewhere and on file systems without `fallocate` this does nothing.
+pub(crate) async fn preallocate(file: &File, len: u64, keep_size: bool) -> io::Result<()> {
+  #[cfg(target_os = "linux")]
+  {
+    use std::os::fd::AsRawFd;
+
+    let file = file.try_clone().await?.into_std().await;
+    let Ok(len) = libc::off_t::try_from(len) else {
+      return Ok(());
+    };
+    let mode = if keep_size {
+      libc::FALLOC_FL_KEEP_SIZE
+    } else {
+      0
+    };
+
+    tokio::task::spawn_blocking(move || {
+      // SAFETY: 文件描述符在 file 被释放前一直有效
+      let result = unsafe { libc::fallocate(file.as_raw_fd(), mode, 0, len) };
+      if result == 0 {
+        return Ok(());
+      }
+      let err = io::Error::last_os_error();
+      match err.raw_os_error() {
+        // 文件系统不支持预分配时照常下载
+        Some(libc::EOPNOTSUPP | libc::ENOSYS) => Ok(()),
+        _ => Err(err),
+      }
+    })
+    .await
+    .map_err(io::Error::other)?
+  }
+
+  #[cfg(not(target_os = "linux"))]
+  {
+    let _ = (file, len, keep_size);
+    Ok(())
+  }
+}
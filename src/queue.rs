@@ -0,0 +1,213 @@
+use std::{cmp::Ordering, collections::BinaryHeap, path::Path, sync::Mutex};
+
+use reqwest::IntoUrl;
+use tokio::sync::Notify;
+
+use crate::{DownloadItem, DownloadResult, RobustDownloader, batch::Batch};
+
+/// A download queue that starts higher-priority items first and accepts new items
+/// while downloads are running.
+///
+/// Created with [`RobustDownloader::queue`]. Items are downloaded by
+/// [`run`](Self::run) with at most `max_concurrent` downloads at a time. Items with the
+/// same priority start in the order they were pushed.
+///
+/// # Example
+///
+/// ```rust
+/// use robust_downloader::{DownloadItem, RobustDownloader};
+/// async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let downloader = RobustDownloader::builder().build();
+/// let queue = downloader.queue()?;
+///
+/// queue.push(
+///   DownloadItem::builder()
+///     .url("https://example.com/large.iso")
+///     .target("local/large.iso")
+///     .build(),
+///   0,
+/// );
+///
+/// let (results, ()) = tokio::join!(queue.run(), async {
+///   // 运行期间仍然可以加入新的下载项
+///   queue.push(
+///     DownloadItem::builder()
+///       .url("https://example.com/urgent.txt")
+///       .target("local/urgent.txt")
+///       .build(),
+///     10,
+///   );
+///   queue.close();
+/// });
+/// assert_eq!(results.len(), 2);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DownloadQueue<U, P> {
+  downloader: RobustDownloader,
+  batch: Batch,
+  state: Mutex<QueueState<U, P>>,
+  /// 有新的下载项或队列关闭时通知空闲的 worker
+  notify: Notify,
+}
+
+#[derive(Debug)]
+struct QueueState<U, P> {
+  pending: BinaryHeap<Queued<U, P>>,
+  /// 已加入队列的下载项数量，用作下载项的序号
+  pushed: usize,
+  closed: bool,
+}
+
+#[derive(Debug)]
+struct Queued<U, P> {
+  priority: i32,
+  index: usize,
+  item: DownloadItem<U, P>,
+}
+
+impl<U, P> PartialEq for Queued<U, P> {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl<U, P> Eq for Queued<U, P> {}
+
+impl<U, P> PartialOrd for Queued<U, P> {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl<U, P> Ord for Queued<U, P> {
+  fn cmp(&self, other: &Self) -> Ordering {
+    // 优先级高的先出队，优先级相同时先加入的先出队
+    self
+      .priority
+      .cmp(&other.priority)
+      .then_with(|| other.index.cmp(&self.index))
+  }
+}
+
+impl<U, P> DownloadQueue<U, P>
+where
+  U: IntoUrl + Clone,
+  P: AsRef<Path>,
+{
+  pub(crate) fn new(downloader: RobustDownloader, batch: Batch) -> Self {
+    Self {
+      downloader,
+      batch,
+      state: Mutex::new(QueueState {
+        pending: BinaryHeap::new(),
+        pushed: 0,
+        closed: false,
+      }),
+      notify: Notify::new(),
+    }
+  }
+
+  /// Adds an item to the queue. Items with a higher `priority` start first.
+  ///
+  /// Items pushed after the queue has been [closed](Self::close) are still downloaded
+  /// if `run` has not returned yet.
+  pub fn push(&self, item: DownloadItem<U, P>, priority: i32) {
+    let mut state = self.state.lock().unwrap();
+    let index = state.pushed;
+    state.pushed += 1;
+    state.pending.push(Queued {
+      priority,
+      index,
+      item,
+    });
+    drop(state);
+
+    self.notify.notify_one();
+  }
+
+  /// Number of items waiting to be started.
+  pub fn len(&self) -> usize {
+    self.state.lock().unwrap().pending.len()
+  }
+
+  /// Returns `true` if no item is waiting to be started.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Signals that no more items will be pushed, so [`run`](Self::run) returns once the
+  /// queue is drained.
+  pub fn close(&self) {
+    self.state.lock().unwrap().closed = true;
+    self.notify.notify_waiters();
+  }
+
+  /// Downloads queued items until the queue is closed and empty.
+  ///
+  /// # Returns
+  ///
+  /// Returns one [`DownloadResult`] per item, in the order the items were pushed.
+  pub async fn run(&self) -> Vec<DownloadResult> {
+    let workers = (0..self.downloader.max_concurrent.max(1)).map(|_| self.worker());
+
+    let mut results = futures::future::join_all(workers)
+      .await
+      .into_iter()
+      .flatten()
+      .collect::<Vec<_>>();
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+  }
+
+  async fn worker(&self) -> Vec<(usize, DownloadResult)> {
+    let mut results = Vec::new();
+
+    while let Some(Queued { index, item, .. }) = self.next().await {
+      let result = self.downloader.run(&self.batch, index, item).await;
+      results.push((index, result));
+    }
+
+    results
+  }
+
+  /// Waits for the next item to download, or `None` once the queue is closed and empty.
+  async fn next(&self) -> Option<Queued<U, P>> {
+    loop {
+      // 先注册通知再检查队列，避免错过检查之后到达的通知
+      let notified = self.notify.notified();
+
+      {
+        let mut state = self.state.lock().unwrap();
+        if let Some(queued) = state.pending.pop() {
+          return Some(queued);
+        }
+        if state.closed {
+          return None;
+        }
+      }
+
+      notified.await;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_priority_order() {
+    let queued = |priority, index| Queued {
+      priority,
+      index,
+      item: DownloadItem::builder().url("").target("").build(),
+    };
+
+    let mut heap = BinaryHeap::from([queued(0, 0), queued(5, 1), queued(0, 2), queued(5, 3)]);
+    let order = std::iter::from_fn(|| heap.pop().map(|queued| queued.index)).collect::<Vec<_>>();
+    assert_eq!(order, [1, 3, 0, 2]);
+  }
+}
@@ -0,0 +1,128 @@
+use reqwest::{
+  Client, RequestBuilder, Response, StatusCode,
+  header::{AUTHORIZATION, COOKIE, LOCATION, PROXY_AUTHORIZATION},
+};
+use typed_builder::TypedBuilder;
+
+use crate::err::ProgressDownloadError;
+
+/// Controls how HTTP redirects are followed.
+///
+/// The URL a file was finally downloaded from is reported in
+/// [`DownloadResult::final_url`](crate::DownloadResult::final_url).
+///
+/// # Example
+///
+/// ```rust
+/// use robust_downloader::{RedirectPolicy, RobustDownloader};
+///
+/// let downloader = RobustDownloader::builder()
+///   .redirect_policy(
+///     RedirectPolicy::builder()
+///       .max_redirects(3)
+///       .allow_cross_origin(false)
+///       .build(),
+///   )
+///   .build();
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct RedirectPolicy {
+  /// Maximum number of redirects followed per request, `0` to fail on any redirect.
+  /// Defaults to 10.
+  #[builder(default = 10)]
+  pub max_redirects: usize,
+
+  /// Whether to follow redirects to another scheme, host or port.
+  /// Defaults to true.
+  #[builder(default = true)]
+  pub allow_cross_origin: bool,
+
+  /// Whether to remove the `Authorization`, `Proxy-Authorization` and `Cookie` headers
+  /// when following a redirect to another origin.
+  /// Defaults to true.
+  #[builder(default = true)]
+  pub strip_auth: bool,
+}
+
+impl Default for RedirectPolicy {
+  fn default() -> Self {
+    Self::builder().build()
+  }
+}
+
+impl RedirectPolicy {
+  /// A policy that fails on any redirect.
+  pub fn none() -> Self {
+    Self::builder().max_redirects(0).build()
+  }
+
+  /// Sends the request, following redirects according to this policy.
+  ///
+  /// The client must be built with redirects disabled.
+  pub(crate) async fn send(
+    &self,
+    client: &Client,
+    request: RequestBuilder,
+  ) -> Result<Response, ProgressDownloadError> {
+    let mut request = request.build()?;
+    let mut redirects = 0;
+
+    loop {
+      // 请求没有流式请求体，总能复制
+      let next = request.try_clone();
+      let response = client.execute(request).await?;
+
+      let is_redirect = matches!(
+        response.status(),
+        StatusCode::MOVED_PERMANENTLY
+          | StatusCode::FOUND
+          | StatusCode::SEE_OTHER
+          | StatusCode::TEMPORARY_REDIRECT
+          | StatusCode::PERMANENT_REDIRECT
+      );
+      let location = response
+        .headers()
+        .get(LOCATION)
+        .and_then(|value| value.to_str().ok());
+
+      let (true, Some(location), Some(mut next)) = (is_redirect, location, next) else {
+        return Ok(response);
+      };
+
+      let url = response
+        .url()
+        .join(location)
+        .map_err(|err| redirect_error(location, err.to_string()))?;
+
+      if redirects >= self.max_redirects {
+        return Err(redirect_error(url.as_str(), "too many redirects"));
+      }
+
+      let cross_origin = url.origin() != response.url().origin();
+      if cross_origin && !self.allow_cross_origin {
+        return Err(redirect_error(
+          url.as_str(),
+          "cross-origin redirect not allowed",
+        ));
+      }
+      if cross_origin && self.strip_auth {
+        let headers = next.headers_mut();
+        headers.remove(AUTHORIZATION);
+        headers.remove(PROXY_AUTHORIZATION);
+        headers.remove(COOKIE);
+      }
+
+      tracing::debug!(from = %response.url(), to = %url, "following redirect");
+      *next.url_mut() = url;
+      redirects += 1;
+      request = next;
+    }
+  }
+}
+
+fn redirect_error(url: &str, message: impl Into<String>) -> ProgressDownloadError {
+  ProgressDownloadError::Redirect {
+    url: url.to_string(),
+    message: message.into(),
+  }
+}
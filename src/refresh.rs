@@ -0,0 +1,49 @@
+use std::{fmt, future::Future};
+
+use futures::{FutureExt, future::BoxFuture};
+
+/// Issues a fresh URL when the current one is no longer accepted, see the
+/// `url_provider` option.
+///
+/// Called with the URL that was answered with `401 Unauthorized` or `403 Forbidden`,
+/// e.g. a presigned URL that has expired, before the download is attempted again with
+/// the returned URL. The download continues with the data received so far. Closures
+/// taking the URL and returning a future implement this trait.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use robust_downloader::RobustDownloader;
+///
+/// async fn presign(url: &str) -> Result<String, String> {
+///   // 向签名服务请求新的 URL
+///   Ok(format!("{}?signature=fresh", url.split('?').next().unwrap_or(url)))
+/// }
+///
+/// let downloader = RobustDownloader::builder()
+///   .url_provider(Arc::new(|url: String| async move { presign(&url).await }))
+///   .build();
+/// ```
+pub trait UrlProvider: Send + Sync {
+  /// Returns the URL to use instead of `url`, or why none could be issued, failing
+  /// the download with the original error.
+  fn refresh(&self, url: String) -> BoxFuture<'static, Result<String, String>>;
+}
+
+impl<F, Fut> UrlProvider for F
+where
+  F: Fn(String) -> Fut + Send + Sync,
+  Fut: Future<Output = Result<String, String>> + Send + 'static,
+{
+  fn refresh(&self, url: String) -> BoxFuture<'static, Result<String, String>> {
+    self(url).boxed()
+  }
+}
+
+impl fmt::Debug for dyn UrlProvider + '_ {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("UrlProvider")
+  }
+}
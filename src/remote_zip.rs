@@ -0,0 +1,312 @@
+use std::io::{self, Write};
+
+use bytes::Bytes;
+
+use crate::transform::ChunkTransform;
+
+/// Bytes requested from the end of an archive to find its central directory: the end
+/// record, a comment of up to 64 KiB and the ZIP64 locator before it.
+pub(crate) const ZIP_TAIL_LEN: u64 = 22 + 0xffff + 20;
+
+/// Length of the end of central directory record without its comment.
+const END_RECORD_LEN: usize = 22;
+
+/// Length of the fixed part of a local file header, before the name and extra field.
+pub(crate) const LOCAL_HEADER_LEN: u64 = 30;
+
+/// Location of the central directory of a ZIP archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Directory {
+  /// Offset and size of the central directory.
+  Found { offset: u64, size: u64 },
+  /// The archive is a ZIP64 archive whose end record starts at this offset.
+  Zip64(u64),
+}
+
+/// A member of a ZIP archive, as listed in its central directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ZipMember {
+  /// 本地文件头的偏移，数据在文件头之后
+  pub header_offset: u64,
+  pub compressed_size: u64,
+  pub size: u64,
+  pub crc32: u32,
+  pub deflated: bool,
+}
+
+/// Finds the central directory in `tail`, the last bytes of an archive of `archive_size`
+/// bytes.
+pub(crate) fn find_directory(tail: &[u8], archive_size: u64) -> Result<Directory, String> {
+  if tail.len() < END_RECORD_LEN {
+    return Err("not a ZIP archive".to_string());
+  }
+  // 从后向前查找结束记录，注释中也可能出现相同的签名
+  let end = (0..=tail.len() - END_RECORD_LEN)
+    .rev()
+    .find(|&index| {
+      tail[index..].starts_with(&[0x50, 0x4b, 0x05, 0x06])
+        && index + END_RECORD_LEN + u16_at(tail, index + 20) as usize == tail.len()
+    })
+    .ok_or("not a ZIP archive")?;
+
+  let size = u32_at(tail, end + 12);
+  let offset = u32_at(tail, end + 16);
+  if size != u32::MAX && offset != u32::MAX {
+    let (size, offset) = (size as u64, offset as u64);
+    if offset + size > archive_size {
+      return Err("central directory lies outside the archive".to_string());
+    }
+    return Ok(Directory::Found { offset, size });
+  }
+
+  let locator = end
+    .checked_sub(20)
+    .filter(|&locator| tail[locator..].starts_with(&[0x50, 0x4b, 0x06, 0x07]))
+    .ok_or("ZIP64 end of central directory locator not found")?;
+  Ok(Directory::Zip64(u64_at(tail, locator + 8)))
+}
+
+/// Reads offset and size of the central directory from a ZIP64 end record.
+pub(crate) fn parse_zip64_end(record: &[u8]) -> Result<(u64, u64), String> {
+  if record.len() < 56 || !record.starts_with(&[0x50, 0x4b, 0x06, 0x06]) {
+    return Err("invalid ZIP64 end of central directory record".to_string());
+  }
+  Ok((u64_at(record, 48), u64_at(record, 40)))
+}
+
+/// Finds the member at `path` in the central directory `directory`.
+pub(crate) fn find_member(directory: &[u8], path: &str) -> Result<ZipMember, String> {
+  let mut index = 0;
+  while index + 46 <= directory.len() && directory[index..].starts_with(&[0x50, 0x4b, 0x01, 0x02]) {
+    let name_len = u16_at(directory, index + 28) as usize;
+    let extra_len = u16_at(directory, index + 30) as usize;
+    let comment_len = u16_at(directory, index + 32) as usize;
+    let next = index + 46 + name_len + extra_len + comment_len;
+    if next > directory.len() {
+      break;
+    }
+
+    let name = &directory[index + 46..index + 46 + name_len];
+    if name != path.as_bytes() {
+      index = next;
+      continue;
+    }
+
+    if u16_at(directory, index + 8) & 1 != 0 {
+      return Err(format!("{path} is encrypted"));
+    }
+    let deflated = match u16_at(directory, index + 10) {
+      0 => false,
+      8 => true,
+      method => {
+        return Err(format!(
+          "{path} uses unsupported compression method {method}"
+        ));
+      }
+    };
+
+    let mut size = u32_at(directory, index + 24) as u64;
+    let mut compressed_size = u32_at(directory, index + 20) as u64;
+    let mut header_offset = u32_at(directory, index + 42) as u64;
+    // 超出 32 位的值记录在 ZIP64 扩展字段中，按固定顺序只包含溢出的字段
+    let extra = &directory[index + 46 + name_len..index + 46 + name_len + extra_len];
+    if let Some(mut zip64) = zip64_field(extra) {
+      for value in [&mut size, &mut compressed_size, &mut header_offset] {
+        if *value == u32::MAX as u64 {
+          if zip64.len() < 8 {
+            return Err(format!("{path} has an invalid ZIP64 extra field"));
+          }
+          *value = u64_at(zip64, 0);
+          zip64 = &zip64[8..];
+        }
+      }
+    }
+
+    return Ok(ZipMember {
+      header_offset,
+      compressed_size,
+      size,
+      crc32: u32_at(directory, index + 16),
+      deflated,
+    });
+  }
+  Err(format!("{path} not found in the archive"))
+}
+
+/// Reads the length of the name and extra field from a local file header, which the
+/// data of the member follows.
+pub(crate) fn local_header_len(header: &[u8]) -> Result<u64, String> {
+  if header.len() < LOCAL_HEADER_LEN as usize || !header.starts_with(&[0x50, 0x4b, 0x03, 0x04]) {
+    return Err("invalid local file header".to_string());
+  }
+  Ok(LOCAL_HEADER_LEN + u16_at(header, 26) as u64 + u16_at(header, 28) as u64)
+}
+
+fn zip64_field(mut extra: &[u8]) -> Option<&[u8]> {
+  while extra.len() >= 4 {
+    let len = (u16_at(extra, 2) as usize).min(extra.len() - 4);
+    if u16_at(extra, 0) == 0x0001 {
+      return Some(&extra[4..4 + len]);
+    }
+    extra = &extra[4 + len..];
+  }
+  None
+}
+
+fn u16_at(data: &[u8], index: usize) -> u16 {
+  u16::from_le_bytes([data[index], data[index + 1]])
+}
+
+fn u32_at(data: &[u8], index: usize) -> u32 {
+  u32::from_le_bytes(data[index..index + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], index: usize) -> u64 {
+  u64::from_le_bytes(data[index..index + 8].try_into().unwrap())
+}
+
+/// Decompresses the data of a ZIP member while it downloads and checks its CRC-32 and
+/// size once it ends.
+pub(crate) struct MemberReader {
+  inflater: Option<flate2::write::DeflateDecoder<Vec<u8>>>,
+  crc32: crc32fast::Hasher,
+  size: u64,
+  member: ZipMember,
+}
+
+impl MemberReader {
+  pub fn new(member: ZipMember) -> Self {
+    Self {
+      inflater: member
+        .deflated
+        .then(|| flate2::write::DeflateDecoder::new(Vec::new())),
+      crc32: crc32fast::Hasher::new(),
+      size: 0,
+      member,
+    }
+  }
+
+  fn output(&mut self, data: Bytes) -> Bytes {
+    self.crc32.update(&data);
+    self.size += data.len() as u64;
+    data
+  }
+}
+
+impl ChunkTransform for MemberReader {
+  fn transform(&mut self, chunk: Bytes) -> io::Result<Bytes> {
+    let data = match &mut self.inflater {
+      Some(inflater) => {
+        inflater.write_all(&chunk)?;
+        std::mem::take(inflater.get_mut()).into()
+      }
+      None => chunk,
+    };
+    Ok(self.output(data))
+  }
+
+  fn finish(&mut self) -> io::Result<Bytes> {
+    let rest = match self.inflater.take() {
+      Some(inflater) => inflater.finish()?.into(),
+      None => Bytes::new(),
+    };
+    let rest = self.output(rest);
+
+    let crc32 = std::mem::take(&mut self.crc32).finalize();
+    if self.size != self.member.size || crc32 != self.member.crc32 {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "ZIP member does not match its size and CRC-32",
+      ));
+    }
+    Ok(rest)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// 包含一个存储的成员 `a.txt` 和一个压缩的成员 `dir/b.txt` 的归档
+  fn archive() -> Vec<u8> {
+    let mut zip = Vec::new();
+    let mut directory = Vec::new();
+    let members: [(&str, &[u8], bool); 2] = [
+      ("a.txt", b"hello", false),
+      ("dir/b.txt", b"world world world", true),
+    ];
+    for (name, content, deflated) in members {
+      let data = match deflated {
+        true => {
+          let mut encoder =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+          encoder.write_all(content).unwrap();
+          encoder.finish().unwrap()
+        }
+        false => content.to_vec(),
+      };
+      let mut fields = Vec::new();
+      fields.extend_from_slice(&[20, 0, 0, 0]);
+      fields.extend_from_slice(&(if deflated { 8u16 } else { 0 }).to_le_bytes());
+      fields.extend_from_slice(&[0; 4]);
+      fields.extend_from_slice(&crc32fast::hash(content).to_le_bytes());
+      fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+      fields.extend_from_slice(&(content.len() as u32).to_le_bytes());
+      fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+      fields.extend_from_slice(&[0, 0]);
+
+      let offset = zip.len() as u32;
+      zip.extend_from_slice(&[0x50, 0x4b, 0x03, 0x04]);
+      zip.extend_from_slice(&fields);
+      zip.extend_from_slice(name.as_bytes());
+      zip.extend_from_slice(&data);
+
+      directory.extend_from_slice(&[0x50, 0x4b, 0x01, 0x02, 20, 0]);
+      directory.extend_from_slice(&fields);
+      directory.extend_from_slice(&[0; 10]);
+      directory.extend_from_slice(&offset.to_le_bytes());
+      directory.extend_from_slice(name.as_bytes());
+    }
+
+    let offset = zip.len() as u32;
+    zip.extend_from_slice(&directory);
+    zip.extend_from_slice(&[0x50, 0x4b, 0x05, 0x06, 0, 0, 0, 0, 2, 0, 2, 0]);
+    zip.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    zip.extend_from_slice(&offset.to_le_bytes());
+    zip.extend_from_slice(&[0, 0]);
+    zip
+  }
+
+  #[test]
+  fn test_find_member() {
+    let zip = archive();
+    let Directory::Found { offset, size } = find_directory(&zip, zip.len() as u64).unwrap() else {
+      panic!("not a ZIP64 archive");
+    };
+    let directory = &zip[offset as usize..(offset + size) as usize];
+
+    for (name, content) in [("a.txt", "hello"), ("dir/b.txt", "world world world")] {
+      let member = find_member(directory, name).unwrap();
+      let start = member.header_offset as usize;
+      let data_start = start + local_header_len(&zip[start..]).unwrap() as usize;
+      let data = &zip[data_start..data_start + member.compressed_size as usize];
+
+      let mut reader = MemberReader::new(member);
+      let mut output = Vec::new();
+      for chunk in data.chunks(3) {
+        output.extend_from_slice(&reader.transform(chunk.to_vec().into()).unwrap());
+      }
+      output.extend_from_slice(&reader.finish().unwrap());
+      assert_eq!(output, content.as_bytes());
+    }
+
+    assert!(find_member(directory, "b.txt").is_err());
+    assert!(find_directory(b"not a zip archive at all", 24).is_err());
+    assert!(find_directory(b"PK\x05\x06", 4).is_err());
+
+    let member = find_member(directory, "a.txt").unwrap();
+    let mut reader = MemberReader::new(member);
+    reader.transform("hellp".into()).unwrap();
+    assert!(reader.finish().is_err());
+  }
+}
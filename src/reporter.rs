@@ -0,0 +1,804 @@
+use std::{
+  collections::HashMap,
+  fmt,
+  io::IsTerminal,
+  path::PathBuf,
+  sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+  },
+  time::{Duration, Instant},
+};
+
+use indicatif::{
+  HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle,
+};
+use typed_builder::TypedBuilder;
+
+use crate::{
+  err::ProgressDownloadError,
+  filename,
+  jsonl::JsonLinesReporter,
+  messages::{DefaultMessages, ProgressMessages},
+  stream,
+};
+
+/// Identifies the download an event belongs to.
+#[derive(Debug, Clone)]
+pub struct DownloadInfo {
+  /// Position of the item in the batch passed to `download`.
+  pub index: usize,
+  /// The URL being downloaded.
+  pub url: String,
+  /// The local path the file will be saved to, empty when downloading into a writer.
+  pub target: PathBuf,
+}
+
+/// A retry that was scheduled after a failed attempt, see
+/// [`ProgressReporter::on_retry_scheduled`].
+#[derive(Debug, Clone, Copy)]
+pub struct RetryAttempt<'a> {
+  /// Number of the retry, starting at 1. Counted again from 1 after an attempt that
+  /// received data if the [`RetryPolicy`](crate::RetryPolicy) resets on progress.
+  pub retry: usize,
+  /// Number of retries allowed by the `max_attempts` of the retry policy, `None` if
+  /// unlimited.
+  pub max_retries: Option<usize>,
+  /// The error the attempt failed with.
+  pub error: &'a ProgressDownloadError,
+  /// Time until the next attempt, at least as long as the server asked for with
+  /// `Retry-After`.
+  pub delay: Duration,
+}
+
+/// Receives lifecycle and progress events of every download.
+///
+/// All methods have empty default implementations, so implementors only need to
+/// override the events they care about.
+///
+/// # Example
+///
+/// ```rust
+/// use robust_downloader::{DownloadInfo, ProgressReporter};
+///
+/// struct LogReporter;
+///
+/// impl ProgressReporter for LogReporter {
+///   fn on_finished(&self, info: &DownloadInfo) {
+///     println!("{} saved to {}", info.url, info.target.display());
+///   }
+/// }
+/// ```
+pub trait ProgressReporter: Send + Sync {
+  /// Called once before the downloads of a batch start, with the number of files and
+  /// their combined size if it is known, see `preflight`.
+  fn on_batch_started(&self, _files: usize, _total_size: Option<u64>) {}
+
+  /// Called when a download attempt starts, with the bytes already present locally
+  /// and the total size if the server reported it.
+  fn on_started(&self, _info: &DownloadInfo, _downloaded: u64, _total: Option<u64>) {}
+
+  /// Called whenever a chunk of data has been received.
+  fn on_bytes_received(&self, _info: &DownloadInfo, _downloaded: u64, _total: Option<u64>) {}
+
+  /// Called whenever decompressed data of a response sent with a `Content-Encoding` has
+  /// been written. `on_bytes_received` counts the compressed bytes in that case.
+  fn on_bytes_decoded(&self, _info: &DownloadInfo, _decoded: u64) {}
+
+  /// Called periodically during BitTorrent downloads with the number of connected peers.
+  fn on_peers(&self, _info: &DownloadInfo, _peers: usize) {}
+
+  /// Called when an attempt failed with a transient error and will be retried after `delay`,
+  /// which is at least as long as the server asked for with `Retry-After`.
+  fn on_retrying(&self, _info: &DownloadInfo, _error: &ProgressDownloadError, _delay: Duration) {}
+
+  /// Called instead of `on_retrying` when an attempt will be retried, with the number
+  /// of the retry. Calls `on_retrying` by default.
+  fn on_retry_scheduled(&self, info: &DownloadInfo, retry: &RetryAttempt<'_>) {
+    self.on_retrying(info, retry.error, retry.delay);
+  }
+
+  /// Called while a downloaded archive is unpacked, with the archive bytes processed so far.
+  fn on_extracting(&self, _info: &DownloadInfo, _extracted: u64, _total: u64) {}
+
+  /// Called once the file has been saved to its target path.
+  fn on_finished(&self, _info: &DownloadInfo) {}
+
+  /// Called instead of any other event when the target file is already up to date.
+  fn on_skipped(&self, _info: &DownloadInfo) {}
+
+  /// Called when the download failed permanently.
+  fn on_failed(&self, _info: &DownloadInfo, _error: &ProgressDownloadError) {}
+}
+
+impl fmt::Debug for dyn ProgressReporter + '_ {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("ProgressReporter")
+  }
+}
+
+/// How much progress the default reporter of a
+/// [`RobustDownloader`](crate::RobustDownloader) shows, see its `progress_mode` option.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProgressMode {
+  /// Draws and prints nothing.
+  Silent,
+  /// Draws a single bar with the combined progress of all files, or prints a line per
+  /// completed file when stdout is not a terminal.
+  Minimal,
+  /// Draws one bar per download plus an overall bar for batches, or prints the progress
+  /// of each download as a line of text when stdout is not a terminal.
+  #[default]
+  Full,
+  /// Prints JSON lines with a [`JsonLinesReporter`], whether or not stdout is a terminal.
+  JsonLines,
+}
+
+/// 什么都不报告
+struct SilentReporter;
+
+impl ProgressReporter for SilentReporter {}
+
+/// 终端下显示进度条，否则由进度条报告器输出文本行
+pub(crate) fn default_reporter(mode: ProgressMode) -> Arc<dyn ProgressReporter> {
+  match mode {
+    ProgressMode::Silent => Arc::new(SilentReporter),
+    ProgressMode::Minimal => Arc::new(ProgressBarReporter::new(
+      ProgressBarOptions::builder().file_bars(false).build(),
+    )),
+    ProgressMode::Full => Arc::new(ProgressBarReporter::default()),
+    ProgressMode::JsonLines => Arc::new(JsonLinesReporter::default()),
+  }
+}
+
+/// Where a [`ProgressBarReporter`] draws its bars.
+///
+/// While a download streams to stdout, see [`DownloadItem::target`](crate::DownloadItem::target),
+/// output meant for stdout goes to stderr so it does not mix with the data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressOutput {
+  Stdout,
+  Stderr,
+  /// Draws nothing.
+  Hidden,
+}
+
+impl ProgressOutput {
+  fn draw_target(self) -> ProgressDrawTarget {
+    match self {
+      Self::Stdout => ProgressDrawTarget::stdout(),
+      Self::Stderr => ProgressDrawTarget::stderr(),
+      Self::Hidden => ProgressDrawTarget::hidden(),
+    }
+  }
+
+  /// 下载内容写入标准输出时改为输出到标准错误
+  fn resolve(self) -> Self {
+    match self {
+      Self::Stdout if stream::stdout_taken() => Self::Stderr,
+      output => output,
+    }
+  }
+
+  fn is_terminal(self) -> bool {
+    match self {
+      Self::Stdout => std::io::stdout().is_terminal(),
+      Self::Stderr => std::io::stderr().is_terminal(),
+      Self::Hidden => false,
+    }
+  }
+}
+
+/// Options of a [`ProgressBarReporter`].
+///
+/// Besides the keys built into indicatif, such as `{binary_bytes_per_sec}`, `{eta}`,
+/// `{percent}` and `{msg}`, the templates of download bars can use:
+///
+/// - `{filename}`: the file name of the target, or the last segment of the URL
+/// - `{url}`: the URL being downloaded
+/// - `{attempt}`: the number of the current attempt, starting at 1
+/// - `{speed}`: the current speed, e.g. `1.50 MiB/s`
+/// - `{encoded_bytes}`: the bytes received, compressed if the response was sent with a
+///   `Content-Encoding`; the same as `{bytes}`, which the bar and `{total_bytes}` count
+/// - `{decoded_bytes}`: the bytes written to the file, decompressed if the response was
+///   sent with a `Content-Encoding` and the same as `{bytes}` otherwise
+///
+/// The overall bar of a batch shows an empty string for these keys.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use robust_downloader::{
+///   ProgressBarOptions, ProgressBarReporter, ProgressOutput, ProgressStyle, RobustDownloader,
+/// };
+///
+/// let style = ProgressStyle::with_template(
+///   "{filename:20} {bar:40.cyan/blue} {bytes}/{total_bytes} {speed} eta {eta} #{attempt}",
+/// )
+/// .unwrap()
+///   .progress_chars("=> ");
+/// let reporter = ProgressBarReporter::new(
+///   ProgressBarOptions::builder()
+///     .style(style)
+///     .output(ProgressOutput::Stderr)
+///     .build(),
+/// );
+/// let downloader = RobustDownloader::builder()
+///   .reporter(Arc::new(reporter))
+///   .build();
+/// ```
+#[derive(Clone, TypedBuilder)]
+pub struct ProgressBarOptions {
+  /// Style of every bar, including the overall bar of a batch.
+  /// Defaults to a green spinner, the elapsed time, a 25 characters wide bar, the
+  /// downloaded and total bytes and a status message.
+  #[builder(default = default_style())]
+  pub style: ProgressStyle,
+
+  /// Style of the bars of downloads whose size the server did not report, shown until
+  /// the download has ended.
+  /// Defaults to a green spinner, the elapsed time, the downloaded bytes, the speed and
+  /// a status message.
+  #[builder(default = default_spinner_style())]
+  pub spinner_style: ProgressStyle,
+
+  /// Where the bars are drawn.
+  /// Defaults to stdout.
+  #[builder(default = ProgressOutput::Stdout)]
+  pub output: ProgressOutput,
+
+  /// How often the progress of each download is printed as text when `output` is not a
+  /// terminal.
+  /// Defaults to 10 seconds.
+  #[builder(default = Duration::from_secs(10))]
+  pub text_interval: Duration,
+
+  /// Whether every download gets its own bar. When disabled only the overall bar is
+  /// drawn, also for a single file, and without a terminal only a line per completed
+  /// file is printed.
+  /// Defaults to true.
+  #[builder(default = true)]
+  pub file_bars: bool,
+
+  /// The status messages of the bars and the lines printed without a terminal, to
+  /// localize or re-brand them.
+  /// Defaults to [`DefaultMessages`], in English.
+  #[builder(default = Arc::new(DefaultMessages))]
+  pub messages: Arc<dyn ProgressMessages>,
+}
+
+impl Default for ProgressBarOptions {
+  fn default() -> Self {
+    Self::builder().build()
+  }
+}
+
+impl fmt::Debug for ProgressBarOptions {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ProgressBarOptions")
+      .field("output", &self.output)
+      .field("text_interval", &self.text_interval)
+      .field("file_bars", &self.file_bars)
+      .field("messages", &self.messages)
+      .finish_non_exhaustive()
+  }
+}
+
+fn default_style() -> ProgressStyle {
+  ProgressStyle::with_template(
+    "{spinner:.green} [{elapsed_precise}] {bar:25.green/white.dim} {bytes}/{total_bytes} {wide_msg:.dim}",
+  )
+  .unwrap()
+  .progress_chars("━━")
+}
+
+fn default_spinner_style() -> ProgressStyle {
+  ProgressStyle::with_template(
+    "{spinner:.green} [{elapsed_precise}] {bytes} {binary_bytes_per_sec} {wide_msg:.dim}",
+  )
+  .unwrap()
+}
+
+/// Reports progress with one indicatif progress bar per download.
+///
+/// Batches of more than one file additionally get an overall bar with the number of
+/// completed files. This is the default reporter of [`RobustDownloader`](crate::RobustDownloader).
+///
+/// When the output is not a terminal, e.g. when it is piped to a file, no bars are
+/// drawn. The progress of each download is printed as a line of text every 10 seconds
+/// instead, see [`ProgressBarOptions::text_interval`].
+pub struct ProgressBarReporter {
+  multi: MultiProgress,
+  style: ProgressStyle,
+  spinner_style: ProgressStyle,
+  bars: Mutex<HashMap<usize, DownloadBar>>,
+  total: Mutex<Option<TotalBar>>,
+  output: ProgressOutput,
+  /// 进度条已因标准输出被下载内容占用而改为绘制到标准错误
+  moved_to_stderr: AtomicBool,
+  /// 输出不是终端时改为定期输出文本
+  text: Option<TextProgress>,
+  /// 为每个下载单独显示进度条
+  file_bars: bool,
+  messages: Arc<dyn ProgressMessages>,
+}
+
+/// 单个下载的进度条，样式带有该下载的模板变量
+struct DownloadBar {
+  bar: ProgressBar,
+  style: ProgressStyle,
+  spinner_style: ProgressStyle,
+  keys: Arc<BarKeys>,
+}
+
+/// 模板变量用到的下载状态
+#[derive(Debug, Default)]
+struct BarKeys {
+  /// 当前是第几次尝试，从 1 开始
+  attempt: AtomicUsize,
+  /// 压缩响应解压后写入的字节数，响应未压缩时为 None
+  decoded: Mutex<Option<u64>>,
+}
+
+impl DownloadBar {
+  fn new(bar: ProgressBar, reporter: &ProgressBarReporter, info: &DownloadInfo) -> Self {
+    let keys = Arc::new(BarKeys::default());
+    let bar = Self {
+      style: with_keys(reporter.style.clone(), info, &keys),
+      spinner_style: with_keys(reporter.spinner_style.clone(), info, &keys),
+      bar,
+      keys,
+    };
+    bar.bar.set_style(bar.style.clone());
+    bar
+  }
+}
+
+impl fmt::Debug for DownloadBar {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("DownloadBar")
+      .field("bar", &self.bar)
+      .field("keys", &self.keys)
+      .finish_non_exhaustive()
+  }
+}
+
+/// 为下载的进度条样式添加 `{filename}`、`{url}`、`{attempt}`、`{speed}`、
+/// `{encoded_bytes}` 和 `{decoded_bytes}` 变量
+fn with_keys(style: ProgressStyle, info: &DownloadInfo, keys: &Arc<BarKeys>) -> ProgressStyle {
+  let name = short_name(info);
+  let url = info.url.clone();
+  let attempt = keys.clone();
+  let decoded = keys.clone();
+  // 模板变量的错误只影响显示，忽略即可
+  style
+    .with_key(
+      "filename",
+      move |_: &ProgressState, w: &mut dyn fmt::Write| {
+        let _ = w.write_str(&name);
+      },
+    )
+    .with_key("url", move |_: &ProgressState, w: &mut dyn fmt::Write| {
+      let _ = w.write_str(&url);
+    })
+    .with_key(
+      "attempt",
+      move |_: &ProgressState, w: &mut dyn fmt::Write| {
+        let _ = write!(w, "{}", attempt.attempt.load(Ordering::Relaxed).max(1));
+      },
+    )
+    .with_key("speed", |state: &ProgressState, w: &mut dyn fmt::Write| {
+      let _ = write!(w, "{}/s", HumanBytes(state.per_sec() as u64));
+    })
+    .with_key(
+      "encoded_bytes",
+      |state: &ProgressState, w: &mut dyn fmt::Write| {
+        let _ = write!(w, "{}", HumanBytes(state.pos()));
+      },
+    )
+    .with_key(
+      "decoded_bytes",
+      move |state: &ProgressState, w: &mut dyn fmt::Write| {
+        let decoded = decoded.decoded.lock().unwrap().unwrap_or(state.pos());
+        let _ = write!(w, "{}", HumanBytes(decoded));
+      },
+    )
+}
+
+/// 目标文件名，写入标准输出或写入器时取 URL 路径的最后一段
+fn short_name(info: &DownloadInfo) -> String {
+  let target = info
+    .target
+    .file_name()
+    .filter(|_| info.target.as_os_str() != "-")
+    .map(|name| name.to_string_lossy().into_owned());
+  target
+    .or_else(|| filename::from_url(&info.url))
+    .unwrap_or_else(|| info.url.clone())
+}
+
+/// 非终端下的文本进度
+#[derive(Debug)]
+struct TextProgress {
+  output: ProgressOutput,
+  interval: Duration,
+  files: Mutex<(usize, usize)>,
+  printed: Mutex<HashMap<usize, Instant>>,
+}
+
+impl TextProgress {
+  fn print(&self, line: &str) {
+    use std::io::Write;
+
+    // 管道关闭时忽略写入错误，不影响下载
+    let _ = match self.output.resolve() {
+      ProgressOutput::Stderr => writeln!(std::io::stderr().lock(), "{line}"),
+      _ => writeln!(std::io::stdout().lock(), "{line}"),
+    };
+  }
+
+  fn on_progress(&self, info: &DownloadInfo, line: impl FnOnce() -> String) {
+    let now = Instant::now();
+    {
+      let mut printed = self.printed.lock().unwrap();
+      let last = printed.entry(info.index).or_insert(now);
+      if now.duration_since(*last) < self.interval {
+        return;
+      }
+      *last = now;
+    }
+
+    self.print(&line());
+  }
+
+  fn on_completed(&self, info: &DownloadInfo, outcome: &str) {
+    self.printed.lock().unwrap().remove(&info.index);
+
+    let (completed, files) = {
+      let mut files = self.files.lock().unwrap();
+      files.0 += 1;
+      *files
+    };
+    // 多文件批次附带已完成的文件数
+    if files > 1 {
+      self.print(&format!("[{completed}/{files}] {outcome}"));
+    } else {
+      self.print(outcome);
+    }
+  }
+}
+
+impl fmt::Debug for ProgressBarReporter {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ProgressBarReporter")
+      .field("multi", &self.multi)
+      .field("bars", &self.bars)
+      .field("total", &self.total)
+      .field("text", &self.text)
+      .finish_non_exhaustive()
+  }
+}
+
+/// 整个批次的总进度条
+#[derive(Debug)]
+struct TotalBar {
+  bar: ProgressBar,
+  files: usize,
+  completed: usize,
+  /// 预检得到的总大小，未知时按各文件报告的大小累加
+  known_length: bool,
+  lengths: HashMap<usize, u64>,
+  positions: HashMap<usize, u64>,
+}
+
+impl TotalBar {
+  fn set_message(&self, messages: &dyn ProgressMessages) {
+    self
+      .bar
+      .set_message(messages.files(self.completed, self.files));
+  }
+}
+
+impl Default for ProgressBarReporter {
+  fn default() -> Self {
+    Self::new(ProgressBarOptions::default())
+  }
+}
+
+impl ProgressBarReporter {
+  pub fn new(options: ProgressBarOptions) -> Self {
+    let multi = MultiProgress::with_draw_target(options.output.draw_target());
+    multi.set_move_cursor(true);
+
+    let text =
+      (options.output != ProgressOutput::Hidden && !options.output.is_terminal()).then(|| {
+        TextProgress {
+          output: options.output,
+          interval: options.text_interval,
+          files: Mutex::new((0, 0)),
+          printed: Mutex::new(HashMap::new()),
+        }
+      });
+
+    Self {
+      multi,
+      style: options.style,
+      spinner_style: options.spinner_style,
+      bars: Mutex::new(HashMap::new()),
+      total: Mutex::new(None),
+      output: options.output,
+      moved_to_stderr: AtomicBool::new(false),
+      text,
+      file_bars: options.file_bars,
+      messages: options.messages,
+    }
+  }
+
+  /// Creates a reporter that prints the progress of each download every `interval`
+  /// when stdout is not a terminal, and draws progress bars otherwise.
+  pub fn with_text_interval(interval: Duration) -> Self {
+    Self::new(
+      ProgressBarOptions::builder()
+        .text_interval(interval)
+        .build(),
+    )
+  }
+
+  /// Draws the bars on stderr once downloaded data is written to stdout.
+  fn avoid_stdout(&self) {
+    if self.output.resolve() != self.output && !self.moved_to_stderr.swap(true, Ordering::Relaxed) {
+      self.multi.set_draw_target(ProgressDrawTarget::stderr());
+    }
+  }
+
+  /// Creates a new progress bar with the configured style, drawn by `multi` once added.
+  fn prepare_progress_bar(&self) -> ProgressBar {
+    let progress_bar = ProgressBar::with_draw_target(Some(0), ProgressDrawTarget::hidden());
+    progress_bar.set_style(self.style.clone());
+    progress_bar
+  }
+
+  /// Shows the bar of `info` as a regular progress bar of `total` bytes.
+  fn set_length(&self, info: &DownloadInfo, total: u64) {
+    let bars = self.bars.lock().unwrap();
+    if let Some(download) = bars.get(&info.index) {
+      download.bar.disable_steady_tick();
+      download.bar.set_style(download.style.clone());
+      download.bar.set_length(total);
+    }
+  }
+
+  fn with_bar<T>(&self, info: &DownloadInfo, f: impl FnOnce(&DownloadBar) -> T) -> T {
+    let mut bars = self.bars.lock().unwrap();
+    let download = bars.entry(info.index).or_insert_with(|| {
+      // 不显示单个进度条时仍记录进度，只是不绘制
+      let bar = if self.file_bars {
+        self.multi.add(self.prepare_progress_bar())
+      } else {
+        ProgressBar::hidden()
+      };
+      DownloadBar::new(bar, self, info)
+    });
+    f(download)
+  }
+
+  fn bar(&self, info: &DownloadInfo) -> ProgressBar {
+    self.with_bar(info, |download| download.bar.clone())
+  }
+
+  fn remove(&self, info: &DownloadInfo) {
+    if let Some(download) = self.bars.lock().unwrap().remove(&info.index) {
+      download.bar.finish_and_clear();
+    }
+
+    if let Some(total) = self.total.lock().unwrap().as_mut() {
+      total.completed += 1;
+      total.set_message(self.messages.as_ref());
+      if total.completed >= total.files {
+        total.bar.finish();
+      }
+    }
+  }
+
+  fn update_total(&self, info: &DownloadInfo, downloaded: u64, length: Option<u64>) {
+    let mut total = self.total.lock().unwrap();
+    let Some(total) = total.as_mut() else {
+      return;
+    };
+
+    if let (false, Some(length)) = (total.known_length, length) {
+      total.lengths.insert(info.index, length);
+      total.bar.set_length(total.lengths.values().sum());
+    }
+
+    let previous = total.positions.insert(info.index, downloaded).unwrap_or(0);
+    let position = (total.bar.position() + downloaded).saturating_sub(previous);
+    total.bar.set_position(position);
+  }
+}
+
+impl ProgressReporter for ProgressBarReporter {
+  fn on_batch_started(&self, files: usize, total_size: Option<u64>) {
+    self.avoid_stdout();
+    if let Some(text) = &self.text {
+      *text.files.lock().unwrap() = (0, files);
+      return;
+    }
+
+    if files <= 1 && self.file_bars {
+      return;
+    }
+
+    let bar = self.multi.insert(0, self.prepare_progress_bar());
+    bar.set_length(total_size.unwrap_or(0));
+
+    let total = TotalBar {
+      bar,
+      files,
+      completed: 0,
+      known_length: total_size.is_some(),
+      lengths: HashMap::new(),
+      positions: HashMap::new(),
+    };
+    total.set_message(self.messages.as_ref());
+    *self.total.lock().unwrap() = Some(total);
+  }
+
+  fn on_started(&self, info: &DownloadInfo, downloaded: u64, total: Option<u64>) {
+    self.avoid_stdout();
+    if let Some(text) = &self.text {
+      if !self.file_bars {
+        return;
+      }
+      text
+        .printed
+        .lock()
+        .unwrap()
+        .insert(info.index, Instant::now());
+      text.print(&self.messages.started(info, total));
+      return;
+    }
+
+    let bar = self.with_bar(info, |download| {
+      download.keys.attempt.fetch_add(1, Ordering::Relaxed);
+      // 压缩响应每次尝试都从头解压
+      *download.keys.decoded.lock().unwrap() = None;
+      if total.is_none() {
+        download.bar.set_style(download.spinner_style.clone());
+      }
+      download.bar.clone()
+    });
+    match total {
+      Some(total) => self.set_length(info, total),
+      // 大小未知时显示旋转指示器，下载结束后再显示总大小
+      None => {
+        bar.unset_length();
+        bar.enable_steady_tick(Duration::from_millis(100));
+        bar.set_message(self.messages.downloading(info, None));
+      }
+    }
+    bar.set_position(downloaded);
+    self.update_total(info, downloaded, total);
+  }
+
+  fn on_bytes_received(&self, info: &DownloadInfo, downloaded: u64, total: Option<u64>) {
+    if let Some(text) = &self.text {
+      if self.file_bars {
+        text.on_progress(info, || self.messages.progress(info, downloaded, total));
+      }
+      return;
+    }
+
+    let bar = self.bar(info);
+    // 大小未知的下载结束时会报告最终大小
+    match total {
+      Some(total) if bar.length().is_none() => {
+        self.set_length(info, total);
+        self.update_total(info, downloaded, Some(total));
+      }
+      _ => self.update_total(info, downloaded, None),
+    }
+    bar.set_position(downloaded);
+    if let Some(total) = total.filter(|total| *total > 0) {
+      let percentage = (downloaded as f64 / total as f64 * 100.0) as u64;
+      bar.set_message(self.messages.downloading(info, Some(percentage)));
+    }
+  }
+
+  fn on_bytes_decoded(&self, info: &DownloadInfo, decoded: u64) {
+    if self.text.is_some() {
+      return;
+    }
+    self.with_bar(info, |download| {
+      *download.keys.decoded.lock().unwrap() = Some(decoded);
+      // 进度不变时也重绘 {decoded_bytes}
+      download.bar.tick();
+    });
+  }
+
+  fn on_peers(&self, info: &DownloadInfo, peers: usize) {
+    if self.text.is_some() {
+      return;
+    }
+
+    let bar = self.bar(info);
+    let percentage = match bar.length() {
+      Some(total) if total > 0 => bar.position() * 100 / total,
+      _ => 0,
+    };
+    bar.set_message(self.messages.peers(info, percentage, peers));
+  }
+
+  fn on_retry_scheduled(&self, info: &DownloadInfo, retry: &RetryAttempt<'_>) {
+    let message = self.messages.retrying(info, retry);
+    if let Some(text) = &self.text {
+      if self.file_bars {
+        text.print(message.trim_end());
+      }
+      return;
+    }
+
+    // 显示重试原因和等待时间，避免进度条看起来卡住
+    self.bar(info).set_message(message);
+  }
+
+  fn on_extracting(&self, info: &DownloadInfo, extracted: u64, total: u64) {
+    if self.text.is_some() {
+      return;
+    }
+
+    let bar = self.bar(info);
+    bar.set_length(total);
+    bar.set_position(extracted);
+    bar.set_message(self.messages.extracting(info));
+  }
+
+  fn on_finished(&self, info: &DownloadInfo) {
+    match &self.text {
+      Some(text) => text.on_completed(info, &self.messages.finished(info)),
+      None => self.remove(info),
+    }
+  }
+
+  fn on_skipped(&self, info: &DownloadInfo) {
+    match &self.text {
+      Some(text) => text.on_completed(info, &self.messages.skipped(info)),
+      None => self.remove(info),
+    }
+  }
+
+  fn on_failed(&self, info: &DownloadInfo, error: &ProgressDownloadError) {
+    match &self.text {
+      Some(text) => text.on_completed(info, &self.messages.failed(info, error)),
+      None => self.remove(info),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_short_name() {
+    let info = |url: &str, target: &str| DownloadInfo {
+      index: 0,
+      url: url.to_string(),
+      target: PathBuf::from(target),
+    };
+    assert_eq!(
+      short_name(&info("https://example.com/a.zip", "out/b.zip")),
+      "b.zip"
+    );
+    assert_eq!(
+      short_name(&info("https://example.com/dir/a%20b.zip?x=1", "-")),
+      "a b.zip"
+    );
+    assert_eq!(
+      short_name(&info("https://example.com/", "")),
+      "https://example.com/"
+    );
+  }
+}
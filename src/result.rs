@@ -0,0 +1,247 @@
+use std::{fmt, path::PathBuf, time::Duration};
+
+use indicatif::{HumanBytes, HumanDuration};
+
+use crate::err::ProgressDownloadError;
+
+/// How a download completed successfully.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadStatus {
+  /// The file was downloaded and saved to its target path.
+  Downloaded,
+  /// The target file was already up to date and nothing was downloaded.
+  Skipped,
+  /// The file was taken from the download cache instead of being downloaded.
+  Cached,
+}
+
+/// What is known about a download once it ends, the parts of its [`DownloadResult`]
+/// besides the URL and the result.
+#[derive(Debug, Default)]
+pub(crate) struct DownloadDetails {
+  pub target: PathBuf,
+  pub final_url: Option<String>,
+  pub http_version: Option<reqwest::Version>,
+  pub digest: Option<String>,
+  pub size: Option<u64>,
+  pub content_type: Option<String>,
+  pub etag: Option<String>,
+  pub bytes: u64,
+  pub attempts: usize,
+  pub retried_bytes: u64,
+  pub backoff: Duration,
+}
+
+/// The outcome of one download of a batch run by
+/// [`RobustDownloader::download_all`](crate::RobustDownloader::download_all).
+#[derive(Debug)]
+pub struct DownloadResult {
+  /// The URL that was downloaded.
+  pub url: String,
+  /// The local path the file was saved to.
+  pub target: PathBuf,
+  /// The URL the file was downloaded from after following redirects, if a request was made.
+  pub final_url: Option<String>,
+  /// The HTTP version of the last response, e.g. to check whether HTTP/2 was negotiated.
+  /// `None` if no HTTP request was made.
+  pub http_version: Option<reqwest::Version>,
+  /// The lowercase hex digest of the downloaded file, computed while it was written, if the
+  /// downloader has a `digest_algorithm` or the item an [`Integrity`](crate::Integrity).
+  /// `None` for skipped files.
+  pub digest: Option<String>,
+  /// Size of the saved file in bytes, or of the data written to stdout or a pipe.
+  /// `None` if the download failed.
+  pub size: Option<u64>,
+  /// The `Content-Type` of the last response, if the server sent one.
+  pub content_type: Option<String>,
+  /// The `ETag` of the remote file from the last response, if the server sent one.
+  pub etag: Option<String>,
+  /// Bytes received over the network, counting every attempt but not bytes resumed
+  /// from a previous run.
+  pub bytes: u64,
+  /// Download attempts that were made, 0 if none was needed, e.g. for skipped files.
+  pub attempts: usize,
+  /// Bytes received again because an attempt could not resume the data of an earlier
+  /// one, e.g. when the server ignored the range request, and started over.
+  pub retried_bytes: u64,
+  /// Time spent waiting between attempts, as chosen by the retry policy.
+  pub backoff: Duration,
+  /// How long the download took, from getting a download slot until it ended.
+  pub elapsed: Duration,
+  /// How the download completed, or the error it failed with after all retry attempts.
+  pub result: Result<DownloadStatus, ProgressDownloadError>,
+}
+
+impl DownloadResult {
+  /// Returns `true` if the file was downloaded successfully or skipped as up to date.
+  pub fn is_success(&self) -> bool {
+    self.result.is_ok()
+  }
+
+  /// Returns `true` if the download was stopped or never started because of a shutdown.
+  pub fn is_interrupted(&self) -> bool {
+    matches!(self.result, Err(ProgressDownloadError::Interrupted))
+  }
+
+  /// Returns `true` if the file was skipped because it was already up to date.
+  pub fn is_skipped(&self) -> bool {
+    matches!(self.result, Ok(DownloadStatus::Skipped))
+  }
+}
+
+/// The totals of a batch, returned by [`RobustDownloader::download`](crate::RobustDownloader::download)
+/// and built from the results of [`download_all`](crate::RobustDownloader::download_all)
+/// with [`BatchSummary::new`]. Its `Display` output is a one-line summary for logs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchSummary {
+  /// Files that were downloaded.
+  pub succeeded: usize,
+  /// Files that failed, including interrupted ones.
+  pub failed: usize,
+  /// Files skipped because they were already up to date.
+  pub skipped: usize,
+  /// Files restored from the `cache_dir` of the downloader.
+  pub cached: usize,
+  /// Bytes received over the network by all downloads, see [`DownloadResult::bytes`].
+  pub bytes: u64,
+  /// Wall time of the whole batch.
+  pub elapsed: Duration,
+  /// Timing and metadata of each file, in the order of the batch.
+  pub files: Vec<FileSummary>,
+}
+
+/// The timing and metadata of one file of a [`BatchSummary`], see [`DownloadResult`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSummary {
+  /// The URL that was downloaded.
+  pub url: String,
+  /// The local path the file was saved to.
+  pub target: PathBuf,
+  /// How the download completed, `None` if it failed.
+  pub status: Option<DownloadStatus>,
+  /// The URL the file was downloaded from after following redirects.
+  pub final_url: Option<String>,
+  /// Size of the saved file in bytes.
+  pub size: Option<u64>,
+  /// The `Content-Type` of the last response.
+  pub content_type: Option<String>,
+  /// The `ETag` of the remote file.
+  pub etag: Option<String>,
+  /// Bytes received over the network.
+  pub bytes: u64,
+  /// Download attempts that were made.
+  pub attempts: usize,
+  /// Bytes received again after an attempt started over.
+  pub retried_bytes: u64,
+  /// Time spent waiting between attempts.
+  pub backoff: Duration,
+  /// How long the download took.
+  pub elapsed: Duration,
+}
+
+impl BatchSummary {
+  /// Sums up the `results` of a batch that took `elapsed`.
+  pub fn new(results: &[DownloadResult], elapsed: Duration) -> Self {
+    let mut summary = Self {
+      elapsed,
+      files: Vec::with_capacity(results.len()),
+      ..Default::default()
+    };
+
+    for result in results {
+      match &result.result {
+        Ok(DownloadStatus::Downloaded) => summary.succeeded += 1,
+        Ok(DownloadStatus::Skipped) => summary.skipped += 1,
+        Ok(DownloadStatus::Cached) => summary.cached += 1,
+        Err(_) => summary.failed += 1,
+      }
+      summary.bytes += result.bytes;
+      summary.files.push(FileSummary {
+        url: result.url.clone(),
+        target: result.target.clone(),
+        status: result.result.as_ref().ok().copied(),
+        final_url: result.final_url.clone(),
+        size: result.size,
+        content_type: result.content_type.clone(),
+        etag: result.etag.clone(),
+        bytes: result.bytes,
+        attempts: result.attempts,
+        retried_bytes: result.retried_bytes,
+        backoff: result.backoff,
+        elapsed: result.elapsed,
+      });
+    }
+
+    summary
+  }
+
+  /// Average speed of the batch in bytes per second.
+  pub fn average_speed(&self) -> f64 {
+    if self.elapsed.is_zero() {
+      return 0.0;
+    }
+    self.bytes as f64 / self.elapsed.as_secs_f64()
+  }
+}
+
+impl fmt::Display for BatchSummary {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} downloaded, ", self.succeeded)?;
+    // 未使用缓存时不显示
+    if self.cached > 0 {
+      write!(f, "{} from cache, ", self.cached)?;
+    }
+    write!(
+      f,
+      "{} skipped, {} failed, {} in {} ({}/s)",
+      self.skipped,
+      self.failed,
+      HumanBytes(self.bytes),
+      HumanDuration(self.elapsed),
+      HumanBytes(self.average_speed() as u64)
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_batch_summary() {
+    let result = |result, bytes, secs| DownloadResult {
+      url: "https://example.com/file".to_string(),
+      target: PathBuf::from("file"),
+      final_url: None,
+      http_version: None,
+      digest: None,
+      size: None,
+      content_type: None,
+      etag: None,
+      bytes,
+      attempts: 1,
+      retried_bytes: 0,
+      backoff: Duration::ZERO,
+      elapsed: Duration::from_secs(secs),
+      result,
+    };
+    let results = [
+      result(Ok(DownloadStatus::Downloaded), 3072, 2),
+      result(Ok(DownloadStatus::Skipped), 0, 0),
+      result(Err(ProgressDownloadError::Interrupted), 1024, 1),
+    ];
+
+    let summary = BatchSummary::new(&results, Duration::from_secs(2));
+    assert_eq!(
+      (summary.succeeded, summary.skipped, summary.failed),
+      (1, 1, 1)
+    );
+    assert_eq!(summary.bytes, 4096);
+    assert_eq!(summary.average_speed(), 2048.0);
+    assert_eq!(summary.files[2].status, None);
+    assert_eq!(
+      summary.to_string(),
+      "1 downloaded, 1 skipped, 1 failed, 4.00 KiB in 2 seconds (2.00 KiB/s)"
+    );
+  }
+}
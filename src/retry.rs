@@ -0,0 +1,349 @@
+use std::{
+  fmt,
+  sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+  },
+  time::{Duration, SystemTime},
+};
+
+use backoff::{ExponentialBackoff, backoff::Backoff};
+use typed_builder::TypedBuilder;
+
+use crate::err::ProgressDownloadError;
+
+/// Controls how failed download attempts are retried.
+///
+/// Waits between attempts grow exponentially from `initial_interval` by `multiplier`,
+/// capped at `max_interval`. Retrying stops once `max_elapsed_time` has passed or
+/// `max_attempts` attempts have been made, whichever comes first.
+///
+/// With `reset_on_progress`, an attempt that received data before failing starts the
+/// schedule over: the wait, the elapsed time and the attempt count are reset. Large
+/// downloads interrupted now and then keep going, while a download failing without
+/// progress still gives up.
+///
+/// When a 429 or 503 response carries a `Retry-After` header, the next wait lasts at
+/// least as long as the server asked for, even beyond `max_interval`. If that wait would
+/// exceed `max_elapsed_time`, the download fails right away instead.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use robust_downloader::{RetryPolicy, RobustDownloader};
+///
+/// let downloader = RobustDownloader::builder()
+///   .retry_policy(
+///     RetryPolicy::builder()
+///       .initial_interval(Duration::from_secs(1))
+///       .max_attempts(5)
+///       .build(),
+///   )
+///   .build();
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct RetryPolicy {
+  /// Wait before the first retry.
+  /// Defaults to 500ms.
+  #[builder(default = Duration::from_millis(500))]
+  pub initial_interval: Duration,
+
+  /// Random jitter applied to every wait, as a fraction of the wait.
+  /// Defaults to 15%.
+  #[builder(default = 0.15)]
+  pub randomization_factor: f64,
+
+  /// Factor each wait grows by.
+  /// Defaults to 1.5x.
+  #[builder(default = 1.5)]
+  pub multiplier: f64,
+
+  /// Upper bound of a single wait.
+  /// Defaults to 5 seconds.
+  #[builder(default = Duration::from_secs(5))]
+  pub max_interval: Duration,
+
+  /// Total time after which no more retries are made, `None` for no limit. Measured
+  /// from the last attempt that received data if `reset_on_progress` is set.
+  /// Defaults to 120 seconds.
+  #[builder(default = Some(Duration::from_secs(120)))]
+  pub max_elapsed_time: Option<Duration>,
+
+  /// Maximum number of attempts including the first one, `None` for no limit. Counts
+  /// only the attempts since the last one that received data if `reset_on_progress` is
+  /// set.
+  /// Defaults to no limit.
+  #[builder(default = None, setter(strip_option))]
+  pub max_attempts: Option<usize>,
+
+  /// Whether an attempt that received data before failing resets the backoff schedule.
+  /// Defaults to true.
+  #[builder(default = true)]
+  pub reset_on_progress: bool,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self::builder().build()
+  }
+}
+
+impl RetryPolicy {
+  /// A policy that makes a single attempt and never retries.
+  pub fn disabled() -> Self {
+    Self::builder().max_attempts(1).build()
+  }
+
+  /// Creates the backoff schedule for one download.
+  pub(crate) fn backoff(&self) -> PolicyBackoff {
+    PolicyBackoff {
+      inner: ExponentialBackoff {
+        initial_interval: self.initial_interval,
+        current_interval: self.initial_interval,
+        randomization_factor: self.randomization_factor,
+        multiplier: self.multiplier,
+        max_interval: self.max_interval,
+        max_elapsed_time: self.max_elapsed_time,
+        ..Default::default()
+      },
+      max_attempts: self.max_attempts,
+      attempts: 1,
+      retries: Arc::default(),
+      retry_after: Arc::default(),
+      reset_on_progress: self.reset_on_progress,
+      progressed: Arc::default(),
+    }
+  }
+}
+
+impl From<ExponentialBackoff> for RetryPolicy {
+  fn from(backoff: ExponentialBackoff) -> Self {
+    Self {
+      initial_interval: backoff.initial_interval,
+      randomization_factor: backoff.randomization_factor,
+      multiplier: backoff.multiplier,
+      max_interval: backoff.max_interval,
+      max_elapsed_time: backoff.max_elapsed_time,
+      max_attempts: None,
+      reset_on_progress: true,
+    }
+  }
+}
+
+/// Decides which failed attempts are retried.
+///
+/// Errors that are not retryable fail the download immediately. Closures taking a
+/// `&ProgressDownloadError` and returning a `bool` implement this trait too.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use robust_downloader::{ProgressDownloadError, RobustDownloader};
+///
+/// // 除默认规则外，404 也重试（例如文件尚未发布）
+/// let downloader = RobustDownloader::builder()
+///   .retry_classifier(Arc::new(|error: &ProgressDownloadError| {
+///     error.is_retryable() || error.status().is_some_and(|status| status.as_u16() == 404)
+///   }))
+///   .build();
+/// ```
+pub trait RetryClassifier: Send + Sync {
+  /// Whether the download should be attempted again after failing with `error`.
+  fn is_retryable(&self, error: &ProgressDownloadError) -> bool;
+}
+
+impl<F> RetryClassifier for F
+where
+  F: Fn(&ProgressDownloadError) -> bool + Send + Sync,
+{
+  fn is_retryable(&self, error: &ProgressDownloadError) -> bool {
+    self(error)
+  }
+}
+
+impl fmt::Debug for dyn RetryClassifier + '_ {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("RetryClassifier")
+  }
+}
+
+/// Retries server errors, timeouts, connection failures and transient I/O errors, see
+/// [`ProgressDownloadError::is_retryable`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultRetryClassifier;
+
+impl RetryClassifier for DefaultRetryClassifier {
+  fn is_retryable(&self, error: &ProgressDownloadError) -> bool {
+    error.is_retryable()
+  }
+}
+
+/// An [`ExponentialBackoff`] that additionally stops after a maximum number of attempts,
+/// waits at least as long as the server asked for with `Retry-After` and starts over
+/// after attempts that made progress.
+#[derive(Debug, Clone)]
+pub(crate) struct PolicyBackoff {
+  inner: ExponentialBackoff,
+  max_attempts: Option<usize>,
+  attempts: usize,
+  /// 当前这轮重试的次数，收到数据后重新计数
+  retries: Arc<AtomicUsize>,
+  retry_after: Arc<Mutex<Option<Duration>>>,
+  reset_on_progress: bool,
+  progressed: Arc<AtomicBool>,
+}
+
+impl PolicyBackoff {
+  /// The `Retry-After` delay of the last failed attempt, set before the next wait is
+  /// chosen.
+  pub(crate) fn retry_after(&self) -> Arc<Mutex<Option<Duration>>> {
+    self.retry_after.clone()
+  }
+
+  /// Number of the retry of the last chosen wait, counted like `max_attempts`.
+  pub(crate) fn retries(&self) -> Arc<AtomicUsize> {
+    self.retries.clone()
+  }
+
+  /// Whether the last failed attempt received data, set before the next wait is chosen.
+  pub(crate) fn progressed(&self) -> Arc<AtomicBool> {
+    self.progressed.clone()
+  }
+}
+
+impl Backoff for PolicyBackoff {
+  fn reset(&mut self) {
+    self.inner.reset();
+    self.attempts = 1;
+  }
+
+  fn next_backoff(&mut self) -> Option<Duration> {
+    // 收到数据的失败不计入重试次数和时长
+    if self.progressed.swap(false, Ordering::Relaxed) && self.reset_on_progress {
+      self.reset();
+    }
+    if self
+      .max_attempts
+      .is_some_and(|max_attempts| self.attempts >= max_attempts)
+    {
+      return None;
+    }
+    self.attempts += 1;
+    self.retries.store(self.attempts - 1, Ordering::Relaxed);
+    let delay = self.inner.next_backoff()?;
+
+    let Some(retry_after) = self.retry_after.lock().unwrap().take() else {
+      return Some(delay);
+    };
+
+    // 服务端要求的等待超出重试时限时直接放弃
+    if self.inner.max_elapsed_time.is_some_and(|max_elapsed_time| {
+      self.inner.get_elapsed_time() + retry_after > max_elapsed_time
+    }) {
+      return None;
+    }
+    Some(delay.max(retry_after))
+  }
+}
+
+/// Parses a `Retry-After` header value, either a number of seconds or an HTTP date.
+pub(crate) fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+  let value = value.trim();
+  if let Ok(seconds) = value.parse::<u64>() {
+    return Some(Duration::from_secs(seconds));
+  }
+
+  // 日期早于当前时间时无需等待
+  let date = httpdate::parse_http_date(value).ok()?;
+  Some(date.duration_since(now).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_max_attempts() {
+    let mut backoff = RetryPolicy::builder().max_attempts(3).build().backoff();
+    backoff.reset();
+    assert!(backoff.next_backoff().is_some());
+    assert!(backoff.next_backoff().is_some());
+    assert!(backoff.next_backoff().is_none());
+
+    assert!(RetryPolicy::disabled().backoff().next_backoff().is_none());
+  }
+
+  #[test]
+  fn test_retry_after() {
+    let mut backoff = RetryPolicy::builder()
+      .initial_interval(Duration::from_millis(10))
+      .randomization_factor(0.0)
+      .build()
+      .backoff();
+    backoff.reset();
+
+    *backoff.retry_after().lock().unwrap() = Some(Duration::from_secs(5));
+    assert_eq!(backoff.next_backoff(), Some(Duration::from_secs(5)));
+    // 只影响紧接着的一次等待
+    assert!(backoff.next_backoff().unwrap() < Duration::from_secs(1));
+
+    // 超出重试时限的等待不再重试
+    *backoff.retry_after().lock().unwrap() = Some(Duration::from_secs(3600));
+    assert_eq!(backoff.next_backoff(), None);
+  }
+
+  #[test]
+  fn test_reset_on_progress() {
+    let mut backoff = RetryPolicy::builder()
+      .initial_interval(Duration::from_millis(10))
+      .randomization_factor(0.0)
+      .multiplier(2.0)
+      .max_attempts(3)
+      .build()
+      .backoff();
+    backoff.reset();
+
+    let retries = backoff.retries();
+    assert_eq!(backoff.next_backoff(), Some(Duration::from_millis(10)));
+    assert_eq!(backoff.next_backoff(), Some(Duration::from_millis(20)));
+    assert_eq!(retries.load(Ordering::Relaxed), 2);
+    backoff.progressed().store(true, Ordering::Relaxed);
+    assert_eq!(backoff.next_backoff(), Some(Duration::from_millis(10)));
+    assert_eq!(retries.load(Ordering::Relaxed), 1);
+    assert_eq!(backoff.next_backoff(), Some(Duration::from_millis(20)));
+    assert_eq!(backoff.next_backoff(), None);
+
+    let mut backoff = RetryPolicy::builder()
+      .reset_on_progress(false)
+      .max_attempts(2)
+      .build()
+      .backoff();
+    backoff.reset();
+    assert!(backoff.next_backoff().is_some());
+    backoff.progressed().store(true, Ordering::Relaxed);
+    assert!(backoff.next_backoff().is_none());
+  }
+
+  #[test]
+  fn test_parse_retry_after() {
+    let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+
+    assert_eq!(
+      parse_retry_after(" 120 ", now),
+      Some(Duration::from_secs(120))
+    );
+    assert_eq!(
+      parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+      Some(Duration::from_secs(30))
+    );
+    assert_eq!(
+      parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+      Some(Duration::ZERO)
+    );
+    assert_eq!(parse_retry_after("soon", now), None);
+  }
+}
@@ -0,0 +1,376 @@
+use std::{fmt, time::Duration};
+
+use aws_config::{BehaviorVersion, Region, retry::RetryConfig, timeout::TimeoutConfig};
+use aws_sdk_s3::{
+  error::{DisplayErrorContext, ProvideErrorMetadata, SdkError},
+  primitives::{ByteStream, DateTimeFormat},
+  types::{CompletedMultipartUpload, CompletedPart},
+};
+use bytes::Bytes;
+use futures::{FutureExt, Stream, StreamExt, future::BoxFuture};
+use reqwest::Url;
+
+use crate::{
+  err::ProgressDownloadError,
+  remote::{S3Options, percent_decode},
+  sink::StorageSink,
+  source::SourceFile,
+};
+
+/// Region used when neither the options nor the environment name one.
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// Starts downloading the object of an `s3://bucket/key` URL from `offset` with a ranged
+/// `GetObject` request.
+pub(crate) async fn open(
+  url: &str,
+  offset: u64,
+  connect_timeout: Duration,
+  options: &S3Options,
+) -> Result<SourceFile, ProgressDownloadError> {
+  let (bucket, key) = parse_url(url)?;
+  // 重试由下载器的重试策略负责，关闭 SDK 自带的重试
+  let client = client(options, RetryConfig::disabled(), Some(connect_timeout)).await;
+
+  let mut request = client.get_object().bucket(bucket).key(key);
+  if offset > 0 {
+    request = request.range(format!("bytes={offset}-"));
+  }
+  let output = request.send().await.map_err(|err| s3_error(url, err))?;
+
+  // 206 响应的 Content-Range 带有对象的总大小
+  let total_size = output
+    .content_range()
+    .and_then(|range| range.rsplit_once('/'))
+    .and_then(|(_, total)| total.parse().ok());
+  let resumed = offset > 0 && output.content_range().is_some();
+  let size = total_size.or_else(|| {
+    output
+      .content_length()
+      .and_then(|length| u64::try_from(length).ok())
+  });
+
+  Ok(SourceFile {
+    size,
+    modified: output
+      .last_modified()
+      .and_then(|modified| modified.fmt(DateTimeFormat::HttpDate).ok()),
+    etag: output.e_tag().map(str::to_string),
+    resumed,
+    stream: into_stream(output.body).boxed(),
+  })
+}
+
+/// The bucket and key of an `s3://bucket/key` URL.
+fn parse_url(url: &str) -> Result<(String, String), ProgressDownloadError> {
+  let error = |message: String| ProgressDownloadError::S3 {
+    url: url.to_string(),
+    message,
+  };
+
+  let parsed = Url::parse(url).map_err(|err| error(err.to_string()))?;
+  let bucket = parsed
+    .host_str()
+    .filter(|bucket| !bucket.is_empty())
+    .ok_or_else(|| error("missing bucket".to_string()))?
+    .to_string();
+  let key = percent_decode(parsed.path().trim_start_matches('/'));
+  if key.is_empty() {
+    return Err(error("missing object key".to_string()));
+  }
+  Ok((bucket, key))
+}
+
+/// Creates a client configured by `options` and the environment.
+async fn client(
+  options: &S3Options,
+  retry: RetryConfig,
+  connect_timeout: Option<Duration>,
+) -> aws_sdk_s3::Client {
+  let mut loader = aws_config::defaults(BehaviorVersion::latest()).retry_config(retry);
+  if let Some(connect_timeout) = connect_timeout {
+    loader = loader.timeout_config(
+      TimeoutConfig::builder()
+        .connect_timeout(connect_timeout)
+        .build(),
+    );
+  }
+  if let Some(region) = &options.region {
+    loader = loader.region(Region::new(region.clone()));
+  }
+  if let Some(profile) = &options.profile {
+    loader = loader.profile_name(profile);
+  }
+  if let Some(endpoint) = &options.endpoint {
+    loader = loader.endpoint_url(endpoint);
+  }
+  let config = loader.load().await;
+
+  let mut s3_config =
+    aws_sdk_s3::config::Builder::from(&config).force_path_style(options.force_path_style);
+  if config.region().is_none() {
+    s3_config = s3_config.region(Region::from_static(DEFAULT_REGION));
+  }
+  aws_sdk_s3::Client::from_conf(s3_config.build())
+}
+
+/// Reads the object body as a stream of chunks.
+fn into_stream(body: ByteStream) -> impl Stream<Item = Result<Bytes, ProgressDownloadError>> {
+  futures::stream::try_unfold(body, |mut body| async move {
+    match body.next().await {
+      Some(chunk) => {
+        let chunk =
+          chunk.map_err(|err| std::io::Error::new(std::io::ErrorKind::ConnectionAborted, err))?;
+        Ok(Some((chunk, body)))
+      }
+      None => Ok(None),
+    }
+  })
+}
+
+/// Converts an SDK error. Throttling and server errors are retried like connection
+/// failures. Other error responses, such as a missing object or denied access, and
+/// missing credentials are permanent.
+fn s3_error<E>(
+  url: &str,
+  err: SdkError<E, aws_sdk_s3::config::http::HttpResponse>,
+) -> ProgressDownloadError
+where
+  E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+{
+  let permanent = |err: &SdkError<E, _>| ProgressDownloadError::S3 {
+    url: url.to_string(),
+    message: DisplayErrorContext(err).to_string(),
+  };
+
+  match err {
+    SdkError::TimeoutError(_) => {
+      std::io::Error::new(std::io::ErrorKind::TimedOut, format!("{url}: timed out")).into()
+    }
+    SdkError::ServiceError(err) => {
+      let status = err.raw().status().as_u16();
+      let message = match (err.err().code(), err.err().message()) {
+        (Some(code), Some(message)) => format!("{code}: {message}"),
+        (Some(code), None) => code.to_string(),
+        _ => format!("HTTP status {status}"),
+      };
+
+      if status >= 500 || matches!(status, 408 | 429) {
+        std::io::Error::other(format!("{url}: {message}")).into()
+      } else {
+        ProgressDownloadError::S3 {
+          url: url.to_string(),
+          message,
+        }
+      }
+    }
+    // 请求无法构建或找不到凭据时重试也不会成功
+    SdkError::ConstructionFailure(_) => permanent(&err),
+    SdkError::DispatchFailure(ref failure) if !failure.is_io() && !failure.is_timeout() => {
+      permanent(&err)
+    }
+    err => std::io::Error::new(
+      std::io::ErrorKind::ConnectionAborted,
+      DisplayErrorContext(&err).to_string(),
+    )
+    .into(),
+  }
+}
+
+/// Smallest part of a multipart upload S3 accepts, except for the last one.
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+/// A [`StorageSink`] uploading a download to an `s3://bucket/key` object, without
+/// storing it on disk.
+///
+/// The data is buffered and sent in parts of `part_size` bytes with a multipart
+/// upload, completed when the sink is finalized and aborted if the download fails.
+/// Files smaller than one part are uploaded with a single `PutObject` request.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use robust_downloader::{RobustDownloader, S3Options, S3UploadSink};
+/// async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let downloader = RobustDownloader::builder().build();
+/// let mut sink = S3UploadSink::new("s3://bucket/file.zip", S3Options::default())?;
+/// downloader
+///     .download_to_sink("https://example.com/file.zip", &mut sink)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct S3UploadSink {
+  url: String,
+  bucket: String,
+  key: String,
+  options: S3Options,
+  part_size: usize,
+  client: Option<aws_sdk_s3::Client>,
+  upload_id: Option<String>,
+  parts: Vec<CompletedPart>,
+  buffer: Vec<u8>,
+}
+
+impl S3UploadSink {
+  /// Creates a sink uploading to the object of an `s3://bucket/key` URL, with the
+  /// endpoint and credentials of `options`.
+  pub fn new(url: &str, options: S3Options) -> Result<Self, ProgressDownloadError> {
+    let (bucket, key) = parse_url(url)?;
+    Ok(Self {
+      url: url.to_string(),
+      bucket,
+      key,
+      options,
+      part_size: 8 * 1024 * 1024,
+      client: None,
+      upload_id: None,
+      parts: Vec::new(),
+      buffer: Vec::new(),
+    })
+  }
+
+  /// Sets the size of the uploaded parts, at least 5 MiB.
+  /// Defaults to 8 MiB.
+  pub fn part_size(mut self, part_size: usize) -> Self {
+    self.part_size = part_size.max(MIN_PART_SIZE);
+    self
+  }
+
+  fn io_error(&self, message: impl fmt::Display) -> std::io::Error {
+    std::io::Error::other(format!("{}: {message}", self.url))
+  }
+
+  async fn client(&mut self) -> aws_sdk_s3::Client {
+    match &self.client {
+      Some(client) => client.clone(),
+      None => {
+        // 上传失败时由 SDK 重试，避免重新下载
+        let client = client(&self.options, RetryConfig::standard(), None).await;
+        self.client.insert(client).clone()
+      }
+    }
+  }
+
+  /// Uploads the buffered data as the next part, starting the upload first if needed.
+  async fn upload_part(&mut self) -> std::io::Result<()> {
+    let client = self.client().await;
+    let upload_id = match &self.upload_id {
+      Some(upload_id) => upload_id.clone(),
+      None => {
+        let output = client
+          .create_multipart_upload()
+          .bucket(&self.bucket)
+          .key(&self.key)
+          .send()
+          .await
+          .map_err(|err| self.io_error(DisplayErrorContext(err)))?;
+        let upload_id = output
+          .upload_id()
+          .ok_or_else(|| self.io_error("missing upload id"))?;
+        self.upload_id.insert(upload_id.to_string()).clone()
+      }
+    };
+
+    let part_number = self.parts.len() as i32 + 1;
+    let output = client
+      .upload_part()
+      .bucket(&self.bucket)
+      .key(&self.key)
+      .upload_id(upload_id)
+      .part_number(part_number)
+      .body(ByteStream::from(self.buffer.clone()))
+      .send()
+      .await
+      .map_err(|err| self.io_error(DisplayErrorContext(err)))?;
+
+    self.parts.push(
+      CompletedPart::builder()
+        .part_number(part_number)
+        .set_e_tag(output.e_tag().map(str::to_string))
+        .build(),
+    );
+    self.buffer.clear();
+    Ok(())
+  }
+}
+
+impl StorageSink for S3UploadSink {
+  fn write(&mut self, chunk: Bytes) -> BoxFuture<'_, std::io::Result<()>> {
+    async move {
+      let buffered = self.buffer.len();
+      self.buffer.extend_from_slice(&chunk);
+      if self.buffer.len() < self.part_size {
+        return Ok(());
+      }
+
+      let result = self.upload_part().await;
+      // 上传失败时撤回这个数据块，下次尝试会重新写入它
+      if result.is_err() {
+        self.buffer.truncate(buffered);
+      }
+      result
+    }
+    .boxed()
+  }
+
+  fn finalize(&mut self) -> BoxFuture<'_, std::io::Result<()>> {
+    async move {
+      let client = self.client().await;
+      if self.upload_id.is_none() {
+        client
+          .put_object()
+          .bucket(&self.bucket)
+          .key(&self.key)
+          .body(ByteStream::from(std::mem::take(&mut self.buffer)))
+          .send()
+          .await
+          .map_err(|err| self.io_error(DisplayErrorContext(err)))?;
+        return Ok(());
+      }
+
+      if !self.buffer.is_empty() {
+        self.upload_part().await?;
+      }
+      client
+        .complete_multipart_upload()
+        .bucket(&self.bucket)
+        .key(&self.key)
+        .set_upload_id(self.upload_id.take())
+        .multipart_upload(
+          CompletedMultipartUpload::builder()
+            .set_parts(Some(std::mem::take(&mut self.parts)))
+            .build(),
+        )
+        .send()
+        .await
+        .map_err(|err| self.io_error(DisplayErrorContext(err)))?;
+      Ok(())
+    }
+    .boxed()
+  }
+
+  fn abort(&mut self) -> BoxFuture<'_, std::io::Result<()>> {
+    async move {
+      self.buffer.clear();
+      self.parts.clear();
+      let Some(upload_id) = self.upload_id.take() else {
+        return Ok(());
+      };
+      // 未中止的分段上传会一直占用存储空间
+      self
+        .client()
+        .await
+        .abort_multipart_upload()
+        .bucket(&self.bucket)
+        .key(&self.key)
+        .upload_id(upload_id)
+        .send()
+        .await
+        .map_err(|err| self.io_error(DisplayErrorContext(err)))?;
+      Ok(())
+    }
+    .boxed()
+  }
+}
@@ -0,0 +1,246 @@
+use std::{
+  io::SeekFrom,
+  path::PathBuf,
+  sync::Arc,
+  time::{Duration, UNIX_EPOCH},
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use reqwest::Url;
+use russh::{
+  client,
+  keys::{self, PrivateKeyWithHashAlg, PublicKeyOrCertificate},
+};
+use russh_sftp::{client::SftpSession, protocol::StatusCode};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::debug;
+
+use crate::{
+  err::{ProgressDownloadError, TimeoutPhase},
+  remote::{SftpOptions, percent_decode},
+  source::SourceFile,
+};
+
+/// Size of the buffer the remote file is read into.
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Private keys tried when [`SftpOptions::private_key`] is not set, relative to `~/.ssh`.
+const DEFAULT_KEYS: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// Checks the server's host key against the `known_hosts` file.
+struct HostKeyCheck {
+  host: String,
+  port: u16,
+  known_hosts: Option<PathBuf>,
+  accept_unknown_hosts: bool,
+}
+
+impl client::Handler for HostKeyCheck {
+  type Error = russh::Error;
+
+  async fn check_server_key(
+    &mut self,
+    server_key: &PublicKeyOrCertificate,
+  ) -> Result<bool, Self::Error> {
+    let PublicKeyOrCertificate::PublicKey { key, .. } = server_key else {
+      return Ok(self.accept_unknown_hosts);
+    };
+
+    let known = match &self.known_hosts {
+      Some(path) => keys::check_known_hosts_path(&self.host, self.port, key, path),
+      None => keys::check_known_hosts(&self.host, self.port, key),
+    };
+
+    match known {
+      Ok(true) => Ok(true),
+      // 主机密钥变化可能是中间人攻击，总是拒绝
+      Err(keys::Error::KeyChanged { line }) => {
+        debug!(line, "host key of {} changed", self.host);
+        Ok(false)
+      }
+      Ok(false) | Err(_) => Ok(self.accept_unknown_hosts),
+    }
+  }
+}
+
+/// Logs in to the server of `url` and opens its file for reading from `offset`.
+///
+/// A password in the URL is used if present, otherwise the private key of `options`.
+pub(crate) async fn open(
+  url: &str,
+  offset: u64,
+  connect_timeout: Duration,
+  options: &SftpOptions,
+) -> Result<SourceFile, ProgressDownloadError> {
+  let error = |message: String| ProgressDownloadError::Sftp {
+    url: url.to_string(),
+    message,
+  };
+
+  let parsed = Url::parse(url).map_err(|err| error(err.to_string()))?;
+  let host = parsed.host_str().unwrap_or_default().to_string();
+  let port = parsed.port().unwrap_or(22);
+  let user = match parsed.username() {
+    "" => std::env::var("USER").unwrap_or_else(|_| "root".to_string()),
+    user => percent_decode(user),
+  };
+  // sftp://host/~/file 表示相对用户主目录的路径
+  let path = percent_decode(parsed.path());
+  let path = path.strip_prefix("/~/").unwrap_or(&path).to_string();
+
+  let handler = HostKeyCheck {
+    host: host.clone(),
+    port,
+    known_hosts: options.known_hosts.clone(),
+    accept_unknown_hosts: options.accept_unknown_hosts,
+  };
+  let config = Arc::new(client::Config::default());
+  let mut session = tokio::time::timeout(
+    connect_timeout,
+    client::connect(config, (host.as_str(), port), handler),
+  )
+  .await
+  .map_err(ProgressDownloadError::timed_out(
+    TimeoutPhase::Connect,
+    connect_timeout,
+  ))?
+  .map_err(|err| ssh_error(url, err))?;
+
+  let authenticated = match parsed.password() {
+    Some(password) => session
+      .authenticate_password(user, percent_decode(password))
+      .await
+      .map_err(|err| ssh_error(url, err))?,
+    None => {
+      let key_path = private_key_path(options)
+        .ok_or_else(|| error("no private key found in ~/.ssh".to_string()))?;
+      let key = keys::load_secret_key(&key_path, options.passphrase.as_deref())
+        .map_err(|err| error(format!("{}: {}", key_path.display(), err)))?;
+      let hash_alg = session
+        .best_supported_rsa_hash()
+        .await
+        .map_err(|err| ssh_error(url, err))?
+        .flatten();
+      session
+        .authenticate_publickey(user, PrivateKeyWithHashAlg::new(Arc::new(key), hash_alg))
+        .await
+        .map_err(|err| ssh_error(url, err))?
+    }
+  };
+  if !authenticated.success() {
+    return Err(error("authentication failed".to_string()));
+  }
+
+  let channel = session
+    .channel_open_session()
+    .await
+    .map_err(|err| ssh_error(url, err))?;
+  channel
+    .request_subsystem(true, "sftp")
+    .await
+    .map_err(|err| ssh_error(url, err))?;
+  let sftp = SftpSession::new(channel.into_stream())
+    .await
+    .map_err(|err| sftp_error(url, err))?;
+
+  let metadata = sftp
+    .metadata(path.as_str())
+    .await
+    .map_err(|err| sftp_error(url, err))?;
+  let mut file = sftp
+    .open(path.as_str())
+    .await
+    .map_err(|err| sftp_error(url, err))?;
+
+  // SFTP 总是支持从任意偏移读取
+  if offset > 0 {
+    file.seek(SeekFrom::Start(offset)).await?;
+  }
+
+  Ok(SourceFile {
+    size: metadata.size,
+    modified: metadata
+      .mtime
+      .map(|mtime| httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(mtime.into()))),
+    etag: None,
+    resumed: offset > 0,
+    stream: into_stream(session, sftp, file).boxed(),
+  })
+}
+
+/// Reads the remote file as a stream of chunks, closing the session at the end.
+fn into_stream(
+  session: client::Handle<HostKeyCheck>,
+  sftp: SftpSession,
+  file: russh_sftp::client::fs::File,
+) -> impl Stream<Item = Result<Bytes, ProgressDownloadError>> {
+  futures::stream::try_unfold(Some((session, sftp, file)), |state| async move {
+    let Some((session, sftp, mut file)) = state else {
+      return Ok(None);
+    };
+
+    let mut buffer = BytesMut::zeroed(READ_BUFFER_SIZE);
+    let read = file.read(&mut buffer).await?;
+    if read > 0 {
+      buffer.truncate(read);
+      return Ok(Some((buffer.freeze(), Some((session, sftp, file)))));
+    }
+
+    drop(file);
+    let _ = sftp.close().await;
+    let _ = session
+      .disconnect(russh::Disconnect::ByApplication, "", "")
+      .await;
+    Ok(None)
+  })
+}
+
+/// The configured private key, or the first default key that exists.
+fn private_key_path(options: &SftpOptions) -> Option<PathBuf> {
+  if let Some(path) = &options.private_key {
+    return Some(path.clone());
+  }
+
+  let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+  let ssh_dir = PathBuf::from(home).join(".ssh");
+  DEFAULT_KEYS
+    .iter()
+    .map(|name| ssh_dir.join(name))
+    .find(|path| path.is_file())
+}
+
+/// Converts an SSH error. Rejected host keys and logins are permanent, other errors are
+/// treated as connection failures and retried.
+fn ssh_error(url: &str, err: russh::Error) -> ProgressDownloadError {
+  match err {
+    russh::Error::IO(err) => err.into(),
+    russh::Error::UnknownKey
+    | russh::Error::KeyChanged { .. }
+    | russh::Error::NotAuthenticated
+    | russh::Error::NoAuthMethod
+    | russh::Error::Keys(_) => ProgressDownloadError::Sftp {
+      url: url.to_string(),
+      message: err.to_string(),
+    },
+    err => std::io::Error::new(std::io::ErrorKind::ConnectionAborted, err).into(),
+  }
+}
+
+/// Converts an SFTP error. Missing files and denied permissions are permanent.
+fn sftp_error(url: &str, err: russh_sftp::client::error::Error) -> ProgressDownloadError {
+  match err {
+    russh_sftp::client::error::Error::Status(status)
+      if matches!(
+        status.status_code,
+        StatusCode::NoSuchFile | StatusCode::PermissionDenied | StatusCode::OpUnsupported
+      ) =>
+    {
+      ProgressDownloadError::Sftp {
+        url: url.to_string(),
+        message: status.error_message,
+      }
+    }
+    err => std::io::Error::new(std::io::ErrorKind::ConnectionAborted, err).into(),
+  }
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Stops the downloads of a [`RobustDownloader`](crate::RobustDownloader) gracefully.
+///
+/// Pass a clone of the handle to the downloader's `shutdown` option and keep the other one
+/// to request the shutdown, or let [`shutdown_on_ctrl_c`](Self::shutdown_on_ctrl_c) request
+/// it. Items that have not started yet are not started anymore. Running downloads write the
+/// chunks received so far to their temp file and keep its resume state, so the next run
+/// continues where they stopped. Every interrupted item fails with
+/// [`ProgressDownloadError::Interrupted`](crate::ProgressDownloadError::Interrupted), see
+/// [`DownloadResult::is_interrupted`](crate::DownloadResult::is_interrupted).
+///
+/// # Example
+///
+/// ```rust
+/// use robust_downloader::{RobustDownloader, ShutdownHandle};
+/// async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let shutdown = ShutdownHandle::new();
+/// shutdown.shutdown_on_ctrl_c();
+///
+/// let downloader = RobustDownloader::builder()
+///   .shutdown(shutdown.clone())
+///   .build();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+  requested: Arc<watch::Sender<bool>>,
+}
+
+impl Default for ShutdownHandle {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ShutdownHandle {
+  pub fn new() -> Self {
+    Self {
+      requested: Arc::new(watch::Sender::new(false)),
+    }
+  }
+
+  /// Requests the shutdown. Running downloads stop after their current chunk.
+  pub fn shutdown(&self) {
+    self.requested.send_replace(true);
+  }
+
+  pub fn is_shutdown(&self) -> bool {
+    *self.requested.borrow()
+  }
+
+  /// Requests the shutdown once the process receives Ctrl-C.
+  ///
+  /// Must be called within a Tokio runtime. Once the handler is installed, Ctrl-C no
+  /// longer terminates the process.
+  pub fn shutdown_on_ctrl_c(&self) {
+    let handle = self.clone();
+    tokio::spawn(async move {
+      if tokio::signal::ctrl_c().await.is_ok() {
+        handle.shutdown();
+      }
+    });
+  }
+
+  /// Completes once the shutdown is requested.
+  pub(crate) async fn requested(&self) {
+    let _ = self
+      .requested
+      .subscribe()
+      .wait_for(|requested| *requested)
+      .await;
+  }
+}
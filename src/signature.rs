@@ -0,0 +1,99 @@
+#[cfg(feature = "minisign")]
+use std::path::Path;
+
+/// The detached [minisign](https://jedisct1.github.io/minisign/) signature of a download,
+/// verified with the downloader's `signature_key` before the file is moved to its target.
+///
+/// Requires the `minisign` feature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signature {
+  /// The content of the `.minisig` file.
+  Inline(String),
+  /// URL of the `.minisig` file, requested once the file has been downloaded.
+  Url(String),
+}
+
+/// Checks that `signature` is a valid signature of the file at `path` made with the minisign
+/// `public_key`, given as its base64 line or the whole `.pub` file. Fails with the reason
+/// otherwise.
+#[cfg(feature = "minisign")]
+pub(crate) async fn verify(public_key: &str, signature: &str, path: &Path) -> Result<(), String> {
+  use minisign_verify::{PublicKey, Signature};
+  use tokio::io::AsyncReadExt;
+
+  let public_key = match public_key.trim() {
+    key if key.contains('\n') => PublicKey::decode(key),
+    key => PublicKey::from_base64(key),
+  }
+  .map_err(|err| format!("invalid public key: {err}"))?;
+  let signature =
+    Signature::decode(signature).map_err(|err| format!("invalid signature: {err}"))?;
+
+  let mut file = tokio::fs::File::open(path)
+    .await
+    .map_err(|err| err.to_string())?;
+  // 旧版 minisign 直接对文件内容签名，只能整体读入后校验
+  let mut verifier = match public_key.verify_stream(&signature) {
+    Ok(verifier) => verifier,
+    Err(minisign_verify::Error::UnsupportedLegacyMode) => {
+      let mut content = Vec::new();
+      file
+        .read_to_end(&mut content)
+        .await
+        .map_err(|err| err.to_string())?;
+      return public_key
+        .verify(&content, &signature, true)
+        .map_err(|err| err.to_string());
+    }
+    Err(err) => return Err(err.to_string()),
+  };
+
+  let mut buffer = vec![0; 64 * 1024];
+  loop {
+    let read = file
+      .read(&mut buffer)
+      .await
+      .map_err(|err| err.to_string())?;
+    if read == 0 {
+      break;
+    }
+    verifier.update(&buffer[..read]);
+  }
+  verifier.finalize().map_err(|err| err.to_string())
+}
+
+#[cfg(all(test, feature = "minisign"))]
+mod tests {
+  use super::*;
+
+  /// minisign-verify 中的测试公钥，及其对内容 `test` 的签名和旧版签名
+  const PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+  const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==";
+  const LEGACY_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=
+trusted comment: timestamp:1555779966\tfile:test
+QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==";
+
+  #[tokio::test]
+  async fn test_verify() {
+    let dir = std::env::temp_dir().join(format!(
+      "robust_downloader_signature_{}",
+      std::process::id()
+    ));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let path = dir.join("test");
+
+    tokio::fs::write(&path, b"test").await.unwrap();
+    verify(PUBLIC_KEY, SIGNATURE, &path).await.unwrap();
+    verify(PUBLIC_KEY, LEGACY_SIGNATURE, &path).await.unwrap();
+
+    tokio::fs::write(&path, b"tampered").await.unwrap();
+    assert!(verify(PUBLIC_KEY, SIGNATURE, &path).await.is_err());
+    assert!(verify("RWQ", SIGNATURE, &path).await.is_err());
+
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+  }
+}
@@ -0,0 +1,191 @@
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use futures::{FutureExt, future::BoxFuture};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::persist;
+
+/// Storage a download is written to chunk by chunk, then finalized, see
+/// [`RobustDownloader::download_to_sink`](crate::RobustDownloader::download_to_sink).
+///
+/// Chunks arrive in order and are never repeated: when an attempt fails, the next one
+/// continues after the data already written. Implementations are provided for
+/// `Vec<u8>`, local files with [`FileSink`] and, with the `s3` feature, S3 multipart
+/// uploads with [`S3UploadSink`](crate::S3UploadSink).
+///
+/// # Example
+///
+/// ```rust
+/// use bytes::Bytes;
+/// use futures::{FutureExt, future::BoxFuture};
+/// use robust_downloader::StorageSink;
+///
+/// /// Counts the bytes of a download without storing them.
+/// #[derive(Default)]
+/// struct Counter(u64);
+///
+/// impl StorageSink for Counter {
+///   fn write(&mut self, chunk: Bytes) -> BoxFuture<'_, std::io::Result<()>> {
+///     self.0 += chunk.len() as u64;
+///     async { Ok(()) }.boxed()
+///   }
+///
+///   fn finalize(&mut self) -> BoxFuture<'_, std::io::Result<()>> {
+///     async { Ok(()) }.boxed()
+///   }
+/// }
+/// ```
+pub trait StorageSink: Send {
+  /// Appends `chunk` to the data written so far.
+  fn write(&mut self, chunk: Bytes) -> BoxFuture<'_, std::io::Result<()>>;
+
+  /// Makes the data available once the whole body was written, e.g. by moving a file
+  /// into place or completing an upload.
+  fn finalize(&mut self) -> BoxFuture<'_, std::io::Result<()>>;
+
+  /// Discards the data after the download failed permanently.
+  /// Does nothing by default.
+  fn abort(&mut self) -> BoxFuture<'_, std::io::Result<()>> {
+    async { Ok(()) }.boxed()
+  }
+}
+
+impl StorageSink for Vec<u8> {
+  fn write(&mut self, chunk: Bytes) -> BoxFuture<'_, std::io::Result<()>> {
+    self.extend_from_slice(&chunk);
+    async { Ok(()) }.boxed()
+  }
+
+  fn finalize(&mut self) -> BoxFuture<'_, std::io::Result<()>> {
+    async { Ok(()) }.boxed()
+  }
+
+  fn abort(&mut self) -> BoxFuture<'_, std::io::Result<()>> {
+    self.clear();
+    async { Ok(()) }.boxed()
+  }
+}
+
+/// Writes a download to `<target>.part`, moved to the target once finalized.
+#[derive(Debug)]
+pub struct FileSink {
+  target: PathBuf,
+  temp_file: PathBuf,
+  file: Option<tokio::fs::File>,
+}
+
+impl FileSink {
+  pub fn new(target: impl Into<PathBuf>) -> Self {
+    let target = target.into();
+    let mut temp_name = target.file_name().unwrap_or_default().to_owned();
+    temp_name.push(".part");
+    Self {
+      temp_file: target.with_file_name(temp_name),
+      target,
+      file: None,
+    }
+  }
+
+  /// The path the file is saved to.
+  pub fn target(&self) -> &Path {
+    &self.target
+  }
+}
+
+impl StorageSink for FileSink {
+  fn write(&mut self, chunk: Bytes) -> BoxFuture<'_, std::io::Result<()>> {
+    async move {
+      let file = match &mut self.file {
+        Some(file) => file,
+        // 第一次写入时才创建临时文件，覆盖上次留下的内容
+        None => self
+          .file
+          .insert(tokio::fs::File::create(&self.temp_file).await?),
+      };
+      file.write_all(&chunk).await
+    }
+    .boxed()
+  }
+
+  fn finalize(&mut self) -> BoxFuture<'_, std::io::Result<()>> {
+    async move {
+      let file = match self.file.take() {
+        Some(file) => file,
+        // 空文件不会收到任何数据块
+        None => tokio::fs::File::create(&self.temp_file).await?,
+      };
+      file.sync_all().await?;
+      drop(file);
+      persist::persist(&self.temp_file, &self.target, false).await?;
+      Ok(())
+    }
+    .boxed()
+  }
+
+  fn abort(&mut self) -> BoxFuture<'_, std::io::Result<()>> {
+    async move {
+      if self.file.take().is_some() {
+        tokio::fs::remove_file(&self.temp_file).await?;
+      }
+      Ok(())
+    }
+    .boxed()
+  }
+}
+
+/// Where the runner streams a body: an [`AsyncWrite`] or a [`StorageSink`].
+pub(crate) trait ChunkWriter {
+  async fn write_chunk(&mut self, chunk: Bytes) -> std::io::Result<()>;
+
+  async fn flush_chunks(&mut self) -> std::io::Result<()>;
+}
+
+impl<W: AsyncWrite + Unpin> ChunkWriter for W {
+  async fn write_chunk(&mut self, chunk: Bytes) -> std::io::Result<()> {
+    self.write_all(&chunk).await
+  }
+
+  async fn flush_chunks(&mut self) -> std::io::Result<()> {
+    self.flush().await
+  }
+}
+
+/// Writes chunks into a [`StorageSink`].
+pub(crate) struct SinkWriter<'a, S: ?Sized>(pub &'a mut S);
+
+impl<S: StorageSink + ?Sized> ChunkWriter for SinkWriter<'_, S> {
+  async fn write_chunk(&mut self, chunk: Bytes) -> std::io::Result<()> {
+    self.0.write(chunk).await
+  }
+
+  async fn flush_chunks(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_file_sink() {
+    let dir = std::env::temp_dir().join(format!("robust_downloader_sink_{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let target = dir.join("file.txt");
+
+    let mut sink = FileSink::new(&target);
+    sink.write(Bytes::from_static(b"hello ")).await.unwrap();
+    sink.write(Bytes::from_static(b"world")).await.unwrap();
+    assert!(!target.exists());
+    sink.finalize().await.unwrap();
+    assert_eq!(tokio::fs::read(&target).await.unwrap(), b"hello world");
+
+    let mut sink = FileSink::new(dir.join("aborted.txt"));
+    sink.write(Bytes::from_static(b"data")).await.unwrap();
+    sink.abort().await.unwrap();
+    assert!(!dir.join("aborted.txt.part").exists());
+
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+}
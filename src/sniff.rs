@@ -0,0 +1,92 @@
+use std::path::Path;
+
+use reqwest::header::{CONTENT_TYPE, HeaderMap};
+
+/// Number of bytes at the start of a file inspected to recognize its content.
+pub(crate) const SNIFF_LEN: usize = 512;
+
+/// Whether `target` is meant to hold an HTML page, so HTML content is expected.
+pub(crate) fn is_html_target(target: &Path) -> bool {
+  target.extension().is_some_and(|extension| {
+    ["html", "htm", "xhtml"]
+      .iter()
+      .any(|known| extension.eq_ignore_ascii_case(known))
+  })
+}
+
+/// Whether the response declares an HTML body in its `Content-Type`.
+pub(crate) fn is_html_response(headers: &HeaderMap) -> bool {
+  headers
+    .get(CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.split(';').next())
+    .is_some_and(|mime| {
+      let mime = mime.trim();
+      mime.eq_ignore_ascii_case("text/html") || mime.eq_ignore_ascii_case("application/xhtml+xml")
+    })
+}
+
+/// Whether `head`, the first bytes of a file, look like an HTML page.
+pub(crate) fn looks_like_html(head: &[u8]) -> bool {
+  // 跳过 UTF-8 BOM 和开头的空白
+  let head = head.strip_prefix(b"\xef\xbb\xbf").unwrap_or(head);
+  let start = head
+    .iter()
+    .position(|byte| !byte.is_ascii_whitespace())
+    .unwrap_or(head.len());
+  let head = &head[start..];
+
+  ["<!doctype html", "<html", "<head", "<body"]
+    .iter()
+    .any(|tag| head.len() >= tag.len() && head[..tag.len()].eq_ignore_ascii_case(tag.as_bytes()))
+}
+
+/// Checks the first bytes of a downloaded file against the expected magic number and,
+/// if `reject_html` is set, against HTML markup. Returns why the content was rejected.
+pub(crate) fn check_content(
+  head: &[u8],
+  expected_magic: Option<&[u8]>,
+  reject_html: bool,
+) -> Result<(), String> {
+  if let Some(magic) = expected_magic {
+    if !head.starts_with(magic) {
+      let found = &head[..head.len().min(magic.len())];
+      return Err(format!(
+        "expected the file to start with {}, found {}",
+        hex(magic),
+        hex(found)
+      ));
+    }
+  }
+
+  if reject_html && looks_like_html(head) {
+    return Err("received an HTML page instead of the file".to_string());
+  }
+
+  Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_check_content() {
+    let page = b"\xef\xbb\xbf\n  <!DOCTYPE HTML><html><body>Not Found</body></html>";
+    assert!(looks_like_html(page));
+    assert!(!looks_like_html(b"PK\x03\x04"));
+    assert!(check_content(page, None, false).is_ok());
+    assert!(check_content(page, None, true).is_err());
+
+    assert!(check_content(b"PK\x03\x04rest", Some(b"PK\x03\x04"), true).is_ok());
+    assert_eq!(
+      check_content(b"<h", Some(b"PK\x03\x04"), false),
+      Err("expected the file to start with 504b0304, found 3c68".to_string())
+    );
+    assert!(is_html_target(Path::new("docs/index.HTML")));
+  }
+}
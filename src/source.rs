@@ -0,0 +1,137 @@
+use std::{fmt, sync::Arc};
+
+use bytes::Bytes;
+use futures::{future::BoxFuture, stream::BoxStream};
+
+use crate::err::ProgressDownloadError;
+
+/// A file opened for download by a [`DownloadSource`].
+pub struct SourceFile {
+  /// Size of the whole file, if the source knows it.
+  pub size: Option<u64>,
+  /// Modification time of the file as an HTTP date, if the source knows it.
+  pub modified: Option<String>,
+  /// Entity tag of the file, if the source has one.
+  pub etag: Option<String>,
+  /// Whether the stream starts at the requested offset rather than at the beginning.
+  pub resumed: bool,
+  pub stream: BoxStream<'static, Result<Bytes, ProgressDownloadError>>,
+}
+
+impl fmt::Debug for SourceFile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("SourceFile")
+      .field("size", &self.size)
+      .field("modified", &self.modified)
+      .field("etag", &self.etag)
+      .field("resumed", &self.resumed)
+      .finish_non_exhaustive()
+  }
+}
+
+/// Downloads the URLs it supports instead of the built-in protocols, see the `sources`
+/// option.
+///
+/// The built-in protocols are not sources themselves: HTTP downloads keep using the
+/// downloader's own client with segmented downloads, the redirect policy and `If-Range`
+/// checks, and `ftp`, `sftp`, `s3`, `file` and `data` URLs their own implementations. A
+/// source supporting such a URL replaces them for it.
+///
+/// Downloads from a source go through the same pipeline as the others: retries, progress,
+/// `transforms`, integrity and signature checks and moving the file into place. Failed
+/// attempts resume from the size of the temp file if the source can open the file at that
+/// offset and the size, modification time and entity tag it reports have not changed.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use bytes::Bytes;
+/// use futures::{FutureExt, StreamExt, future::BoxFuture};
+/// use robust_downloader::{DownloadSource, ProgressDownloadError, RobustDownloader, SourceFile};
+///
+/// // 测试用的数据源，`mem://` URL 的内容是重复的路径
+/// struct Memory;
+///
+/// impl DownloadSource for Memory {
+///   fn supports(&self, url: &str) -> bool {
+///     url.starts_with("mem://")
+///   }
+///
+///   fn open(&self, url: &str, offset: u64) -> BoxFuture<'_, Result<SourceFile, ProgressDownloadError>> {
+///     let content = Bytes::from(url["mem://".len()..].repeat(1000));
+///     async move {
+///       let size = content.len() as u64;
+///       let rest = content.slice(offset.min(size) as usize..);
+///       Ok(SourceFile {
+///         size: Some(size),
+///         modified: None,
+///         etag: None,
+///         resumed: true,
+///         stream: futures::stream::iter([Ok(rest)]).boxed(),
+///       })
+///     }
+///     .boxed()
+///   }
+/// }
+///
+/// let downloader = RobustDownloader::builder()
+///   .sources(vec![Arc::new(Memory)])
+///   .build();
+/// ```
+pub trait DownloadSource: Send + Sync {
+  /// Whether this source downloads `url`.
+  fn supports(&self, url: &str) -> bool;
+
+  /// Opens the file at `url` for download from `offset` on. A source that cannot start
+  /// at the offset returns the whole file with `resumed` set to false.
+  fn open(
+    &self,
+    url: &str,
+    offset: u64,
+  ) -> BoxFuture<'_, Result<SourceFile, ProgressDownloadError>>;
+}
+
+impl fmt::Debug for dyn DownloadSource + '_ {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("DownloadSource")
+  }
+}
+
+/// The first of `sources` that supports `url`.
+pub(crate) fn find<'a>(
+  sources: &'a [Arc<dyn DownloadSource>],
+  url: &str,
+) -> Option<&'a Arc<dyn DownloadSource>> {
+  sources.iter().find(|source| source.supports(url))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_find() {
+    struct Memory;
+
+    impl DownloadSource for Memory {
+      fn supports(&self, url: &str) -> bool {
+        url.starts_with("mem://")
+      }
+
+      fn open(
+        &self,
+        _url: &str,
+        _offset: u64,
+      ) -> BoxFuture<'_, Result<SourceFile, ProgressDownloadError>> {
+        unimplemented!()
+      }
+    }
+
+    let sources: Vec<Arc<dyn DownloadSource>> = vec![Arc::new(Memory)];
+    assert!(find(&sources, "mem://file.bin").is_some());
+    assert!(find(&sources, "https://example.com/file.bin").is_none());
+    assert!(find(&[], "mem://file.bin").is_none());
+  }
+}
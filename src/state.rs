@@ -0,0 +1,173 @@
+use std::{
+  path::{Path, PathBuf},
+  time::SystemTime,
+};
+
+use reqwest::header::{ETAG, HeaderMap, LAST_MODIFIED};
+
+/// Metadata of the remote file a local file was downloaded from.
+///
+/// Persisted next to partially downloaded temp files so a download can be resumed safely
+/// after the process restarts, and next to finished files to detect remote changes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResumeState {
+  pub url: String,
+  /// The URL the data was downloaded from if it is not `url`, e.g. a mirror.
+  pub source: Option<String>,
+  pub etag: Option<String>,
+  pub last_modified: Option<String>,
+  pub total_size: Option<u64>,
+  /// The part of the remote file the data is, if it is not the whole file, see
+  /// [`RemotePart::key`](crate::RemotePart).
+  pub part: Option<String>,
+}
+
+impl ResumeState {
+  pub fn from_headers(url: &str, headers: &HeaderMap, total_size: Option<u64>) -> Self {
+    let header = |name| {
+      headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+    };
+
+    Self {
+      url: url.to_string(),
+      source: None,
+      etag: header(ETAG),
+      last_modified: header(LAST_MODIFIED),
+      total_size,
+      part: None,
+    }
+  }
+
+  /// The URL the data was downloaded from.
+  pub fn source(&self) -> &str {
+    self.source.as_deref().unwrap_or(&self.url)
+  }
+
+  /// The modification time of the remote file, if it is known.
+  pub fn modified(&self) -> Option<SystemTime> {
+    httpdate::parse_http_date(self.last_modified.as_deref()?).ok()
+  }
+
+  /// The state file belonging to `file`.
+  pub fn path(file: &Path) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(".state");
+    PathBuf::from(path)
+  }
+
+  pub async fn load(file: &Path) -> Option<Self> {
+    let content = tokio::fs::read_to_string(Self::path(file)).await.ok()?;
+
+    let mut state = Self::default();
+    for line in content.lines() {
+      let Some((key, value)) = line.split_once('=') else {
+        continue;
+      };
+      match key {
+        "url" => state.url = value.to_string(),
+        "source" => state.source = Some(value.to_string()),
+        "etag" => state.etag = Some(value.to_string()),
+        "last_modified" => state.last_modified = Some(value.to_string()),
+        "total_size" => state.total_size = value.parse().ok(),
+        "part" => state.part = Some(value.to_string()),
+        _ => {}
+      }
+    }
+
+    Some(state)
+  }
+
+  pub async fn save(&self, file: &Path) -> std::io::Result<()> {
+    let mut content = format!("url={}\n", self.url);
+    if let Some(source) = &self.source {
+      content.push_str(&format!("source={}\n", source));
+    }
+    if let Some(etag) = &self.etag {
+      content.push_str(&format!("etag={}\n", etag));
+    }
+    if let Some(last_modified) = &self.last_modified {
+      content.push_str(&format!("last_modified={}\n", last_modified));
+    }
+    if let Some(total_size) = self.total_size {
+      content.push_str(&format!("total_size={}\n", total_size));
+    }
+    if let Some(part) = &self.part {
+      content.push_str(&format!("part={}\n", part));
+    }
+
+    tokio::fs::write(Self::path(file), content).await
+  }
+
+  pub async fn remove(file: &Path) {
+    let _ = tokio::fs::remove_file(Self::path(file)).await;
+  }
+
+  /// The value for an `If-Range` header: a strong ETag, or else the Last-Modified date.
+  pub fn if_range(&self) -> Option<&str> {
+    self
+      .etag
+      .as_deref()
+      .filter(|etag| !etag.starts_with("W/"))
+      .or(self.last_modified.as_deref())
+  }
+
+  /// Whether a response describes the same remote file this state was recorded for.
+  pub fn matches(&self, other: &Self) -> bool {
+    fn same<T: PartialEq>(a: &Option<T>, b: &Option<T>) -> bool {
+      match (a, b) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+      }
+    }
+
+    same(&self.etag, &other.etag)
+      && same(&self.last_modified, &other.last_modified)
+      && self.matches_size(other)
+  }
+
+  /// Whether a response from another server reports the same size as the remote file
+  /// this state was recorded for. Servers generate their own ETags and modification
+  /// times, so only the size can be compared.
+  pub fn matches_size(&self, other: &Self) -> bool {
+    match (self.total_size, other.total_size) {
+      (Some(size), Some(other)) => size == other,
+      _ => true,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_save_and_load() {
+    let tmp_file = std::env::temp_dir().join("robust_downloader_state_test.bin");
+    let state = ResumeState {
+      url: "https://example.com/file.bin".to_string(),
+      source: Some("https://mirror.example.com/file.bin".to_string()),
+      etag: Some("\"abc\"".to_string()),
+      last_modified: None,
+      total_size: Some(42),
+      part: Some("bytes=0-99".to_string()),
+    };
+
+    state.save(&tmp_file).await.unwrap();
+    assert_eq!(ResumeState::load(&tmp_file).await, Some(state.clone()));
+
+    ResumeState::remove(&tmp_file).await;
+    assert_eq!(ResumeState::load(&tmp_file).await, None);
+
+    let changed = ResumeState {
+      total_size: Some(43),
+      ..state.clone()
+    };
+    assert!(!state.matches(&changed));
+    assert!(!state.matches_size(&changed));
+    assert_eq!(state.source(), "https://mirror.example.com/file.bin");
+    assert_eq!(state.if_range(), Some("\"abc\""));
+  }
+}
@@ -0,0 +1,323 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use crate::{
+  err::ProgressDownloadError,
+  reporter::{DownloadInfo, ProgressReporter, RetryAttempt},
+};
+
+/// Minimum time between two samples of the current speed.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Weight of the newest sample in the moving average of the current speed.
+const SMOOTHING: f64 = 0.3;
+
+/// A snapshot of the progress and speed of one download, or of all downloads combined.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DownloadStats {
+  /// Bytes downloaded so far, including bytes resumed from a previous run.
+  pub downloaded: u64,
+  /// Total size, if the server reported it.
+  pub total: Option<u64>,
+  /// Current speed in bytes per second, as a moving average.
+  pub speed: f64,
+  /// Average speed in bytes per second since the download started.
+  pub average_speed: f64,
+  /// Time since the download started, or how long it took once finished.
+  pub elapsed: Duration,
+}
+
+impl DownloadStats {
+  /// Bytes left to download, if the total size is known.
+  pub fn remaining(&self) -> Option<u64> {
+    self
+      .total
+      .map(|total| total.saturating_sub(self.downloaded))
+  }
+
+  /// Estimated time until the download completes at the current speed.
+  pub fn eta(&self) -> Option<Duration> {
+    let remaining = self.remaining()?;
+    if remaining == 0 {
+      return Some(Duration::ZERO);
+    }
+    (self.speed > 0.0).then(|| Duration::from_secs_f64(remaining as f64 / self.speed))
+  }
+}
+
+/// A [`ProgressReporter`] that records live statistics of every download, which can be
+/// polled while the downloads run.
+///
+/// Events are forwarded to an optional inner reporter, so statistics can be collected
+/// alongside the default progress bars.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use robust_downloader::{ProgressBarReporter, RobustDownloader, StatsReporter};
+///
+/// let stats = Arc::new(StatsReporter::wrap(Arc::new(ProgressBarReporter::default())));
+/// let downloader = RobustDownloader::builder().reporter(stats.clone()).build();
+///
+/// // 在另一个任务中定期读取
+/// let total = stats.total();
+/// println!("{:.0} B/s, eta {:?}", total.speed, total.eta());
+/// ```
+#[derive(Debug, Default)]
+pub struct StatsReporter {
+  inner: Option<Arc<dyn ProgressReporter>>,
+  downloads: Mutex<HashMap<usize, Tracked>>,
+}
+
+/// 单个下载的统计状态
+#[derive(Debug)]
+struct Tracked {
+  started_at: Instant,
+  finished_at: Option<Instant>,
+  downloaded: u64,
+  total: Option<u64>,
+  /// 本次运行实际传输的字节数，不含续传前已有的数据
+  transferred: u64,
+  sampled_at: Instant,
+  sampled_bytes: u64,
+  speed: f64,
+}
+
+impl Tracked {
+  fn new(now: Instant) -> Self {
+    Self {
+      started_at: now,
+      finished_at: None,
+      downloaded: 0,
+      total: None,
+      transferred: 0,
+      sampled_at: now,
+      sampled_bytes: 0,
+      speed: 0.0,
+    }
+  }
+
+  fn update(&mut self, downloaded: u64, total: Option<u64>, now: Instant) {
+    self.transferred += downloaded.saturating_sub(self.downloaded);
+    self.downloaded = downloaded;
+    self.total = total;
+
+    let interval = now.duration_since(self.sampled_at);
+    if interval >= SAMPLE_INTERVAL {
+      let rate = (self.transferred - self.sampled_bytes) as f64 / interval.as_secs_f64();
+      self.speed = if self.speed > 0.0 {
+        SMOOTHING * rate + (1.0 - SMOOTHING) * self.speed
+      } else {
+        rate
+      };
+      self.sampled_at = now;
+      self.sampled_bytes = self.transferred;
+    }
+  }
+
+  fn finish(&mut self, now: Instant) {
+    self.finished_at.get_or_insert(now);
+    self.speed = 0.0;
+  }
+
+  fn stats(&self, now: Instant) -> DownloadStats {
+    let elapsed = self
+      .finished_at
+      .unwrap_or(now)
+      .duration_since(self.started_at);
+    DownloadStats {
+      downloaded: self.downloaded,
+      total: self.total,
+      speed: self.speed,
+      average_speed: average_speed(self.transferred, elapsed),
+      elapsed,
+    }
+  }
+}
+
+impl StatsReporter {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Records statistics and forwards every event to `inner`.
+  pub fn wrap(inner: Arc<dyn ProgressReporter>) -> Self {
+    Self {
+      inner: Some(inner),
+      downloads: Mutex::default(),
+    }
+  }
+
+  /// Statistics of the download with the given index, once it has started.
+  pub fn get(&self, index: usize) -> Option<DownloadStats> {
+    let now = Instant::now();
+    self
+      .downloads
+      .lock()
+      .unwrap()
+      .get(&index)
+      .map(|tracked| tracked.stats(now))
+  }
+
+  /// Statistics of every started download, ordered by index.
+  pub fn all(&self) -> Vec<(usize, DownloadStats)> {
+    let now = Instant::now();
+    let mut all = self
+      .downloads
+      .lock()
+      .unwrap()
+      .iter()
+      .map(|(index, tracked)| (*index, tracked.stats(now)))
+      .collect::<Vec<_>>();
+    all.sort_by_key(|(index, _)| *index);
+    all
+  }
+
+  /// Combined statistics of all started downloads.
+  ///
+  /// The total size is only known if it is known for every download.
+  pub fn total(&self) -> DownloadStats {
+    let now = Instant::now();
+    let downloads = self.downloads.lock().unwrap();
+
+    let started_at = downloads.values().map(|tracked| tracked.started_at).min();
+    // 仍有下载进行中时按当前时间计算耗时
+    let finished_at = downloads
+      .values()
+      .map(|tracked| tracked.finished_at)
+      .collect::<Option<Vec<_>>>()
+      .and_then(|finished_at| finished_at.into_iter().max());
+    let elapsed = started_at
+      .map(|started_at| finished_at.unwrap_or(now).duration_since(started_at))
+      .unwrap_or_default();
+    let transferred = downloads
+      .values()
+      .map(|tracked| tracked.transferred)
+      .sum::<u64>();
+
+    DownloadStats {
+      downloaded: downloads.values().map(|tracked| tracked.downloaded).sum(),
+      total: downloads.values().map(|tracked| tracked.total).sum(),
+      speed: downloads.values().map(|tracked| tracked.speed).sum(),
+      average_speed: average_speed(transferred, elapsed),
+      elapsed,
+    }
+  }
+
+  fn track(&self, info: &DownloadInfo, update: impl FnOnce(&mut Tracked, Instant)) {
+    let now = Instant::now();
+    let mut downloads = self.downloads.lock().unwrap();
+    let tracked = downloads
+      .entry(info.index)
+      .or_insert_with(|| Tracked::new(now));
+    update(tracked, now);
+  }
+}
+
+impl ProgressReporter for StatsReporter {
+  fn on_batch_started(&self, files: usize, total_size: Option<u64>) {
+    if let Some(inner) = &self.inner {
+      inner.on_batch_started(files, total_size);
+    }
+  }
+
+  fn on_started(&self, info: &DownloadInfo, downloaded: u64, total: Option<u64>) {
+    self.track(info, |tracked, _| {
+      // 重新开始的尝试可能从更小的偏移继续，不计入传输量
+      tracked.downloaded = downloaded;
+      tracked.total = total;
+    });
+    if let Some(inner) = &self.inner {
+      inner.on_started(info, downloaded, total);
+    }
+  }
+
+  fn on_bytes_received(&self, info: &DownloadInfo, downloaded: u64, total: Option<u64>) {
+    self.track(info, |tracked, now| tracked.update(downloaded, total, now));
+    if let Some(inner) = &self.inner {
+      inner.on_bytes_received(info, downloaded, total);
+    }
+  }
+
+  fn on_bytes_decoded(&self, info: &DownloadInfo, decoded: u64) {
+    if let Some(inner) = &self.inner {
+      inner.on_bytes_decoded(info, decoded);
+    }
+  }
+
+  fn on_retrying(&self, info: &DownloadInfo, error: &ProgressDownloadError, delay: Duration) {
+    if let Some(inner) = &self.inner {
+      inner.on_retrying(info, error, delay);
+    }
+  }
+
+  fn on_retry_scheduled(&self, info: &DownloadInfo, retry: &RetryAttempt<'_>) {
+    if let Some(inner) = &self.inner {
+      inner.on_retry_scheduled(info, retry);
+    }
+  }
+
+  fn on_extracting(&self, info: &DownloadInfo, extracted: u64, total: u64) {
+    if let Some(inner) = &self.inner {
+      inner.on_extracting(info, extracted, total);
+    }
+  }
+
+  fn on_finished(&self, info: &DownloadInfo) {
+    self.track(info, |tracked, now| tracked.finish(now));
+    if let Some(inner) = &self.inner {
+      inner.on_finished(info);
+    }
+  }
+
+  fn on_skipped(&self, info: &DownloadInfo) {
+    self.track(info, |tracked, now| tracked.finish(now));
+    if let Some(inner) = &self.inner {
+      inner.on_skipped(info);
+    }
+  }
+
+  fn on_failed(&self, info: &DownloadInfo, error: &ProgressDownloadError) {
+    self.track(info, |tracked, now| tracked.finish(now));
+    if let Some(inner) = &self.inner {
+      inner.on_failed(info, error);
+    }
+  }
+}
+
+fn average_speed(bytes: u64, elapsed: Duration) -> f64 {
+  if elapsed.is_zero() {
+    return 0.0;
+  }
+  bytes as f64 / elapsed.as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_speed_and_eta() {
+    let start = Instant::now();
+    let mut tracked = Tracked::new(start);
+    tracked.downloaded = 100;
+    tracked.update(100, Some(2100), start);
+    tracked.update(1100, Some(2100), start + Duration::from_secs(1));
+
+    let stats = tracked.stats(start + Duration::from_secs(1));
+    assert_eq!(stats.speed, 1000.0);
+    assert_eq!(stats.average_speed, 1000.0);
+    assert_eq!(stats.remaining(), Some(1000));
+    assert_eq!(stats.eta(), Some(Duration::from_secs(1)));
+
+    tracked.finish(start + Duration::from_secs(2));
+    let stats = tracked.stats(start + Duration::from_secs(5));
+    assert_eq!(stats.elapsed, Duration::from_secs(2));
+    assert_eq!(stats.eta(), None);
+  }
+}
@@ -0,0 +1,87 @@
+use std::{
+  path::{Path, PathBuf},
+  pin::Pin,
+  sync::atomic::{AtomicBool, Ordering},
+};
+
+use tokio::io::AsyncWrite;
+
+/// 下载内容写入标准输出后，原本输出到标准输出的进度改为输出到标准错误
+static STDOUT_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Marks stdout as receiving downloaded data for the rest of the process.
+pub(crate) fn take_stdout() {
+  STDOUT_TAKEN.store(true, Ordering::Relaxed);
+}
+
+/// Whether downloaded data is written to stdout, so progress must not be.
+pub(crate) fn stdout_taken() -> bool {
+  STDOUT_TAKEN.load(Ordering::Relaxed)
+}
+
+/// A target the body is streamed to as it arrives, instead of being saved to a
+/// temporary file and renamed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum StreamTarget {
+  /// The target `-`, standard output.
+  Stdout,
+  /// An existing named pipe.
+  Pipe(PathBuf),
+}
+
+impl StreamTarget {
+  /// The stream target `path` refers to, if it is `-` or a named pipe.
+  pub fn of(path: &Path) -> Option<Self> {
+    if path.as_os_str() == "-" {
+      return Some(Self::Stdout);
+    }
+    is_pipe(path).then(|| Self::Pipe(path.to_path_buf()))
+  }
+
+  /// Opens the target for writing. Opening a named pipe waits until it has a reader.
+  pub async fn open(&self) -> std::io::Result<Pin<Box<dyn AsyncWrite + Send>>> {
+    match self {
+      Self::Stdout => {
+        take_stdout();
+        Ok(Box::pin(tokio::io::stdout()))
+      }
+      Self::Pipe(path) => {
+        let file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+        Ok(Box::pin(file))
+      }
+    }
+  }
+}
+
+#[cfg(unix)]
+fn is_pipe(path: &Path) -> bool {
+  use std::os::unix::fs::FileTypeExt;
+
+  // 只查询一次文件类型，不必放到阻塞线程中
+  std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+#[cfg(windows)]
+fn is_pipe(path: &Path) -> bool {
+  let path = path.as_os_str().to_string_lossy();
+  path
+    .get(..9)
+    .is_some_and(|prefix| prefix.eq_ignore_ascii_case(r"\\.\pipe\"))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_pipe(_path: &Path) -> bool {
+  false
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_stream_target() {
+    assert_eq!(StreamTarget::of(Path::new("-")), Some(StreamTarget::Stdout));
+    assert_eq!(StreamTarget::of(Path::new("./-")), None);
+    assert_eq!(StreamTarget::of(Path::new("Cargo.toml")), None);
+  }
+}
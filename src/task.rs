@@ -1,114 +1,2614 @@
-use std::{io::ErrorKind, path::Path, time::Duration};
-
-use futures::StreamExt;
-use hashery::Hashery;
-use indicatif::ProgressBar;
-use log::debug;
-use reqwest::IntoUrl;
-use tokio::io::AsyncWriteExt;
+use std::{
+  collections::HashMap,
+  io::SeekFrom,
+  path::{Path, PathBuf},
+  sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+  },
+  time::{Duration, Instant, SystemTime},
+};
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream::BoxStream};
+use reqwest::{
+  IntoUrl, Method, RequestBuilder, StatusCode, Version,
+  header::{
+    CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, HeaderMap, IF_MODIFIED_SINCE,
+    IF_NONE_MATCH, RETRY_AFTER,
+  },
+};
+use tokio::{
+  io::{AsyncReadExt, AsyncSeekExt},
+  sync::OwnedSemaphorePermit,
+};
+use tracing::{debug, warn};
 use typed_builder::TypedBuilder;
 
-use crate::{err::ProgressDownloadError, item::DownloadItem, tracker::DownloadTracker};
+#[cfg(feature = "remote-zip")]
+use crate::remote_zip;
+#[cfg(feature = "minisign")]
+use crate::signature::{self, Signature};
+#[cfg(feature = "zsync")]
+use crate::zsync;
+use crate::{
+  breaker::CircuitBreaker,
+  budget::{MemoryBudget, Reservation},
+  decode::{self, Decoder, DecoderTransform},
+  err::{ProgressDownloadError, TimeoutPhase},
+  file,
+  hasher::{HashAlgorithm, Hasher},
+  host::HostLimiter,
+  item::DownloadItem,
+  limiter::RateLimiter,
+  middleware::{self, RequestMiddleware},
+  part::RemotePart,
+  persist::{self, OverwritePolicy},
+  poison::{self, PoisonedMirrors},
+  prealloc,
+  redirect::RedirectPolicy,
+  refresh::UrlProvider,
+  remote::{self, S3Options, Scheme, SftpOptions},
+  reporter::{DownloadInfo, ProgressReporter},
+  retry,
+  shutdown::ShutdownHandle,
+  sink::ChunkWriter,
+  sniff,
+  source::{self, DownloadSource, SourceFile},
+  state::ResumeState,
+  tracker::DownloadTracker,
+  transform::{ChunkTransform, TransformChain, TransformFactory},
+  validator::ResponseValidator,
+  watchdog::{MinSpeed, SpeedWatchdog},
+  writer::TempWriter,
+};
+
+/// Number of bytes downloaded again when a partial download continues from another URL,
+/// to check that it serves the same content.
+const RESUME_OVERLAP: u64 = 64 * 1024;
+
+/// A response body, independent of the protocol it is received over.
+struct Body {
+  stream: BoxStream<'static, Result<Bytes, ProgressDownloadError>>,
+  /// Whether the body continues after the bytes downloaded before instead of starting over.
+  resumed: bool,
+  /// Number of bytes the body should contain, if known.
+  expected: Option<u64>,
+  /// Decompresses the body and applies the `transforms`, after which its bytes no longer
+  /// match the offsets of the remote file.
+  decoder: Option<TransformChain>,
+}
+
+/// How a response answers a request for the bytes from `start` to `end` of the file,
+/// or to its end if no `end` was requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RangeReply {
+  /// The body is the requested range.
+  Resumed,
+  /// The body is the whole file: the server answered 200, or 206 for the whole file.
+  Restarted,
+  /// 416 Range Not Satisfiable: the offset may be the end of the file, nothing is left
+  /// to send.
+  Unsatisfiable,
+  /// 206 for another range than the requested one, or with a `Content-Range` that
+  /// contradicts itself or the `Content-Length`. Writing it would splice unrelated data
+  /// into the file.
+  Mismatched,
+}
+
+impl RangeReply {
+  fn of(status: StatusCode, headers: &HeaderMap, start: u64, end: Option<u64>) -> Self {
+    match status {
+      StatusCode::RANGE_NOT_SATISFIABLE => return Self::Unsatisfiable,
+      StatusCode::PARTIAL_CONTENT => {}
+      _ => return Self::Restarted,
+    }
+
+    // 没有 Content-Range 的 206 无法判断内容的位置，按请求的范围处理
+    let Some(range) = ContentRange::parse(headers) else {
+      return Self::Resumed;
+    };
+    let content_length = headers
+      .get(CONTENT_LENGTH)
+      .and_then(|value| value.to_str().ok())
+      .and_then(|value| value.parse().ok());
+    if !range.is_valid(content_length) {
+      return Self::Mismatched;
+    }
+
+    let reaches_end = range.total.is_none_or(|total| range.end + 1 == total);
+    let ends_as_requested = match end {
+      Some(end) => range.end == end || (range.end < end && reaches_end),
+      None => reaches_end,
+    };
+    match range.start {
+      _ if range.start == start && ends_as_requested => Self::Resumed,
+      0 if reaches_end => Self::Restarted,
+      _ => Self::Mismatched,
+    }
+  }
+}
+
+/// A parsed `Content-Range: bytes start-end/total` header, with an unknown total as `*`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ContentRange {
+  start: u64,
+  end: u64,
+  total: Option<u64>,
+}
+
+impl ContentRange {
+  fn parse(headers: &HeaderMap) -> Option<Self> {
+    let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    Some(Self {
+      start: start.trim().parse().ok()?,
+      end: end.trim().parse().ok()?,
+      total: match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+      },
+    })
+  }
+
+  /// Whether the range lies within the file and has the length of the body.
+  fn is_valid(&self, content_length: Option<u64>) -> bool {
+    self.start <= self.end
+      && self.total.is_none_or(|total| self.end < total)
+      && content_length.is_none_or(|length| length == self.end - self.start + 1)
+  }
+}
+
+/// The last bytes of a remote ZIP archive, which hold its central directory.
+#[cfg(feature = "remote-zip")]
+struct Archive {
+  tail: Bytes,
+  size: u64,
+}
+
+/// A byte range `[start, end]` of the file downloaded by its own connection.
+#[derive(Debug, Clone)]
+struct Segment {
+  start: u64,
+  end: u64,
+  /// Bytes of this segment already flushed to disk.
+  written: u64,
+}
+
+impl Segment {
+  fn len(&self) -> u64 {
+    self.end - self.start + 1
+  }
+
+  fn is_complete(&self) -> bool {
+    self.written >= self.len()
+  }
+}
+
+#[derive(Debug, TypedBuilder)]
+pub struct DownloadTaskRunner<U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path>> {
+  #[builder]
+  client: reqwest::Client,
+  #[builder]
+  reporter: Arc<dyn ProgressReporter>,
+  #[builder]
+  info: DownloadInfo,
+
+  #[builder]
+  tmp_file: P,
+
+  #[builder]
+  item: DownloadItem<U, TP>,
+  /// 读写目标文件使用的路径，Windows 上超长的路径带有 `\\?\` 前缀
+  #[builder]
+  target_path: PathBuf,
+  /// 等待响应头的超时
+  #[builder]
+  timeout: Duration,
+  /// 整个下载（包括重试）的时限，从创建时开始计算
+  #[builder(default)]
+  deadline: Option<Duration>,
+  #[builder(default = tokio::time::Instant::now(), setter(skip))]
+  started_at: tokio::time::Instant,
+
+  #[builder]
+  stall_timeout: Duration,
+  #[builder(default)]
+  min_speed: Option<MinSpeed>,
+  /// 连接超时，HTTP 请求由 client 负责，只用于 FTP、SFTP 和 S3 连接
+  #[builder(default = Duration::from_millis(2_000))]
+  connect_timeout: Duration,
+
+  #[builder(default)]
+  sftp: SftpOptions,
+  #[builder(default)]
+  s3: S3Options,
+  /// 代替内置协议下载其支持的 URL
+  #[builder(default)]
+  sources: Vec<Arc<dyn DownloadSource>>,
+  #[builder]
+  flush_threshold: usize,
+  /// 写入临时文件的缓冲区容量
+  #[builder(default = 1024 * 1024)]
+  write_buffer_size: usize,
+
+  #[builder(default)]
+  bearer_token: Option<String>,
+
+  #[builder(default = 1)]
+  segments_per_file: usize,
+
+  /// 所有下载共享的限速器
+  #[builder(default)]
+  global_limiter: Option<Arc<RateLimiter>>,
+  /// 当前下载项自己的限速器
+  #[builder(default)]
+  limiter: Option<RateLimiter>,
+  /// 所有下载共享的每主机连接数限制
+  #[builder(default)]
+  hosts: Option<Arc<HostLimiter>>,
+  /// 本批次中提供过错误数据的镜像，由所有下载共享
+  #[builder(default)]
+  poisoned: Arc<PoisonedMirrors>,
+  /// 所有下载共享的熔断器，放弃连续失败过多的主机
+  #[builder(default)]
+  breaker: Option<Arc<CircuitBreaker>>,
+  /// 所有下载共享的内存预算，限制尚未写入磁盘的数据
+  #[builder(default)]
+  memory_budget: Option<Arc<MemoryBudget>>,
+  /// 请求停止所有下载的句柄
+  #[builder(default)]
+  shutdown: Option<ShutdownHandle>,
+
+  #[builder(default = false)]
+  skip_unchanged: bool,
+
+  #[builder(default)]
+  overwrite: OverwritePolicy,
+
+  #[builder(default)]
+  max_download_size: Option<u64>,
+  #[builder(default)]
+  response_validator: Option<Arc<dyn ResponseValidator>>,
+  /// URL 失效时获取新的 URL
+  #[builder(default)]
+  url_provider: Option<Arc<dyn UrlProvider>>,
+  /// 写入前依次处理响应数据
+  #[builder(default)]
+  transforms: Option<Arc<dyn TransformFactory>>,
+  /// 校验签名的 minisign 公钥
+  #[builder(default)]
+  signature_key: Option<String>,
+  /// 每次发送请求前调整请求
+  #[builder(default)]
+  request_middleware: Option<Arc<dyn RequestMiddleware>>,
+  /// url_provider 提供的新 URL，按镜像的序号保存，0 是下载项自己的 URL
+  #[builder(default, setter(skip))]
+  refreshed: Mutex<HashMap<usize, String>>,
+  /// 上一次尝试是否使用了刚获取的新 URL
+  #[builder(default, setter(skip))]
+  just_refreshed: AtomicBool,
+  /// 目标文件不是 HTML 时拒绝 HTML 页面
+  #[builder(default = false)]
+  reject_html: bool,
+
+  #[builder(default = false)]
+  preserve_mtime: bool,
+
+  /// 开始接收数据前为文件预留磁盘空间
+  #[builder(default = false)]
+  preallocate: bool,
+
+  /// 允许从其他 URL 继续下载，先校验重叠部分的数据
+  #[builder(default = false)]
+  resume_across_urls: bool,
+
+  /// 达到该大小的文件使用直接 I/O 写入
+  #[builder(default)]
+  direct_io_threshold: Option<u64>,
+
+  /// 通过 io_uring 写入临时文件
+  #[builder(default = false)]
+  io_uring: bool,
+  #[builder(default)]
+  file_mode: Option<u32>,
+
+  #[builder(default = true)]
+  decompress: bool,
+
+  #[builder(default)]
+  redirect_policy: RedirectPolicy,
+
+  /// 边下载边计算摘要的算法，设置了 integrity 时使用其算法
+  #[builder(default)]
+  digest_algorithm: Option<HashAlgorithm>,
+
+  /// 最近一次响应在重定向之后的 URL
+  #[builder(default, setter(skip))]
+  final_url: Mutex<Option<String>>,
+
+  /// 最近一次响应使用的 HTTP 版本
+  #[builder(default, setter(skip))]
+  http_version: Mutex<Option<Version>>,
+
+  /// 最近一次成功响应的 Content-Type
+  #[builder(default, setter(skip))]
+  content_type: Mutex<Option<String>>,
+
+  /// 最近一次响应描述的远端文件信息
+  #[builder(default, setter(skip))]
+  remote: Mutex<Option<ResumeState>>,
+
+  /// 所有尝试通过网络收到的字节数
+  #[builder(default, setter(skip))]
+  transferred: AtomicU64,
+
+  /// 已开始的下载尝试次数
+  #[builder(default, setter(skip))]
+  attempts: AtomicUsize,
+
+  /// 无法续传而重新下载的字节数
+  #[builder(default, setter(skip))]
+  retried_bytes: AtomicU64,
+
+  /// 上一次尝试结束的时间，用于统计重试前等待的时长
+  #[builder(default, setter(skip))]
+  attempt_ended: Mutex<Option<Instant>>,
+
+  /// 两次尝试之间等待的总时长
+  #[builder(default, setter(skip))]
+  backoff: Mutex<Duration>,
+
+  /// 当前使用的镜像，0 表示主 URL
+  #[builder(default, setter(skip))]
+  mirror: AtomicUsize,
+
+  /// 已写入目标 writer 的字节数，在重试之间保留
+  #[builder(default, setter(skip))]
+  streamed: AtomicU64,
+
+  /// 分段下载的进度，在重试之间保留以便续传
+  #[builder(default, setter(skip))]
+  segments: Mutex<Option<Vec<Segment>>>,
+
+  /// 已写入临时文件的数据的摘要状态，在重试之间保留
+  #[builder(default, setter(skip))]
+  hasher: Mutex<Option<Hasher>>,
+
+  /// 下载完成的文件的摘要
+  #[builder(default, setter(skip))]
+  digest: Mutex<Option<String>>,
+
+  /// 文件实际保存的路径，按 RenameWithSuffix 策略改名时与 target 不同
+  #[builder(default, setter(skip))]
+  saved_to: Mutex<Option<PathBuf>>,
+
+  /// 按 Skip 策略保留了已存在的目标文件
+  #[builder(default, setter(skip))]
+  kept_existing: AtomicBool,
+}
+
+impl<U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path>> DownloadTaskRunner<U, P, TP> {
+  /// Builds a request to the current URL with the item's headers and credentials.
+  fn request(&self, method: Method) -> RequestBuilder {
+    let mut request = self
+      .item
+      .apply_headers(self.client.request(method, self.url()));
+
+    if let Some(token) = &self.bearer_token {
+      request = request.bearer_auth(token);
+    }
+
+    request
+  }
+
+  async fn send(
+    &self,
+    range: String,
+    if_range: Option<&str>,
+  ) -> Result<reqwest::Response, ProgressDownloadError> {
+    let mut request = self.request(Method::GET).header("Range", range);
+
+    if let Some(if_range) = if_range {
+      request = request.header("If-Range", if_range);
+    }
+    let request = middleware::apply(&self.request_middleware, request).await;
+
+    // 只限制等待响应头的时间，读取响应体由 stall_timeout 和 deadline 限制
+    let response = tokio::time::timeout(
+      self.timeout,
+      self.redirect_policy.send(&self.client, request),
+    )
+    .await
+    .map_err(ProgressDownloadError::timed_out(
+      TimeoutPhase::Response,
+      self.timeout,
+    ))??;
+    *self.final_url.lock().unwrap() = Some(response.url().to_string());
+    *self.http_version.lock().unwrap() = Some(response.version());
+
+    // 416 由调用方处理，其余错误状态交给重试策略分类
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+      return Ok(response);
+    }
+
+    let error = match response.error_for_status_ref() {
+      Ok(_) => {
+        if self.reject_html && sniff::is_html_response(response.headers()) {
+          return Err(self.unexpected_content("received an HTML page instead of the file"));
+        }
+        if let Some(validator) = &self.response_validator {
+          validator.validate(response.headers()).map_err(|message| {
+            ProgressDownloadError::InvalidResponse {
+              url: response.url().to_string(),
+              message,
+            }
+          })?;
+        }
+        *self.content_type.lock().unwrap() = response
+          .headers()
+          .get(CONTENT_TYPE)
+          .and_then(|value| value.to_str().ok())
+          .map(str::to_string);
+        return Ok(response);
+      }
+      Err(error) => error,
+    };
+
+    // 429 和 503 可能通过 Retry-After 告知多久之后再重试
+    let retry_after = matches!(
+      response.status(),
+      StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    )
+    .then(|| response.headers().get(RETRY_AFTER)?.to_str().ok())
+    .flatten()
+    .and_then(|value| retry::parse_retry_after(value, SystemTime::now()));
+
+    Err(match retry_after {
+      Some(delay) => ProgressDownloadError::RetryAfter {
+        source: error,
+        delay,
+      },
+      None => error.into(),
+    })
+  }
+
+  /// Waits for a free connection to the host of the current URL if connections per host
+  /// are limited. The connection counts against the limit until the permit is dropped.
+  async fn connection(&self) -> Result<Option<OwnedSemaphorePermit>, ProgressDownloadError> {
+    match &self.hosts {
+      Some(hosts) => Ok(hosts.acquire(&self.url()).await?),
+      None => Ok(None),
+    }
+  }
+
+  /// Whether the current URL is downloaded by one of the `sources` or over FTP, SFTP or
+  /// S3 rather than with the HTTP client.
+  fn is_remote(&self) -> bool {
+    let url = self.url();
+    source::find(&self.sources, &url).is_some() || Scheme::of(&url) != Scheme::Http
+  }
+
+  /// Whether only a part of the remote file is downloaded, see the item's `part`.
+  pub fn is_part(&self) -> bool {
+    self.item.part.is_some()
+  }
+
+  /// Whether the item's file is verified with a detached signature.
+  pub fn is_signed(&self) -> bool {
+    self.item.signature.is_some()
+  }
+
+  /// Whether the body passes through `transforms` before it is written.
+  pub fn is_transformed(&self) -> bool {
+    self.transforms.is_some()
+  }
+
+  /// Creates the decoder for a response, if it should be decompressed or transformed.
+  fn decoder(&self, headers: &HeaderMap) -> Result<Option<TransformChain>, ProgressDownloadError> {
+    let decoder = match self.decompress {
+      true => Decoder::from_headers(headers)?,
+      false => None,
+    };
+    if decoder.is_none() && !self.is_transformed() {
+      return Ok(None);
+    }
+
+    let mut chain = TransformChain::new().then(DecoderTransform(decoder));
+    if let Some(transforms) = &self.transforms {
+      chain = chain.then(transforms.create(&self.info));
+    }
+    Ok(Some(chain))
+  }
+
+  /// Whether the target file exists and the remote file has not changed since it was
+  /// downloaded, according to a conditional HEAD request.
+  pub async fn is_up_to_date(&self) -> bool {
+    let target = self.target_path.as_path();
+
+    if !target.is_file() {
+      return false;
+    }
+    let Some(state) = ResumeState::load(target)
+      .await
+      .filter(|state| state.url == self.item.url.as_str() && state.part == self.part_key())
+    else {
+      return false;
+    };
+
+    self.is_unchanged(&state).await
+  }
+
+  /// Checks with a conditional request whether the remote file still matches `state`.
+  pub async fn is_unchanged(&self, state: &ResumeState) -> bool {
+    let mut request = self.request(Method::HEAD).timeout(self.timeout);
+    if let Some(etag) = &state.etag {
+      request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &state.last_modified {
+      request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+
+    let Ok(_connection) = self.connection().await else {
+      return false;
+    };
+
+    let request = middleware::apply(&self.request_middleware, request).await;
+    match self.redirect_policy.send(&self.client, request).await {
+      Ok(response) => response.status() == StatusCode::NOT_MODIFIED,
+      Err(err) => {
+        debug!("conditional request failed, downloading again: {}", err);
+        false
+      }
+    }
+  }
+
+  /// The URL of the latest response after following redirects.
+  pub fn final_url(&self) -> Option<String> {
+    self.final_url.lock().unwrap().clone()
+  }
+
+  /// The state of the remote file of the last response, if a request was made.
+  pub fn remote_state(&self) -> Option<ResumeState> {
+    self.remote.lock().unwrap().clone()
+  }
+
+  /// The reporter receiving the events of the item.
+  pub fn reporter(&self) -> &dyn ProgressReporter {
+    self.reporter.as_ref()
+  }
+
+  /// Number of attempts started so far.
+  pub fn attempts(&self) -> usize {
+    self.attempts.load(Ordering::Relaxed)
+  }
+
+  /// Bytes received again because an attempt started over instead of resuming.
+  pub fn retried_bytes(&self) -> u64 {
+    self.retried_bytes.load(Ordering::Relaxed)
+  }
+
+  /// Time spent waiting between attempts.
+  pub fn backoff(&self) -> Duration {
+    *self.backoff.lock().unwrap()
+  }
+
+  /// Counts `bytes` received by earlier attempts that the next one downloads again.
+  fn discarded(&self, bytes: u64) {
+    if bytes > 0 {
+      debug!(bytes, "partial data discarded, starting over");
+      self.retried_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+  }
+
+  /// Bytes received over the network by all attempts.
+  pub fn transferred(&self) -> u64 {
+    self.transferred.load(Ordering::Relaxed)
+  }
+
+  /// The HTTP version of the last response, if a request was made.
+  pub fn http_version(&self) -> Option<Version> {
+    *self.http_version.lock().unwrap()
+  }
+
+  /// The `Content-Type` of the last successful response, if it had one.
+  pub fn content_type(&self) -> Option<String> {
+    self.content_type.lock().unwrap().clone()
+  }
+
+  /// The `ETag` of the remote file of the last response, if it had one.
+  pub fn etag(&self) -> Option<String> {
+    self.remote.lock().unwrap().as_ref()?.etag.clone()
+  }
+
+  /// Bytes written to the writer of `download_to_writer` so far.
+  pub fn streamed(&self) -> u64 {
+    self.streamed.load(Ordering::Relaxed)
+  }
+
+  /// Records that the file was saved to `path` instead of the target, e.g. under a new
+  /// name when restored from the cache.
+  pub fn set_saved_to(&self, path: PathBuf) {
+    if path != self.item.target.as_ref() {
+      *self.saved_to.lock().unwrap() = Some(path);
+    }
+  }
+
+  /// The path the file was saved to, the item's target unless it was renamed according
+  /// to the [`OverwritePolicy`].
+  pub fn target(&self) -> PathBuf {
+    self
+      .saved_to
+      .lock()
+      .unwrap()
+      .clone()
+      .unwrap_or_else(|| self.item.target.as_ref().to_path_buf())
+  }
+
+  /// Whether an existing target was kept instead of the download, see
+  /// [`OverwritePolicy::Skip`].
+  pub fn kept_existing(&self) -> bool {
+    self.kept_existing.load(Ordering::Relaxed)
+  }
+
+  /// Checks the [`OverwritePolicy`] before the download starts.
+  ///
+  /// Returns `Ok(true)` if the target exists and is to be kept, and fails if it exists
+  /// and the policy is [`OverwritePolicy::Error`].
+  pub async fn keeps_existing_target(&self) -> Result<bool, ProgressDownloadError> {
+    let target = self.target_path.as_path();
+    let keeps = matches!(
+      self.overwrite,
+      OverwritePolicy::Skip | OverwritePolicy::Error
+    );
+    if !keeps || !tokio::fs::try_exists(target).await? {
+      return Ok(false);
+    }
+
+    match self.overwrite {
+      OverwritePolicy::Error => Err(ProgressDownloadError::TargetExists {
+        path: target.to_path_buf(),
+      }),
+      _ => Ok(true),
+    }
+  }
+
+  /// The hex digest of the downloaded file, if a digest algorithm or integrity is set.
+  pub fn digest(&self) -> Option<String> {
+    self.digest.lock().unwrap().clone()
+  }
+
+  /// The algorithm of the digest computed while downloading, if any.
+  pub fn hash_algorithm(&self) -> Option<HashAlgorithm> {
+    self
+      .item
+      .integrity
+      .as_ref()
+      .map(|integrity| integrity.hash_algorithm())
+      .or(self.digest_algorithm)
+  }
+
+  /// The hasher continuing after the `downloaded_size` bytes already in the temp file.
+  ///
+  /// The hasher of the previous attempt is reused if it has not hashed more than those
+  /// bytes, otherwise the existing bytes are read from the temp file once.
+  async fn resume_hasher(
+    &self,
+    resumed: bool,
+    downloaded_size: u64,
+  ) -> Result<Option<Hasher>, ProgressDownloadError> {
+    let Some(algorithm) = self.hash_algorithm() else {
+      return Ok(None);
+    };
+
+    let previous = self.hasher.lock().unwrap().take();
+    let mut hasher = match previous {
+      Some(hasher) if resumed && hasher.position() <= downloaded_size => hasher,
+      _ => Hasher::new(algorithm),
+    };
+
+    if resumed {
+      hasher
+        .update_from_file(self.tmp_file.as_ref(), downloaded_size)
+        .await?;
+    }
+
+    Ok(Some(hasher))
+  }
+
+  /// Creates the writer of the temp file `file`, positioned at `offset`, using direct I/O
+  /// if the file has a known `size` of at least `direct_io_threshold` bytes, or io_uring
+  /// if it is enabled.
+  async fn temp_writer(
+    &self,
+    file: tokio::fs::File,
+    offset: u64,
+    size: Option<u64>,
+    capacity: usize,
+  ) -> std::io::Result<TempWriter> {
+    let large = self
+      .direct_io_threshold
+      .is_some_and(|threshold| size.is_some_and(|size| size >= threshold));
+    if large {
+      if let Some(writer) = TempWriter::direct(self.tmp_file.as_ref(), offset, capacity).await? {
+        return Ok(writer);
+      }
+      debug!("direct I/O is not available, writing through the page cache");
+    }
+    if self.io_uring {
+      if let Some(writer) = TempWriter::uring(self.tmp_file.as_ref(), offset, capacity).await? {
+        return Ok(writer);
+      }
+      debug!("io_uring is not available, writing through the blocking thread pool");
+    }
+    Ok(TempWriter::buffered(file, capacity))
+  }
+
+  /// Waits until `bytes` may be consumed without exceeding the configured speed limits.
+  async fn throttle(&self, bytes: usize) {
+    if let Some(limiter) = &self.global_limiter {
+      limiter.acquire(bytes).await;
+    }
+    if let Some(limiter) = &self.limiter {
+      limiter.acquire(bytes).await;
+    }
+  }
+
+  /// The URL the next request goes to: the item's URL or one of its mirrors, unless the
+  /// `url_provider` replaced it.
+  fn url(&self) -> String {
+    let index = self.mirror_index();
+    if let Some(url) = self.refreshed.lock().unwrap().get(&index) {
+      return url.clone();
+    }
+    match index {
+      0 => self.item.url.as_str().to_string(),
+      index => self.item.mirrors[index - 1].as_str().to_string(),
+    }
+  }
+
+  /// The position of the current URL: 0 for the item's URL, then one per mirror.
+  fn mirror_index(&self) -> usize {
+    self.mirror.load(Ordering::Relaxed) % (self.item.mirrors.len() + 1)
+  }
+
+  /// Asks the `url_provider` for a fresh URL after `url` was refused with `error`,
+  /// returning whether the next attempt should use it.
+  async fn refresh_url(&self, url: &str, error: &ProgressDownloadError) -> bool {
+    let Some(provider) = &self.url_provider else {
+      return false;
+    };
+    let refused = error
+      .status()
+      .is_some_and(|status| status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN);
+    // 新 URL 同样被拒绝时不再重复获取
+    let retried = self.just_refreshed.swap(false, Ordering::Relaxed);
+    if !refused || retried {
+      return false;
+    }
+
+    match provider.refresh(url.to_string()).await {
+      Ok(fresh) => {
+        debug!("{} refused, continuing with a fresh URL: {}", url, error);
+        self
+          .refreshed
+          .lock()
+          .unwrap()
+          .insert(self.mirror_index(), fresh);
+        self.just_refreshed.store(true, Ordering::Relaxed);
+        true
+      }
+      Err(message) => {
+        warn!("failed to refresh {}: {}", url, message);
+        false
+      }
+    }
+  }
+
+  /// Switches to the next mirror, skipping the ones that served bad data or were given
+  /// up in this batch unless all of them were.
+  fn switch_mirror(&self) {
+    self.mirror.fetch_add(1, Ordering::Relaxed);
+    self.skip_unusable_mirrors();
+  }
+
+  /// Moves on from the current URL while it is a mirror that served bad data or whose
+  /// host was given up by the circuit breaker in this batch, staying put if all of them
+  /// were.
+  fn skip_unusable_mirrors(&self) {
+    let start = self.mirror.load(Ordering::Relaxed);
+    for offset in 0..=self.item.mirrors.len() {
+      self.mirror.store(start + offset, Ordering::Relaxed);
+      if !self.poisoned.is_poisoned(&self.url()) && !self.is_circuit_open() {
+        return;
+      }
+    }
+    self.mirror.store(start, Ordering::Relaxed);
+  }
+
+  /// Whether the circuit breaker has given up the host of the current URL.
+  fn is_circuit_open(&self) -> bool {
+    self
+      .breaker
+      .as_ref()
+      .is_some_and(|breaker| breaker.is_open(&self.url()))
+  }
+
+  /// Counts the outcome of a request to `url` towards the circuit breaker: connection
+  /// errors, timeouts and server errors are failures, a completed download a success.
+  fn record_outcome(&self, url: &str, result: &Result<(), ProgressDownloadError>) {
+    let Some(breaker) = &self.breaker else {
+      return;
+    };
+    match result {
+      Ok(()) => breaker.record_success(url),
+      Err(err)
+        if err.is_connection_error()
+          || err.status().is_some_and(|status| status.is_server_error()) =>
+      {
+        breaker.record_failure(url)
+      }
+      Err(_) => {}
+    }
+  }
+
+  /// Waits for the next chunk of the response body.
+  ///
+  /// Fails with [`ProgressDownloadError::Paused`] as soon as the download is paused,
+  /// [`ProgressDownloadError::Interrupted`] once a shutdown is requested, or
+  /// [`ProgressDownloadError::DeadlineExceeded`] once the `deadline` has passed, so the
+  /// caller drops the connection.
+  ///
+  /// `watchdog` checks the speed of the connection if the `min_speed` option is set, see
+  /// [`speed_watchdog`](Self::speed_watchdog).
+  async fn next_chunk<S, E>(
+    &self,
+    stream: &mut S,
+    watchdog: &mut Option<SpeedWatchdog>,
+  ) -> Result<Option<Bytes>, ProgressDownloadError>
+  where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    ProgressDownloadError: From<E>,
+  {
+    // 只计算等待数据的时间，限速等待不算在内
+    let started = Instant::now();
+    let next = tokio::time::timeout(self.stall_timeout, stream.next());
+
+    let chunk = tokio::select! {
+      chunk = next => match chunk {
+        Ok(chunk) => chunk.transpose()?,
+        Err(_) => return Err(ProgressDownloadError::Stalled {
+          timeout: self.stall_timeout,
+        }),
+      },
+      _ = self.paused() => return Err(ProgressDownloadError::Paused),
+      _ = self.interrupted() => return Err(ProgressDownloadError::Interrupted),
+      _ = self.deadline_reached() => return Err(self.deadline_exceeded()),
+    };
+
+    if let (Some(watchdog), Some(chunk)) = (watchdog.as_mut(), &chunk) {
+      watchdog.record(chunk.len(), started.elapsed())?;
+    }
+    Ok(chunk)
+  }
+
+  /// A new speed check for one connection, `None` without the `min_speed` option.
+  fn speed_watchdog(&self) -> Option<SpeedWatchdog> {
+    self.min_speed.map(SpeedWatchdog::new)
+  }
+
+  /// Completes once the download is paused, never without a handle.
+  async fn paused(&self) {
+    match &self.item.handle {
+      Some(handle) => handle.paused().await,
+      None => std::future::pending().await,
+    }
+  }
+
+  /// Completes once the `deadline` has passed, never without one.
+  async fn deadline_reached(&self) {
+    match self.deadline {
+      Some(deadline) => tokio::time::sleep_until(self.started_at + deadline).await,
+      None => std::future::pending().await,
+    }
+  }
+
+  fn deadline_exceeded(&self) -> ProgressDownloadError {
+    ProgressDownloadError::DeadlineExceeded {
+      deadline: self.deadline.unwrap_or_default(),
+    }
+  }
+
+  /// Completes once a shutdown is requested, never without a shutdown handle.
+  async fn interrupted(&self) {
+    match &self.shutdown {
+      Some(shutdown) => shutdown.requested().await,
+      None => std::future::pending().await,
+    }
+  }
+
+  pub async fn download(&self) -> Result<(), ProgressDownloadError> {
+    self.attempt(|| self.try_download()).await
+  }
+
+  /// Streams the response body into `writer`, continuing after the bytes delivered by
+  /// previous attempts.
+  pub async fn download_to_writer<W: ChunkWriter>(
+    &self,
+    writer: &tokio::sync::Mutex<W>,
+  ) -> Result<(), ProgressDownloadError> {
+    self.attempt(|| self.try_download_to_writer(writer)).await
+  }
+
+  fn unexpected_content(&self, message: impl Into<String>) -> ProgressDownloadError {
+    ProgressDownloadError::UnexpectedContent {
+      url: self.url(),
+      message: message.into(),
+    }
+  }
+
+  /// Checks the first bytes of the downloaded file, see [`sniff::check_content`].
+  fn check_content(&self, head: &[u8]) -> Result<(), ProgressDownloadError> {
+    sniff::check_content(head, self.item.expected_magic.as_deref(), self.reject_html)
+      .map_err(|message| self.unexpected_content(message))
+  }
+
+  /// Checks the first chunk of a body starting at the beginning of the file, so
+  /// unexpected content fails before the rest is downloaded.
+  fn check_first_chunk(&self, chunk: &[u8]) -> Result<(), ProgressDownloadError> {
+    // 数据块比魔数短时无法判断，留到下载完成后检查
+    let magic = self
+      .item
+      .expected_magic
+      .as_deref()
+      .filter(|magic| chunk.len() >= magic.len());
+    sniff::check_content(
+      &chunk[..chunk.len().min(sniff::SNIFF_LEN)],
+      magic,
+      self.reject_html,
+    )
+    .map_err(|message| self.unexpected_content(message))
+  }
+
+  /// Checks `size` bytes of the file against `max_download_size` and the item's
+  /// `expected_size`, which it must match exactly if it is the size of the whole file.
+  fn check_size(&self, size: u64, whole_file: bool) -> Result<(), ProgressDownloadError> {
+    if let Some(limit) = self.max_download_size.filter(|limit| size > *limit) {
+      return Err(ProgressDownloadError::TooLarge { size, limit });
+    }
+    match self.item.expected_size {
+      Some(expected) if size > expected || (whole_file && size != expected) => {
+        Err(ProgressDownloadError::SizeMismatch {
+          expected,
+          actual: size,
+        })
+      }
+      _ => Ok(()),
+    }
+  }
+
+  /// Runs one download attempt, waiting while the download is paused and starting over
+  /// once it is resumed.
+  async fn attempt<F, Fut>(&self, operation: F) -> Result<(), ProgressDownloadError>
+  where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<(), ProgressDownloadError>>,
+  {
+    // 先尝试未提供过错误数据、也未被熔断的镜像
+    self.skip_unusable_mirrors();
+
+    if let Some(ended) = self.attempt_ended.lock().unwrap().take() {
+      *self.backoff.lock().unwrap() += ended.elapsed();
+    }
+
+    loop {
+      if let Some(handle) = &self.item.handle {
+        tokio::select! {
+          _ = handle.resumed() => {}
+          _ = self.interrupted() => return Err(ProgressDownloadError::Interrupted),
+        }
+      }
+
+      // 请求停止后不再开始新的尝试
+      if self
+        .shutdown
+        .as_ref()
+        .is_some_and(ShutdownHandle::is_shutdown)
+      {
+        return Err(ProgressDownloadError::Interrupted);
+      }
+
+      // 超过时限后不再重试
+      if self
+        .deadline
+        .is_some_and(|deadline| self.started_at.elapsed() >= deadline)
+      {
+        return Err(self.deadline_exceeded());
+      }
+
+      // 主机及所有镜像都已被熔断时不再请求
+      if self.is_circuit_open() {
+        return Err(ProgressDownloadError::CircuitOpen {
+          origin: poison::origin(&self.url()),
+        });
+      }
+
+      self.attempts.fetch_add(1, Ordering::Relaxed);
+      let url = self.url();
+      let result = operation().await;
+      self.record_outcome(&url, &result);
+
+      // URL 过期被拒绝时换用新的 URL 立即重试
+      if let Err(err) = &result {
+        if self.refresh_url(&url, err).await {
+          continue;
+        }
+      }
+
+      match &result {
+        Err(ProgressDownloadError::Paused) => {
+          debug!("download paused: {}", self.info.url);
+          continue;
+        }
+        // 连接失败时切换到下一个镜像再重试
+        Err(err) if err.is_connection_error() && !self.item.mirrors.is_empty() => {
+          debug!("{} unreachable, switching mirror: {}", url, err);
+          self.switch_mirror();
+        }
+        // 校验失败时记录提供错误数据的镜像，立即从其他镜像重新下载
+        Err(err @ ProgressDownloadError::IntegrityHash { .. }) if !self.item.mirrors.is_empty() => {
+          let url = self.url();
+          self.poisoned.poison(&url);
+          self.switch_mirror();
+          if !self.poisoned.is_poisoned(&self.url()) {
+            warn!("{} served a corrupt file, switching mirror: {}", url, err);
+            continue;
+          }
+        }
+        _ => {}
+      }
+
+      *self.attempt_ended.lock().unwrap() = Some(Instant::now());
+      return result;
+    }
+  }
+
+  async fn try_download(&self) -> Result<(), ProgressDownloadError> {
+    let result = async {
+      if let Some(part) = &self.item.part {
+        self.download_part(part).await?;
+      } else if self.is_remote() {
+        self.download_remote().await?;
+      } else if self.download_delta().await? {
+        debug!("assembled from the existing target and the changed blocks");
+      } else if self.segments_per_file <= 1
+        // 处理后的数据无法按偏移写入各分段，只能单连接下载
+        || self.transforms.is_some()
+        || !self.download_segmented().await?
+      {
+        self.download_stream().await?;
+      }
+
+      self.finish().await
+    }
+    .await;
+
+    // 大小或内容不符的文件不是要下载的内容，不保留用于续传；处理后的数据本就无法续传，
+    // 例如未通过认证的解密结果也不应留在磁盘上
+    let unusable = matches!(
+      result,
+      Err(
+        ProgressDownloadError::TooLarge { .. }
+          | ProgressDownloadError::SizeMismatch { .. }
+          | ProgressDownloadError::UnexpectedContent { .. }
+          | ProgressDownloadError::IntegrityHash { .. }
+          | ProgressDownloadError::SignatureInvalid { .. }
+      )
+    );
+    if unusable || (result.is_err() && self.is_transformed()) {
+      let temp_file = self.tmp_file.as_ref();
+      let _ = tokio::fs::remove_file(temp_file).await;
+      ResumeState::remove(temp_file).await;
+      *self.segments.lock().unwrap() = None;
+    }
+
+    result
+  }
+
+  /// The size of the temp file and its resume state, if the download can continue from it.
+  async fn partial_download(&self) -> (u64, Option<ResumeState>) {
+    self
+      .load_partial(|state| state.url == self.item.url.as_str() && state.part == self.part_key())
+      .await
+  }
+
+  /// The key of the item's `part` in resume states, `None` for the whole file.
+  fn part_key(&self) -> Option<String> {
+    self.item.part.as_ref().map(RemotePart::key)
+  }
+
+  /// The partial download a single connection continues and the number of its last
+  /// bytes to request again, which are compared with the local ones before resuming
+  /// when the data came from another URL, see the `resume_across_urls` option.
+  async fn stream_partial(&self) -> (u64, Option<ResumeState>, u64) {
+    if !self.resume_across_urls {
+      let (downloaded_size, state) = self.partial_download().await;
+      return (downloaded_size, state, 0);
+    }
+
+    let (downloaded_size, state) = self.load_partial(|state| state.part.is_none()).await;
+    let overlap = match &state {
+      Some(state) if state.source() != self.url() => downloaded_size.min(RESUME_OVERLAP),
+      _ => 0,
+    };
+    (downloaded_size, state, overlap)
+  }
+
+  /// The size of the temp file and its resume state if `accept` approves of it.
+  async fn load_partial(
+    &self,
+    accept: impl FnOnce(&ResumeState) -> bool,
+  ) -> (u64, Option<ResumeState>) {
+    let temp_file = self.tmp_file.as_ref();
+    let downloaded_size = temp_file.metadata().map(|item| item.len()).unwrap_or(0);
+
+    // 没有可校验的续传记录时，无法确认远端文件未变化，只能从头下载
+    let state = match downloaded_size {
+      0 => None,
+      _ => ResumeState::load(temp_file).await.filter(accept),
+    };
+
+    match state {
+      Some(state) => (downloaded_size, Some(state)),
+      None => (0, None),
+    }
+  }
+
+  /// Reads the first `overlap` bytes of `stream` and compares them with the bytes of the
+  /// temp file they should repeat, which end at `downloaded_size`. Returns the rest of
+  /// the stream if they match, `None` otherwise.
+  async fn verify_overlap(
+    &self,
+    mut stream: BoxStream<'static, Result<Bytes, ProgressDownloadError>>,
+    downloaded_size: u64,
+    overlap: u64,
+  ) -> Result<Option<BoxStream<'static, Result<Bytes, ProgressDownloadError>>>, ProgressDownloadError>
+  {
+    let temp_file = self.tmp_file.as_ref();
+    let mut local = vec![0; overlap as usize];
+    let mut file = tokio::fs::File::open(temp_file)
+      .await
+      .map_err(ProgressDownloadError::io_at(temp_file))?;
+    file
+      .seek(SeekFrom::Start(downloaded_size - overlap))
+      .await?;
+    file.read_exact(&mut local).await?;
+
+    let mut remote = Vec::with_capacity(local.len());
+    let mut watchdog = self.speed_watchdog();
+    let mut rest = None;
+    while remote.len() < local.len() {
+      let Some(chunk) = self.next_chunk(&mut stream, &mut watchdog).await? else {
+        return Err(ProgressDownloadError::Incomplete {
+          expected: overlap,
+          received: remote.len() as u64,
+        });
+      };
+      // 超出重叠部分的数据之后照常计算
+      let needed = (local.len() - remote.len()).min(chunk.len());
+      self.throttle(needed).await;
+      self.transferred.fetch_add(needed as u64, Ordering::Relaxed);
+      if chunk.len() > needed {
+        rest = Some(chunk.slice(needed..));
+      }
+      remote.extend_from_slice(&chunk[..needed]);
+    }
+
+    if remote != local {
+      return Ok(None);
+    }
+    debug!(overlap, "partial download matches {}", self.url());
+    Ok(Some(
+      futures::stream::iter(rest.map(Ok)).chain(stream).boxed(),
+    ))
+  }
+
+  /// Deletes the temp file and its resume state, failing the attempt with `reason` so the
+  /// next one starts over.
+  async fn discard_partial(&self, reason: &str) -> Result<(), ProgressDownloadError> {
+    let temp_file = self.tmp_file.as_ref();
+    self.discarded(temp_file.metadata().map(|item| item.len()).unwrap_or(0));
+    tokio::fs::remove_file(temp_file).await?;
+    ResumeState::remove(temp_file).await;
+    Err(std::io::Error::other(reason.to_string()).into())
+  }
+
+  /// Downloads the file over a single connection, resuming from the size of the temp file.
+  async fn download_stream(&self) -> Result<(), ProgressDownloadError> {
+    let temp_file = self.tmp_file.as_ref();
+    let (mut downloaded_size, state, overlap) = self.stream_partial().await;
+    let start = downloaded_size - overlap;
+
+    let _connection = self.connection().await?;
+    // 其他服务器的 ETag 和修改时间不可比较，改为校验重叠部分
+    let if_range = state
+      .as_ref()
+      .filter(|_| overlap == 0)
+      .and_then(ResumeState::if_range);
+    let response = self.send(format!("bytes={}-", start), if_range).await?;
+
+    let reply = RangeReply::of(response.status(), response.headers(), start, None);
+    match reply {
+      RangeReply::Unsatisfiable if overlap > 0 => {
+        return self
+          .discard_partial("remote file is shorter than the partial download")
+          .await;
+      }
+      RangeReply::Unsatisfiable => {
+        return self
+          .complete_partial(&response, downloaded_size, state)
+          .await;
+      }
+      RangeReply::Mismatched => {
+        return self
+          .discard_partial("server returned a different range than requested")
+          .await;
+      }
+      RangeReply::Resumed | RangeReply::Restarted => {}
+    }
+
+    let url = self.url();
+    let remote = ResumeState {
+      // 记录实际提供数据的镜像或新 URL，换用其他 URL 续传时需要校验
+      source: (url != self.item.url.as_str()).then_some(url),
+      ..ResumeState::from_headers(
+        self.item.url.as_str(),
+        response.headers(),
+        if response.status() == StatusCode::PARTIAL_CONTENT {
+          content_range_total(response.headers())
+        } else {
+          response.content_length()
+        },
+      )
+    };
+
+    // 服务端忽略 Range 返回完整内容时从头写入，进度也从 0 开始
+    let should_resume = downloaded_size > 0 && reply == RangeReply::Resumed;
+
+    let decoder = self.decoder(response.headers())?;
+
+    if should_resume && decoder.is_some() {
+      // 已有数据是解压后的内容，无法与压缩响应的偏移对应，丢弃后重试
+      return self
+        .discard_partial("cannot resume a compressed response")
+        .await;
+    }
+
+    let unchanged = state.as_ref().is_some_and(|state| match overlap {
+      0 => state.matches(&remote),
+      _ => state.matches_size(&remote),
+    });
+    if should_resume && !unchanged {
+      // 服务端忽略了 If-Range 且文件已变化，丢弃旧数据后重试
+      return self
+        .discard_partial("remote file changed since the partial download")
+        .await;
+    }
+
+    // 压缩响应的长度不是文件大小，收到数据后再检查
+    if let Some(remaining_size) = response.content_length().filter(|_| decoder.is_none()) {
+      let offset = if should_resume { start } else { 0 };
+      self.check_size(remaining_size + offset, true)?;
+    }
+
+    if !should_resume {
+      downloaded_size = 0;
+      if decoder.is_some() {
+        // 压缩响应无法续传，不保存续传记录
+        ResumeState::remove(temp_file).await;
+      } else {
+        remote.save(temp_file).await?;
+      }
+    }
+    *self.remote.lock().unwrap() = Some(remote);
+
+    // 从头下载时响应不包含重叠部分
+    let overlap = if should_resume { overlap } else { 0 };
+    let offset = if should_resume { start } else { 0 };
+    let total_size = response
+      .content_length()
+      .map(|remaining_size| remaining_size + offset);
+    // 本次响应应当返回的字节数，用于发现连接提前断开
+    let expected =
+      expected_body_size(&response, offset).map(|expected| expected.saturating_sub(overlap));
+
+    let mut stream = response.bytes_stream().map(|chunk| Ok(chunk?)).boxed();
+    if overlap > 0 {
+      match self
+        .verify_overlap(stream, downloaded_size, overlap)
+        .await?
+      {
+        Some(rest) => stream = rest,
+        None => {
+          return self
+            .discard_partial("remote file differs from the partial download")
+            .await;
+        }
+      }
+    }
+
+    let body = Body {
+      resumed: should_resume,
+      expected,
+      decoder,
+      stream,
+    };
+
+    self
+      .write_temp_file(body, downloaded_size, total_size)
+      .await
+  }
+
+  /// Downloads the item's `part` of the remote file with Range requests.
+  async fn download_part(&self, part: &RemotePart) -> Result<(), ProgressDownloadError> {
+    if self.is_remote() {
+      return Err(ProgressDownloadError::RangeNotSupported { url: self.url() });
+    }
+
+    match part {
+      RemotePart::Range { start, end } => self.download_span(*start, *end, None).await,
+      RemotePart::ZipMember(path) => {
+        #[cfg(not(feature = "remote-zip"))]
+        {
+          let _ = path;
+          Err(ProgressDownloadError::MissingFeature {
+            option: "part",
+            feature: "remote-zip",
+          })
+        }
+
+        #[cfg(feature = "remote-zip")]
+        {
+          self.download_zip_member(path).await
+        }
+      }
+    }
+  }
+
+  /// Downloads the bytes from `start` up to `end` of the remote file, or to its end, and
+  /// passes them through `reader` before the `transforms`. Resumes from the size of the
+  /// temp file unless the bytes are transformed.
+  async fn download_span(
+    &self,
+    start: u64,
+    end: Option<u64>,
+    reader: Option<TransformChain>,
+  ) -> Result<(), ProgressDownloadError> {
+    let temp_file = self.tmp_file.as_ref();
+    let resumable = reader.is_none() && !self.is_transformed();
+    let (downloaded_size, state) = match resumable {
+      true => self.partial_download().await,
+      false => (0, None),
+    };
+    let offset = start + downloaded_size;
+
+    // 范围为空或已全部下载，不再请求
+    if end.is_some_and(|end| offset >= end) {
+      *self.remote.lock().unwrap() = state;
+      let body = Body {
+        resumed: true,
+        expected: Some(0),
+        decoder: None,
+        stream: futures::stream::empty().boxed(),
+      };
+      return self
+        .write_temp_file(body, downloaded_size, Some(downloaded_size))
+        .await;
+    }
+
+    let _connection = self.connection().await?;
+    let range = match end {
+      Some(end) => format!("bytes={}-{}", offset, end - 1),
+      None => format!("bytes={}-", offset),
+    };
+    let if_range = state.as_ref().and_then(ResumeState::if_range);
+    let response = self.send(range, if_range).await?;
+
+    match RangeReply::of(
+      response.status(),
+      response.headers(),
+      offset,
+      end.map(|end| end - 1),
+    ) {
+      RangeReply::Resumed => {}
+      // 从头到尾的范围就是完整的文件
+      RangeReply::Restarted if offset == 0 && end.is_none() => {}
+      // If-Range 不匹配时服务端返回完整文件
+      RangeReply::Restarted if if_range.is_some() => {
+        return self
+          .discard_partial("remote file changed since the partial download")
+          .await;
+      }
+      RangeReply::Restarted => {
+        return Err(ProgressDownloadError::RangeNotSupported { url: self.url() });
+      }
+      RangeReply::Unsatisfiable if downloaded_size > 0 => {
+        return self
+          .discard_partial("remote file is shorter than the partial download")
+          .await;
+      }
+      RangeReply::Unsatisfiable => return Err(response.error_for_status().unwrap_err().into()),
+      RangeReply::Mismatched => {
+        return Err(ProgressDownloadError::RangeNotSupported { url: self.url() });
+      }
+    }
+    // 压缩后的内容与文件的偏移不对应
+    if response.headers().contains_key(CONTENT_ENCODING) {
+      return Err(ProgressDownloadError::RangeNotSupported { url: self.url() });
+    }
+
+    let remote = ResumeState {
+      part: self.part_key(),
+      ..ResumeState::from_headers(
+        self.item.url.as_str(),
+        response.headers(),
+        content_range_total(response.headers()).or(response.content_length()),
+      )
+    };
+    let resumed = downloaded_size > 0;
+    if resumed && !state.as_ref().is_some_and(|state| state.matches(&remote)) {
+      return self
+        .discard_partial("remote file changed since the partial download")
+        .await;
+    }
+
+    let total_size = response
+      .content_length()
+      .map(|remaining_size| remaining_size + downloaded_size);
+    if let Some(total_size) = total_size.filter(|_| reader.is_none()) {
+      self.check_size(total_size, true)?;
+    }
+    if !resumable {
+      ResumeState::remove(temp_file).await;
+    } else if !resumed {
+      remote.save(temp_file).await?;
+    }
+    *self.remote.lock().unwrap() = Some(remote);
+
+    let decoder = match (reader, &self.transforms) {
+      (None, None) => None,
+      (reader, transforms) => {
+        let mut chain = reader.unwrap_or_default();
+        if let Some(transforms) = transforms {
+          chain = chain.then(transforms.create(&self.info));
+        }
+        Some(chain)
+      }
+    };
+    let body = Body {
+      resumed,
+      expected: response.content_length(),
+      decoder,
+      stream: response.bytes_stream().map(|chunk| Ok(chunk?)).boxed(),
+    };
+    self
+      .write_temp_file(body, downloaded_size, total_size)
+      .await
+  }
+
+  /// Downloads the member at `path` of the remote ZIP archive, finding its data through
+  /// the central directory at the end of the archive.
+  #[cfg(feature = "remote-zip")]
+  async fn download_zip_member(&self, path: &str) -> Result<(), ProgressDownloadError> {
+    let invalid = |message| ProgressDownloadError::RemoteZip {
+      url: self.url(),
+      message,
+    };
+
+    let (tail, archive_size) = self
+      .fetch_range(format!("bytes=-{}", remote_zip::ZIP_TAIL_LEN))
+      .await?;
+    let archive_size =
+      archive_size.ok_or_else(|| invalid("server did not send the archive size".to_string()))?;
+    if tail.len() as u64 > archive_size {
+      return Err(invalid(
+        "server sent more bytes than the size of the archive".to_string(),
+      ));
+    }
+    let archive = Archive {
+      tail,
+      size: archive_size,
+    };
+
+    let (offset, size) =
+      match remote_zip::find_directory(&archive.tail, archive_size).map_err(invalid)? {
+        remote_zip::Directory::Found { offset, size } => (offset, size),
+        remote_zip::Directory::Zip64(record) => {
+          let record = self.read_archive(&archive, record, 56).await?;
+          remote_zip::parse_zip64_end(&record).map_err(invalid)?
+        }
+      };
+    let directory = self.read_archive(&archive, offset, size).await?;
+    let member = remote_zip::find_member(&directory, path).map_err(invalid)?;
+    self.check_size(member.size, true)?;
+
+    let header = self
+      .read_archive(&archive, member.header_offset, remote_zip::LOCAL_HEADER_LEN)
+      .await?;
+    let start = member.header_offset + remote_zip::local_header_len(&header).map_err(invalid)?;
+    let end = start + member.compressed_size;
+    debug!(start, end, "downloading the ZIP member {}", path);
+    let reader = TransformChain::new().then(remote_zip::MemberReader::new(member));
+    self.download_span(start, Some(end), Some(reader)).await
+  }
+
+  /// Reads `len` bytes of the remote archive from `offset` on, from its tail if they are
+  /// part of it.
+  #[cfg(feature = "remote-zip")]
+  async fn read_archive(
+    &self,
+    archive: &Archive,
+    offset: u64,
+    len: u64,
+  ) -> Result<Bytes, ProgressDownloadError> {
+    if offset.checked_add(len).is_none_or(|end| end > archive.size) {
+      return Err(ProgressDownloadError::RemoteZip {
+        url: self.url(),
+        message: "archive is truncated".to_string(),
+      });
+    }
+    let tail_start = archive.size - archive.tail.len() as u64;
+    if offset >= tail_start {
+      let start = (offset - tail_start) as usize;
+      return Ok(archive.tail.slice(start..start + len as usize));
+    }
+
+    let (data, _) = self
+      .fetch_range(format!("bytes={}-{}", offset, offset + len - 1))
+      .await?;
+    if data.len() as u64 != len {
+      return Err(ProgressDownloadError::Incomplete {
+        expected: len,
+        received: data.len() as u64,
+      });
+    }
+    Ok(data)
+  }
+
+  /// Downloads the bytes of the remote file in `range`, along with the size of the file
+  /// if the server sent it.
+  #[cfg(feature = "remote-zip")]
+  async fn fetch_range(
+    &self,
+    range: String,
+  ) -> Result<(Bytes, Option<u64>), ProgressDownloadError> {
+    let _connection = self.connection().await?;
+    let response = self.send(range, None).await?;
+    match response.status() {
+      StatusCode::PARTIAL_CONTENT => {}
+      StatusCode::RANGE_NOT_SATISFIABLE => {
+        return Err(response.error_for_status().unwrap_err().into());
+      }
+      _ => return Err(ProgressDownloadError::RangeNotSupported { url: self.url() }),
+    }
+
+    let total_size = content_range_total(response.headers());
+    let data = tokio::time::timeout(self.timeout, response.bytes())
+      .await
+      .map_err(ProgressDownloadError::timed_out(
+        TimeoutPhase::Response,
+        self.timeout,
+      ))??;
+    self
+      .transferred
+      .fetch_add(data.len() as u64, Ordering::Relaxed);
+    Ok((data, total_size))
+  }
+
+  /// Assembles the temp file from the blocks of the existing target listed in the item's
+  /// zsync control file, downloading only the other blocks with Range requests.
+  ///
+  /// Returns `Ok(false)` if the file has to be downloaded as a whole instead: without a
+  /// control file or an existing target, if they share no block, if a partial download
+  /// can be resumed, or if the server does not serve the ranges.
+  async fn download_delta(&self) -> Result<bool, ProgressDownloadError> {
+    let Some(zsync_url) = &self.item.zsync else {
+      return Ok(false);
+    };
+
+    #[cfg(not(feature = "zsync"))]
+    {
+      let _ = zsync_url;
+      Err(ProgressDownloadError::MissingFeature {
+        option: "zsync",
+        feature: "zsync",
+      })
+    }
+
+    #[cfg(feature = "zsync")]
+    {
+      let temp_file = self.tmp_file.as_ref();
+      // 处理后的内容与远端文件的块不对应
+      let partial = tokio::fs::metadata(temp_file)
+        .await
+        .is_ok_and(|metadata| metadata.len() > 0);
+      let seed = tokio::fs::metadata(&self.target_path)
+        .await
+        .is_ok_and(|metadata| metadata.is_file());
+      if self.is_transformed() || partial || !seed {
+        return Ok(false);
+      }
+
+      let result = self.try_download_delta(zsync_url).await;
+      // 按块组装的临时文件无法续传
+      if !matches!(result, Ok(true)) {
+        let _ = tokio::fs::remove_file(temp_file).await;
+        ResumeState::remove(temp_file).await;
+      }
+      result
+    }
+  }
+
+  #[cfg(feature = "zsync")]
+  async fn try_download_delta(&self, zsync_url: &str) -> Result<bool, ProgressDownloadError> {
+    use tokio::io::AsyncWriteExt;
+
+    let temp_file = self.tmp_file.as_ref();
+    let url = match reqwest::Url::parse(&self.url()).and_then(|url| url.join(zsync_url)) {
+      Ok(url) => url,
+      Err(err) => {
+        warn!("invalid zsync URL {}: {}", zsync_url, err);
+        return Ok(false);
+      }
+    };
+    let Some(control) = self.fetch_control(url.as_str()).await? else {
+      return Ok(false);
+    };
+    let control = match zsync::ControlFile::parse(&control) {
+      Ok(control) => control,
+      Err(message) => {
+        warn!("invalid zsync control file {}: {}", url, message);
+        return Ok(false);
+      }
+    };
+    self.check_size(control.length, true)?;
+
+    // 滚动校验整个旧文件较慢，放到阻塞线程中进行
+    let seed = self.target_path.clone();
+    let (control, plan) = tokio::task::spawn_blocking(move || {
+      let plan = control.match_seed(&seed);
+      (control, plan)
+    })
+    .await
+    .map_err(std::io::Error::other)?;
+    let plan = plan.map_err(ProgressDownloadError::io_at(&self.target_path))?;
+
+    let reused = (0..plan.len())
+      .filter(|index| plan[*index].is_some())
+      .map(|index| control.block_len(index))
+      .sum::<u64>();
+    if reused == 0 {
+      debug!("the existing target shares no block with the remote file");
+      return Ok(false);
+    }
+    debug!(
+      reused,
+      length = control.length,
+      "downloading the changed blocks"
+    );
+
+    // 临时文件按块重新生成，之前的摘要状态不再有效
+    *self.hasher.lock().unwrap() = None;
+    let mut delegate = DownloadTracker::builder()
+      .reporter(self.reporter.as_ref())
+      .info(&self.info)
+      .downloaded_size(reused)
+      .total_size(Some(control.length))
+      .build();
+    delegate.init_progress();
+
+    let mut seed = tokio::fs::File::open(&self.target_path)
+      .await
+      .map_err(ProgressDownloadError::io_at(&self.target_path))?;
+    let mut file = tokio::io::BufWriter::with_capacity(
+      self.write_buffer_size,
+      tokio::fs::File::create(temp_file)
+        .await
+        .map_err(ProgressDownloadError::io_at(temp_file))?,
+    );
+    let block_size = control.block_size as u64;
+    let mut remote = None;
+    let mut index = 0;
+
+    while index < plan.len() {
+      // 连续的块合并为一次复制或一个 Range 请求
+      let mut end = index + 1;
+      while end < plan.len()
+        && match plan[index] {
+          Some(offset) => plan[end] == Some(offset + (end - index) as u64 * block_size),
+          None => plan[end].is_none(),
+        }
+      {
+        end += 1;
+      }
+      let start = index as u64 * block_size;
+      let len = (index..end)
+        .map(|block| control.block_len(block))
+        .sum::<u64>();
+
+      match plan[index] {
+        Some(offset) => {
+          seed.seek(SeekFrom::Start(offset)).await?;
+          let copied = tokio::io::copy(&mut (&mut seed).take(len), &mut file).await?;
+          // 种子文件末尾的块按 0 补齐后匹配
+          tokio::io::copy(&mut tokio::io::repeat(0).take(len - copied), &mut file).await?;
+        }
+        None => {
+          let Some(headers) = self
+            .download_range(start, len, &mut file, &mut delegate)
+            .await?
+          else {
+            return Ok(false);
+          };
+          remote = Some(headers);
+        }
+      }
+      index = end;
+    }
+    file.flush().await?;
+    drop(file);
+
+    if let Some(expected) = &control.sha1 {
+      let mut hasher = Hasher::new(HashAlgorithm::SHA1);
+      hasher.update_from_file(temp_file, control.length).await?;
+      if !hasher.finalize().eq_ignore_ascii_case(expected) {
+        warn!("file assembled from zsync blocks has the wrong SHA-1, downloading it whole");
+        return Ok(false);
+      }
+    }
+
+    *self.remote.lock().unwrap() = remote.map(|headers| {
+      ResumeState::from_headers(self.item.url.as_str(), &headers, Some(control.length))
+    });
+    delegate.finish_progress();
+    Ok(true)
+  }
+
+  /// Downloads the `len` bytes of the file from `start` on into `file`. Returns the
+  /// response headers, or `None` if the server did not answer with the range.
+  #[cfg(feature = "zsync")]
+  async fn download_range<W>(
+    &self,
+    start: u64,
+    len: u64,
+    file: &mut W,
+    delegate: &mut DownloadTracker<'_>,
+  ) -> Result<Option<HeaderMap>, ProgressDownloadError>
+  where
+    W: tokio::io::AsyncWrite + Unpin,
+  {
+    use tokio::io::AsyncWriteExt;
+
+    let end = start + len - 1;
+    let _connection = self.connection().await?;
+    let response = self.send(format!("bytes={start}-{end}"), None).await?;
+    let reply = RangeReply::of(response.status(), response.headers(), start, Some(end));
+    // 压缩后的内容与文件的偏移不对应
+    if reply != RangeReply::Resumed || response.headers().contains_key(CONTENT_ENCODING) {
+      debug!(status = %response.status(), "server did not return the requested range");
+      return Ok(None);
+    }
+
+    let headers = response.headers().clone();
+    let mut stream = response.bytes_stream().boxed();
+    let mut watchdog = self.speed_watchdog();
+    let mut received = 0;
+    while let Some(chunk) = self.next_chunk(&mut stream, &mut watchdog).await? {
+      self.throttle(chunk.len()).await;
+      self
+        .transferred
+        .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+      delegate.update_progress(chunk.len());
+      // 多出的数据不属于请求的范围
+      let take = chunk.len().min((len - received) as usize);
+      file.write_all(&chunk[..take]).await?;
+      received += take as u64;
+    }
+    ensure_complete(Some(len), received)?;
+    Ok(Some(headers))
+  }
+
+  /// Downloads the zsync control file at `url` with the headers of the item's requests,
+  /// `None` if the server refuses it with a client error.
+  #[cfg(feature = "zsync")]
+  async fn fetch_control(&self, url: &str) -> Result<Option<Bytes>, ProgressDownloadError> {
+    let request = self.item.apply_headers(self.client.get(url));
+    let request = middleware::apply(&self.request_middleware, request).await;
+    tokio::time::timeout(self.timeout, async {
+      let response = self.redirect_policy.send(&self.client, request).await?;
+      if response.status().is_client_error() {
+        warn!("cannot download {}: HTTP {}", url, response.status());
+        return Ok(None);
+      }
+      Ok(Some(response.error_for_status()?.bytes().await?))
+    })
+    .await
+    .map_err(ProgressDownloadError::timed_out(
+      TimeoutPhase::Response,
+      self.timeout,
+    ))?
+  }
+
+  /// Completes the download with the `downloaded_size` bytes of the temp file after the
+  /// server answered 416 to the request for the rest, if they are the whole file: their
+  /// size is the size of the remote file or, if that is unknown, their digest matches
+  /// the item's integrity. Otherwise the temp file is discarded and the next attempt
+  /// starts over.
+  async fn complete_partial(
+    &self,
+    response: &reqwest::Response,
+    downloaded_size: u64,
+    state: Option<ResumeState>,
+  ) -> Result<(), ProgressDownloadError> {
+    let total_size = content_range_total(response.headers())
+      .or_else(|| state.as_ref().and_then(|state| state.total_size))
+      .or(self.item.expected_size);
+    let complete = match total_size {
+      Some(total_size) => total_size == downloaded_size,
+      // 大小未知时只能通过校验和确认文件完整
+      None => downloaded_size > 0 && self.matches_integrity().await?,
+    };
+    if !complete {
+      if downloaded_size == 0 {
+        return Err(response.error_for_status_ref().unwrap_err().into());
+      }
+      return self.discard_partial("range not satisfiable").await;
+    }
+
+    debug!(bytes = downloaded_size, "temp file already complete");
+    let total_size = Some(downloaded_size);
+    let remote = state.unwrap_or_else(|| {
+      ResumeState::from_headers(self.item.url.as_str(), response.headers(), total_size)
+    });
+    *self.remote.lock().unwrap() = Some(remote);
+
+    // 没有剩余数据，按续传处理以便报告进度并计算摘要
+    let body = Body {
+      resumed: true,
+      expected: Some(0),
+      decoder: None,
+      stream: futures::stream::empty().boxed(),
+    };
+    self
+      .write_temp_file(body, downloaded_size, total_size)
+      .await
+  }
+
+  /// Whether the digest of the temp file matches the item's integrity, `false` without one.
+  async fn matches_integrity(&self) -> Result<bool, ProgressDownloadError> {
+    let Some(integrity) = &self.item.integrity else {
+      return Ok(false);
+    };
+    let digest = self.finish_digest(integrity.hash_algorithm()).await?;
+    Ok(digest.eq_ignore_ascii_case(integrity.value()))
+  }
+
+  /// Downloads the file with its source or over FTP, SFTP or S3, resuming from the size of
+  /// the temp file.
+  async fn download_remote(&self) -> Result<(), ProgressDownloadError> {
+    let temp_file = self.tmp_file.as_ref();
+    let decoder = self.decoder(&HeaderMap::new())?;
+    // 处理后的数据无法续传，总是从头下载
+    let (mut downloaded_size, state) = match decoder {
+      Some(_) => (0, None),
+      None => self.partial_download().await,
+    };
+
+    if downloaded_size == 0 && decoder.is_none() && self.clone_local().await? {
+      return Ok(());
+    }
+
+    let _connection = self.connection().await?;
+    let response = self.open_remote(downloaded_size).await?;
+
+    // FTP 和 SFTP 没有 ETag，只能使用修改时间和文件大小判断远端文件是否变化
+    let remote = ResumeState {
+      url: self.item.url.as_str().to_string(),
+      source: None,
+      etag: response.etag,
+      last_modified: response.modified,
+      total_size: response.size,
+      part: None,
+    };
+
+    if response.resumed && !state.as_ref().is_some_and(|state| state.matches(&remote)) {
+      return self
+        .discard_partial("remote file changed since the partial download")
+        .await;
+    }
 
-#[derive(Debug, TypedBuilder)]
-pub struct DownloadTaskRunner<U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path>> {
-  #[builder]
-  client: reqwest::Client,
-  #[builder]
-  progress_bar: ProgressBar,
+    if let Some(size) = response.size {
+      self.check_size(size, true)?;
+    }
 
-  #[builder]
-  tmp_file: P,
+    if !response.resumed {
+      downloaded_size = 0;
+      if decoder.is_some() {
+        ResumeState::remove(temp_file).await;
+      } else {
+        remote.save(temp_file).await?;
+      }
+    }
+    *self.remote.lock().unwrap() = Some(remote);
 
-  #[builder]
-  item: DownloadItem<U, TP>,
-  #[builder]
-  timeout: Duration,
+    let total_size = response.size;
+    let body = Body {
+      resumed: response.resumed,
+      expected: total_size.map(|size| size.saturating_sub(downloaded_size)),
+      decoder,
+      stream: response.stream,
+    };
 
-  #[builder]
-  read_chunk_timeout: Duration,
-  #[builder]
-  flush_threshold: usize,
-}
+    self
+      .write_temp_file(body, downloaded_size, total_size)
+      .await
+  }
 
-impl<U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path>> DownloadTaskRunner<U, P, TP> {
-  async fn send(&self, downloaded_size: u64) -> Result<reqwest::Response, ProgressDownloadError> {
-    let request = self
-      .client
-      .get(self.item.url.as_str())
-      .header("Range", format!("bytes={}-", downloaded_size))
-      .timeout(self.timeout);
+  /// Clones the local file of a `file://` URL into the temp file, sharing its data blocks
+  /// on file systems supporting copy-on-write clones. Returns false if the file has to be
+  /// copied instead.
+  async fn clone_local(&self) -> Result<bool, ProgressDownloadError> {
+    let url = self.url();
+    if Scheme::of(&url) != Scheme::File || source::find(&self.sources, &url).is_some() {
+      return Ok(false);
+    }
+
+    let path = file::path_of(&url)?;
+    let metadata = tokio::fs::metadata(&path)
+      .await
+      .map_err(ProgressDownloadError::io_at(&path))?;
+    if !metadata.is_file() {
+      return Ok(false);
+    }
+    self.check_size(metadata.len(), true)?;
 
-    let response = request.send().await?;
+    let temp_file = self.tmp_file.as_ref();
+    let discarded = temp_file.metadata().map(|item| item.len()).unwrap_or(0);
+    // 不使用硬链接：修改目标文件或其权限会同时改变源文件
+    if persist::reflink(&path, temp_file).await.is_err() {
+      return Ok(false);
+    }
+    self.discarded(discarded);
+    debug!(source = %path.display(), "cloned the local file");
 
-    Ok(response)
+    let remote = ResumeState {
+      url: self.item.url.as_str().to_string(),
+      source: None,
+      etag: None,
+      last_modified: metadata.modified().ok().map(httpdate::fmt_http_date),
+      total_size: Some(metadata.len()),
+      part: None,
+    };
+    remote.save(temp_file).await?;
+    *self.remote.lock().unwrap() = Some(remote);
+    *self.hasher.lock().unwrap() = None;
+
+    let mut delegate = DownloadTracker::builder()
+      .reporter(self.reporter.as_ref())
+      .info(&self.info)
+      .downloaded_size(0)
+      .total_size(Some(metadata.len()))
+      .build();
+    delegate.init_progress();
+    delegate.update_progress(metadata.len() as usize);
+    delegate.finish_progress();
+    Ok(true)
   }
 
-  pub async fn download(&self) -> Result<(), ProgressDownloadError> {
-    let temp_file = self.tmp_file.as_ref();
-    let downloaded_size = temp_file.metadata().map(|item| item.len()).unwrap_or(0);
+  /// Opens the file at the current URL with its source, or over FTP, SFTP or S3, from
+  /// `offset` on.
+  async fn open_remote(&self, offset: u64) -> Result<SourceFile, ProgressDownloadError> {
+    let url = self.url();
+    let open = async {
+      let mut file = match source::find(&self.sources, &url) {
+        Some(source) => source.open(&url, offset).await?,
+        None => remote::open(&url, offset, self.connect_timeout, &self.sftp, &self.s3).await?,
+      };
+      // 从头开始的数据流不是续传，即使数据源认为是
+      file.resumed &= offset > 0;
+      Ok(file)
+    };
+    tokio::time::timeout(self.timeout, open)
+      .await
+      .map_err(ProgressDownloadError::timed_out(
+        TimeoutPhase::Response,
+        self.timeout,
+      ))?
+  }
 
-    let response = self.send(downloaded_size).await?;
-    let supports_resume = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
-    let remaining_size = response.content_length().unwrap_or(0);
+  /// Writes `body` to the temp file, appending to the `downloaded_size` bytes already in it
+  /// if the body resumes the download.
+  async fn write_temp_file(
+    &self,
+    body: Body,
+    downloaded_size: u64,
+    total_size: Option<u64>,
+  ) -> Result<(), ProgressDownloadError> {
+    let Body {
+      mut stream,
+      resumed,
+      expected,
+      mut decoder,
+    } = body;
+    let mut received = 0;
+    // 写入临时文件的字节数，压缩响应按解压后的大小计算
+    let mut written = downloaded_size;
+    let mut hasher = self.resume_hasher(resumed, downloaded_size).await?;
 
-    let should_resume = supports_resume && downloaded_size > 0;
+    if !resumed {
+      let temp_file = self.tmp_file.as_ref();
+      self.discarded(temp_file.metadata().map(|item| item.len()).unwrap_or(0));
+    }
 
     let file = tokio::fs::OpenOptions::new()
       .write(true)
       .create(true)
-      .truncate(!should_resume)
-      .append(should_resume)
-      .open(temp_file)
-      .await?;
+      .truncate(!resumed)
+      .append(resumed)
+      .open(self.tmp_file.as_ref())
+      .await
+      .map_err(ProgressDownloadError::io_at(self.tmp_file.as_ref()))?;
+    // 保持文件长度不变，续传仍以文件大小为准；压缩响应的大小不是文件大小
+    if let Some(total_size) = total_size.filter(|_| self.preallocate && decoder.is_none()) {
+      prealloc::preallocate(&file, total_size, true).await?;
+    }
 
     let mut delegate = DownloadTracker::builder()
-      .progress_bar(&self.progress_bar)
+      .reporter(self.reporter.as_ref())
+      .info(&self.info)
       .downloaded_size(downloaded_size)
-      .remaining_size(remaining_size)
-      .url(self.item.url.clone())
+      .total_size(total_size)
       .build();
 
     delegate.init_progress();
 
-    let mut writer = tokio::io::BufWriter::with_capacity(1024 * 1024, file);
-
-    let stream = response.bytes_stream();
+    let size = total_size.filter(|_| decoder.is_none());
+    let mut writer = self
+      .temp_writer(file, downloaded_size, size, self.write_buffer_size)
+      .await?;
+    let mut reservation = Reservation::new(self.memory_budget.as_deref());
+    let mut watchdog = self.speed_watchdog();
 
-    tokio::pin!(stream);
+    loop {
+      let chunk = match self.next_chunk(&mut stream, &mut watchdog).await {
+        Ok(Some(chunk)) => chunk,
+        Ok(None) => break,
+        Err(err) => {
+          // 暂停、停止或连接出错时保存已收到的数据，下次从断开处续传
+          writer.finish().await?;
+          *self.hasher.lock().unwrap() = hasher;
+          return Err(err);
+        }
+      };
 
-    while let Some(chunk) = tokio::time::timeout(self.read_chunk_timeout, stream.next())
-      .await?
-      .transpose()?
-    {
+      self.throttle(chunk.len()).await;
+      self
+        .transferred
+        .fetch_add(chunk.len() as u64, Ordering::Relaxed);
       delegate.update_progress(chunk.len());
+      received += chunk.len() as u64;
 
+      let chunk = decode_chunk(&mut decoder, chunk, &mut delegate)?;
+      if written == 0 && !chunk.is_empty() {
+        self.check_first_chunk(&chunk)?;
+      }
+      written += chunk.len() as u64;
+      self.check_size(written, false)?;
+      // 写入缓冲区前申请内存预算；预算不足时先写出缓冲区，再等待其他下载释放内存
+      if !reservation.try_resize(writer.pending() + chunk.len()) {
+        writer.flush_partial().await?;
+        reservation.resize(chunk.len()).await;
+      }
       writer.write_all(&chunk).await?;
+      if let Some(hasher) = hasher.as_mut() {
+        hasher.update(&chunk);
+      }
 
       // 减少刷新频率，提高性能
-      if writer.buffer().len() >= self.flush_threshold {
-        writer.flush().await?;
+      if writer.pending() >= self.flush_threshold {
+        writer.flush_partial().await?;
+        reservation.try_resize(writer.pending());
+      }
+    }
+
+    // 数据不完整时先保存已收到的部分，重试时从断开处续传
+    if received < expected.unwrap_or(0) {
+      writer.finish().await?;
+      *self.hasher.lock().unwrap() = hasher;
+      return ensure_complete(expected, received);
+    }
+
+    if let Some(mut decoder) = decoder {
+      let rest = decoder.finish()?;
+      delegate.update_decoded(rest.len());
+      self.check_size(written + rest.len() as u64, false)?;
+      writer.write_all(&rest).await?;
+      if let Some(hasher) = hasher.as_mut() {
+        hasher.update(&rest);
       }
     }
 
     // 确保所有数据都写入
-    writer.flush().await?;
+    writer.finish().await?;
+    *self.hasher.lock().unwrap() = hasher;
 
-    writer.into_inner().sync_all().await?;
+    // 大小未知时以数据流结束为准，报告最终收到的字节数
+    delegate.finish_progress();
+    debug!(bytes = delegate.downloaded_size(), "response body written");
 
-    let target = self.item.target.as_ref();
+    Ok(())
+  }
 
-    if let Some(integrity) = &self.item.integrity {
-      let actual = Hashery::builder()
-        .algorithm(integrity.algorithm())
-        .build()
-        .digest(temp_file)
-        .await?;
+  /// Downloads the file over several parallel Range requests.
+  ///
+  /// Returns `Ok(false)` if the server does not support range requests, in which case
+  /// the caller should fall back to a single-connection download.
+  async fn download_segmented(&self) -> Result<bool, ProgressDownloadError> {
+    let temp_file = self.tmp_file.as_ref();
+
+    let pending = self.segments.lock().unwrap().clone();
+
+    let segments = match pending {
+      Some(segments) => segments,
+      None => {
+        // 探测服务端是否支持分段下载以及文件总大小
+        let _connection = self.connection().await?;
+        let response = self.send("bytes=0-0".to_string(), None).await?;
+        let total_size = match response.status() {
+          StatusCode::PARTIAL_CONTENT => content_range_total(response.headers()),
+          _ => None,
+        };
+
+        let Some(total_size) = total_size.filter(|size| *size > 0) else {
+          debug!("range requests unsupported, falling back to a single connection");
+          return Ok(false);
+        };
+
+        if self.decompress && decode::content_encoding(response.headers()).is_some() {
+          debug!("compressed response, falling back to a single connection");
+          return Ok(false);
+        }
+        self.check_size(total_size, true)?;
+
+        let remote =
+          ResumeState::from_headers(self.item.url.as_str(), response.headers(), Some(total_size));
+
+        // 上次停止时保存的连续数据可以继续使用
+        let (downloaded_size, state) = self.partial_download().await;
+        let prefix = match state {
+          Some(state) if state.matches(&remote) && downloaded_size <= total_size => downloaded_size,
+          _ => 0,
+        };
+        if prefix == 0 {
+          self.discarded(downloaded_size);
+        }
+        *self.remote.lock().unwrap() = Some(remote);
+
+        // 预分配临时文件，各分段写入各自的偏移位置
+        // 分段进度只保存在内存中，删除续传记录以免被单连接下载误用
+        ResumeState::remove(temp_file).await;
+        // 分段乱序写入，摘要在全部完成后读取临时文件计算
+        *self.hasher.lock().unwrap() = None;
+        let file = tokio::fs::OpenOptions::new()
+          .write(true)
+          .create(true)
+          .truncate(prefix == 0)
+          .open(temp_file)
+          .await
+          .map_err(ProgressDownloadError::io_at(temp_file))?;
+        file.set_len(total_size).await?;
+        if self.preallocate {
+          prealloc::preallocate(&file, total_size, false).await?;
+        }
+
+        let mut segments = split_segments(total_size, self.segments_per_file);
+        for segment in &mut segments {
+          segment.written = prefix.saturating_sub(segment.start).min(segment.len());
+        }
+        *self.segments.lock().unwrap() = Some(segments.clone());
+        segments
+      }
+    };
+
+    let total_size = segments.last().map(|segment| segment.end + 1).unwrap_or(0);
+    let downloaded_size = segments.iter().map(|segment| segment.written).sum::<u64>();
+
+    let delegate = DownloadTracker::builder()
+      .reporter(self.reporter.as_ref())
+      .info(&self.info)
+      .downloaded_size(downloaded_size)
+      .total_size(Some(total_size))
+      .build();
+
+    let delegate = Mutex::new(delegate);
+    delegate.lock().unwrap().init_progress();
+
+    let futures = segments
+      .iter()
+      .enumerate()
+      .filter(|(_, segment)| !segment.is_complete())
+      .map(|(index, segment)| self.download_segment(index, segment, &delegate));
+
+    // 等待所有分段结束，避免取消其它分段时丢失未写入的数据
+    let mut errors = futures::future::join_all(futures)
+      .await
+      .into_iter()
+      .filter_map(Result::err)
+      .collect::<Vec<_>>();
+
+    if !errors.is_empty() {
+      let stopped = errors.iter().position(|err| {
+        matches!(
+          err,
+          ProgressDownloadError::Interrupted | ProgressDownloadError::DeadlineExceeded { .. }
+        )
+      });
+      // 停止和超时优先于单个分段的错误
+      let Some(index) = stopped else {
+        return Err(errors.swap_remove(0));
+      };
+      self.save_segments().await?;
+      return Err(errors.swap_remove(index));
+    }
+
+    *self.segments.lock().unwrap() = None;
+
+    debug!(
+      bytes = delegate.lock().unwrap().downloaded_size(),
+      "segments written"
+    );
+
+    Ok(true)
+  }
+
+  async fn download_segment(
+    &self,
+    index: usize,
+    segment: &Segment,
+    delegate: &Mutex<DownloadTracker<'_>>,
+  ) -> Result<(), ProgressDownloadError> {
+    let offset = segment.start + segment.written;
+
+    let _connection = self.connection().await?;
+    let response = self
+      .send(format!("bytes={}-{}", offset, segment.end), None)
+      .await?;
+
+    let reply = RangeReply::of(
+      response.status(),
+      response.headers(),
+      offset,
+      Some(segment.end),
+    );
+    if reply != RangeReply::Resumed {
+      return Err(
+        std::io::Error::other(format!(
+          "unexpected status {} for segment {}",
+          response.status(),
+          index
+        ))
+        .into(),
+      );
+    }
+
+    let mut file = tokio::fs::OpenOptions::new()
+      .write(true)
+      .open(self.tmp_file.as_ref())
+      .await
+      .map_err(ProgressDownloadError::io_at(self.tmp_file.as_ref()))?;
+    file.seek(SeekFrom::Start(offset)).await?;
+    // 临时文件已预分配为完整大小
+    let size = file.metadata().await?.len();
+
+    let mut writer = self
+      .temp_writer(file, offset, Some(size), self.flush_threshold)
+      .await?;
+    let mut reservation = Reservation::new(self.memory_budget.as_deref());
+
+    let stream = response.bytes_stream();
+
+    tokio::pin!(stream);
+
+    // 只有 flush 之后的数据才算写入，避免重试时跳过丢失的缓冲数据
+    let mut unflushed = 0;
+    let mut received = 0;
+    let mut watchdog = self.speed_watchdog();
+
+    loop {
+      let chunk = match self.next_chunk(&mut stream, &mut watchdog).await {
+        Ok(Some(chunk)) => chunk,
+        Ok(None) => break,
+        Err(err) => {
+          writer.finish().await?;
+          self.mark_written(index, unflushed);
+          return Err(err);
+        }
+      };
+
+      self.throttle(chunk.len()).await;
+      self
+        .transferred
+        .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+      delegate.lock().unwrap().update_progress(chunk.len());
+
+      // 写入缓冲区前申请内存预算；预算不足时先写出缓冲区，再等待其他下载释放内存
+      if !reservation.try_resize(writer.pending() + chunk.len()) {
+        self
+          .flush_segment(&mut writer, index, &mut unflushed)
+          .await?;
+        reservation.resize(chunk.len()).await;
+      }
+      writer.write_all(&chunk).await?;
+      unflushed += chunk.len() as u64;
+      received += chunk.len() as u64;
+
+      if writer.pending() >= self.flush_threshold {
+        self
+          .flush_segment(&mut writer, index, &mut unflushed)
+          .await?;
+        reservation.try_resize(writer.pending());
+      }
+    }
+
+    writer.finish().await?;
+    self.mark_written(index, unflushed);
+
+    ensure_complete(Some(segment.end - offset + 1), received)
+  }
+
+  /// Keeps the data written from the start of the file up to the first gap, together
+  /// with its resume state, so the next run can continue the download.
+  async fn save_segments(&self) -> Result<(), ProgressDownloadError> {
+    let temp_file = self.tmp_file.as_ref();
+
+    let segments = self.segments.lock().unwrap().take().unwrap_or_default();
+    let prefix = contiguous_prefix(&segments);
+
+    let file = tokio::fs::OpenOptions::new()
+      .write(true)
+      .open(temp_file)
+      .await
+      .map_err(ProgressDownloadError::io_at(temp_file))?;
+    file.set_len(prefix).await?;
+    file.sync_all().await?;
+
+    let remote = self.remote.lock().unwrap().clone();
+    if let Some(remote) = remote {
+      remote.save(temp_file).await?;
+    }
+
+    debug!(bytes = prefix, "kept the contiguous part of the segments");
+    Ok(())
+  }
+
+  /// Writes out the buffered data of segment `index`, counting it as written.
+  async fn flush_segment(
+    &self,
+    writer: &mut TempWriter,
+    index: usize,
+    unflushed: &mut u64,
+  ) -> std::io::Result<()> {
+    writer.flush_partial().await?;
+    // 直接 I/O 会保留不足一个块的数据
+    let pending = writer.pending() as u64;
+    self.mark_written(index, *unflushed - pending);
+    *unflushed = pending;
+    Ok(())
+  }
+
+  fn mark_written(&self, index: usize, bytes: u64) {
+    if let Some(segments) = self.segments.lock().unwrap().as_mut() {
+      segments[index].written += bytes;
+    }
+  }
+
+  async fn try_download_to_writer<W: ChunkWriter>(
+    &self,
+    writer: &tokio::sync::Mutex<W>,
+  ) -> Result<(), ProgressDownloadError> {
+    let offset = self.streamed.load(Ordering::Relaxed);
+
+    let _connection = self.connection().await?;
+    let Body {
+      mut stream,
+      resumed,
+      expected,
+      mut decoder,
+    } = self.open_body(offset).await?;
+    let encoded = decoder.is_some();
+
+    // 服务端不支持续传时会返回完整内容，跳过已经写入的部分
+    // 压缩响应总是从头开始，进度按收到的压缩字节计算
+    let (mut skip, downloaded_size, total_size) = if resumed {
+      (0, offset, expected.map(|size| size + offset))
+    } else if encoded {
+      (offset, 0, expected)
+    } else {
+      (offset, offset, expected)
+    };
+
+    if let Some(total_size) = total_size.filter(|_| !encoded) {
+      self.check_size(total_size, true)?;
+    }
+    if !resumed {
+      self.discarded(offset);
+    }
 
+    let mut received = 0;
+
+    let mut delegate = DownloadTracker::builder()
+      .reporter(self.reporter.as_ref())
+      .info(&self.info)
+      .downloaded_size(downloaded_size)
+      .total_size(total_size)
+      .build();
+
+    delegate.init_progress();
+
+    let mut writer = writer.lock().await;
+
+    let mut chunks = Vec::with_capacity(1);
+    let mut watchdog = self.speed_watchdog();
+
+    loop {
+      match self.next_chunk(&mut stream, &mut watchdog).await? {
+        Some(chunk) => {
+          self.throttle(chunk.len()).await;
+          self
+            .transferred
+            .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+          received += chunk.len() as u64;
+          if encoded {
+            delegate.update_progress(chunk.len());
+          }
+          chunks.push(decode_chunk(&mut decoder, chunk, &mut delegate)?);
+        }
+        // 压缩流不完整时无法解压剩余数据
+        None if received < expected.unwrap_or(0) => break,
+        None => match decoder.take() {
+          Some(mut decoder) => {
+            let rest = decoder.finish()?;
+            delegate.update_decoded(rest.len());
+            chunks.push(rest);
+          }
+          None => break,
+        },
+      }
+
+      for mut chunk in chunks.drain(..) {
+        if skip > 0 {
+          let skipped = skip.min(chunk.len() as u64);
+          skip -= skipped;
+          chunk = chunk.slice(skipped as usize..);
+        }
+        if chunk.is_empty() {
+          continue;
+        }
+
+        if !encoded {
+          delegate.update_progress(chunk.len());
+        }
+
+        let streamed = self.streamed.load(Ordering::Relaxed);
+        // 写入目标的数据无法撤回，只能检查第一个数据块
+        if streamed == 0 {
+          self.check_first_chunk(&chunk)?;
+        }
+        let streamed = streamed + chunk.len() as u64;
+        self.check_size(streamed, false)?;
+        writer.write_chunk(chunk).await?;
+        self.streamed.store(streamed, Ordering::Relaxed);
+      }
+    }
+
+    writer.flush_chunks().await?;
+
+    ensure_complete(expected, received)?;
+
+    delegate.finish_progress();
+    debug!(bytes = delegate.downloaded_size(), "response body written");
+
+    Ok(())
+  }
+
+  /// Requests the file from `offset` on, over the protocol of the current URL.
+  async fn open_body(&self, offset: u64) -> Result<Body, ProgressDownloadError> {
+    // 处理后的数据无法续传，从头请求后跳过已写入的部分
+    let offset = match self.transforms {
+      Some(_) => 0,
+      None => offset,
+    };
+
+    if self.is_remote() {
+      let response = self.open_remote(offset).await?;
+      let start = if response.resumed { offset } else { 0 };
+
+      return Ok(Body {
+        resumed: response.resumed,
+        expected: response.size.map(|size| size.saturating_sub(start)),
+        decoder: self.decoder(&HeaderMap::new())?,
+        stream: response.stream,
+      });
+    }
+
+    let response = self.send(format!("bytes={}-", offset), None).await?;
+
+    let decoder = self.decoder(response.headers())?;
+    let partial = match RangeReply::of(response.status(), response.headers(), offset, None) {
+      // 已写入目标的数据就是完整的文件
+      RangeReply::Unsatisfiable
+        if offset > 0
+          && content_range_total(response.headers()).or(self.item.expected_size)
+            == Some(offset) =>
+      {
+        return Ok(Body {
+          resumed: true,
+          expected: Some(0),
+          decoder: None,
+          stream: futures::stream::empty().boxed(),
+        });
+      }
+      RangeReply::Unsatisfiable => return Err(response.error_for_status().unwrap_err().into()),
+      RangeReply::Mismatched => {
+        return Err(ProgressDownloadError::RangeNotSupported { url: self.url() });
+      }
+      RangeReply::Resumed => true,
+      RangeReply::Restarted => false,
+    };
+
+    if partial && decoder.is_some() && offset > 0 {
+      return Err(ProgressDownloadError::RangeNotSupported { url: self.url() });
+    }
+
+    Ok(Body {
+      resumed: partial && decoder.is_none(),
+      expected: expected_body_size(&response, offset),
+      decoder,
+      stream: response.bytes_stream().map(|chunk| Ok(chunk?)).boxed(),
+    })
+  }
+
+  /// Verifies the integrity of the temp file and moves it to the target path.
+  async fn finish(&self) -> Result<(), ProgressDownloadError> {
+    let temp_file = self.tmp_file.as_ref();
+    let target = self.target_path.as_path();
+
+    let size = tokio::fs::metadata(temp_file).await?.len();
+    self.check_size(size, true)?;
+
+    let mut head = Vec::with_capacity(sniff::SNIFF_LEN);
+    tokio::fs::File::open(temp_file)
+      .await
+      .map_err(ProgressDownloadError::io_at(temp_file))?
+      .take(sniff::SNIFF_LEN as u64)
+      .read_to_end(&mut head)
+      .await?;
+    self.check_content(&head)?;
+
+    let digest = match self.hash_algorithm() {
+      Some(algorithm) => Some(self.finish_digest(algorithm).await?),
+      None => None,
+    };
+
+    if let (Some(integrity), Some(actual)) = (&self.item.integrity, digest.clone()) {
       let expect = integrity.value().to_string();
 
-      if actual != expect {
+      if !actual.eq_ignore_ascii_case(&expect) {
+        // 校验失败后会重新下载整个文件
+        self.discarded(size);
         tokio::fs::remove_file(temp_file).await?;
+        ResumeState::remove(temp_file).await;
         return Err(ProgressDownloadError::IntegrityHash {
           expect,
           actual,
@@ -118,23 +2618,407 @@ impl<U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path>> DownloadTaskRunner<U,
       }
     }
 
-    // 确保目标文件的父目录存在
-    if let Some(parent) = target.parent() {
-      tokio::fs::create_dir_all(parent).await?;
+    self.verify_signature().await?;
+    self.apply_metadata(temp_file).await?;
+
+    let Some(target) = self.persist(target).await? else {
+      return Ok(());
+    };
+
+    ResumeState::remove(temp_file).await;
+    *self.digest.lock().unwrap() = digest;
+
+    // 记录远端文件信息，供下次判断文件是否需要更新
+    if self.skip_unchanged {
+      let remote = self.remote.lock().unwrap().clone();
+      if let Some(remote) = remote {
+        remote.save(&target).await?;
+      }
     }
 
-    if let Err(e) = tokio::fs::rename(&self.tmp_file, &target).await {
-      if e.kind() == ErrorKind::CrossesDevices {
-        // 跨设备重命名失败，尝试复制
-        tokio::fs::copy(&self.tmp_file, target).await?;
-        tokio::fs::remove_file(&self.tmp_file).await?;
-      } else {
-        return Err(e.into());
+    debug!(target = %target.display(), "😆 Download Success");
+
+    Ok(())
+  }
+
+  /// Verifies the item's detached signature of the complete temp file, if it has one.
+  async fn verify_signature(&self) -> Result<(), ProgressDownloadError> {
+    let Some(signature) = &self.item.signature else {
+      return Ok(());
+    };
+    let invalid = |message: String| ProgressDownloadError::SignatureInvalid {
+      target: self.target_path.clone(),
+      message,
+    };
+
+    #[cfg(not(feature = "minisign"))]
+    {
+      let _ = (signature, invalid, &self.signature_key);
+      Err(ProgressDownloadError::MissingFeature {
+        option: "signature",
+        feature: "minisign",
+      })
+    }
+
+    #[cfg(feature = "minisign")]
+    {
+      let Some(public_key) = &self.signature_key else {
+        return Err(invalid("no signature_key configured".to_string()));
+      };
+      let signature = match signature {
+        Signature::Inline(signature) => signature.clone(),
+        Signature::Url(url) => self.fetch_signature(url).await?,
+      };
+      signature::verify(public_key, &signature, self.tmp_file.as_ref())
+        .await
+        .map_err(invalid)?;
+      debug!("signature verified");
+      Ok(())
+    }
+  }
+
+  /// Downloads the signature file at `url` with the headers of the item's requests. A
+  /// signature the server refuses with a client error counts as invalid, other errors
+  /// are retried.
+  #[cfg(feature = "minisign")]
+  async fn fetch_signature(&self, url: &str) -> Result<String, ProgressDownloadError> {
+    let request = self.item.apply_headers(self.client.get(url));
+    let request = middleware::apply(&self.request_middleware, request).await;
+    let response = tokio::time::timeout(self.timeout, async {
+      let response = self.redirect_policy.send(&self.client, request).await?;
+      if response.status().is_client_error() {
+        return Err(ProgressDownloadError::SignatureInvalid {
+          target: self.target_path.clone(),
+          message: format!("cannot download {}: HTTP {}", url, response.status()),
+        });
       }
+      Ok(response.error_for_status()?.text().await?)
+    })
+    .await
+    .map_err(ProgressDownloadError::timed_out(
+      TimeoutPhase::Response,
+      self.timeout,
+    ))??;
+    Ok(response)
+  }
+
+  /// Sets the modification time and permissions of the complete temp file, before it is
+  /// moved into place.
+  async fn apply_metadata(&self, temp_file: &Path) -> std::io::Result<()> {
+    let modified = self
+      .remote
+      .lock()
+      .unwrap()
+      .as_ref()
+      .and_then(ResumeState::modified);
+    if let Some(modified) = modified.filter(|_| self.preserve_mtime) {
+      let file = tokio::fs::File::options()
+        .write(true)
+        .open(temp_file)
+        .await?
+        .into_std()
+        .await;
+      file.set_modified(modified)?;
     }
 
-    debug!("😆 Download Success: {}", target.display());
+    #[cfg(unix)]
+    if let Some(mode) = self.file_mode {
+      use std::os::unix::fs::PermissionsExt;
+      tokio::fs::set_permissions(temp_file, std::fs::Permissions::from_mode(mode)).await?;
+    }
+    // 其他平台没有 Unix 权限位
+    #[cfg(not(unix))]
+    let _ = self.file_mode;
 
     Ok(())
   }
+
+  /// Moves the complete temp file into place according to the [`OverwritePolicy`].
+  ///
+  /// Returns the path the file was saved to, or `None` if an existing target was kept.
+  async fn persist(&self, target: &Path) -> Result<Option<PathBuf>, ProgressDownloadError> {
+    let temp_file = self.tmp_file.as_ref();
+    let mut path = target.to_path_buf();
+
+    loop {
+      let no_clobber = self.overwrite != OverwritePolicy::Overwrite;
+      if persist::persist(temp_file, &path, no_clobber)
+        .await
+        .map_err(ProgressDownloadError::io_at(&path))?
+      {
+        break;
+      }
+
+      match self.overwrite {
+        OverwritePolicy::RenameWithSuffix => path = persist::suffixed_path(target).await,
+        policy => {
+          tokio::fs::remove_file(temp_file).await?;
+          ResumeState::remove(temp_file).await;
+          if policy == OverwritePolicy::Error {
+            return Err(ProgressDownloadError::TargetExists { path });
+          }
+          self.kept_existing.store(true, Ordering::Relaxed);
+          return Ok(None);
+        }
+      }
+    }
+
+    if path != target {
+      *self.saved_to.lock().unwrap() = Some(path.clone());
+    }
+    Ok(Some(path))
+  }
+
+  /// The digest of the complete temp file, hashing only the bytes not already hashed
+  /// while they were written.
+  async fn finish_digest(&self, algorithm: HashAlgorithm) -> Result<String, ProgressDownloadError> {
+    let temp_file = self.tmp_file.as_ref();
+    let size = tokio::fs::metadata(temp_file).await?.len();
+
+    let previous = self.hasher.lock().unwrap().take();
+    let mut hasher = match previous {
+      Some(hasher) if hasher.position() <= size => hasher,
+      _ => Hasher::new(algorithm),
+    };
+    hasher.update_from_file(temp_file, size).await?;
+
+    Ok(hasher.finalize())
+  }
+
+  /// Unpacks the downloaded archive into the item's `extract_to` directory, if set.
+  pub async fn extract(&self) -> Result<(), ProgressDownloadError> {
+    let Some(dir) = self.item.extract_to.clone() else {
+      return Ok(());
+    };
+    let archive = self.target();
+
+    let error = |message: String| ProgressDownloadError::Extract {
+      archive: archive.clone(),
+      message,
+    };
+
+    #[cfg(not(feature = "extract"))]
+    {
+      let _ = dir;
+      Err(error(
+        "archive extraction requires the `extract` feature".to_string(),
+      ))
+    }
+
+    #[cfg(feature = "extract")]
+    {
+      let name = archive
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+      let Some(format) = crate::extract::ArchiveFormat::from_file_name(&name) else {
+        return Err(error("unsupported archive format".to_string()));
+      };
+
+      let reporter = self.reporter.clone();
+      let info = self.info.clone();
+      let path = archive.clone();
+
+      // 解压是阻塞操作，放到阻塞线程池中执行
+      tokio::task::spawn_blocking(move || {
+        crate::extract::extract(&path, format, &dir, |extracted, total| {
+          reporter.on_extracting(&info, extracted, total)
+        })
+      })
+      .await
+      .map_err(|err| error(err.to_string()))?
+      .map_err(|err| error(err.to_string()))?;
+
+      debug!(archive = %archive.display(), "archive extracted");
+
+      Ok(())
+    }
+  }
+}
+
+/// Decompresses and transforms `chunk` if the body needs to, counting the decoded bytes.
+fn decode_chunk(
+  decoder: &mut Option<TransformChain>,
+  chunk: Bytes,
+  delegate: &mut DownloadTracker<'_>,
+) -> Result<Bytes, ProgressDownloadError> {
+  let Some(decoder) = decoder else {
+    return Ok(chunk);
+  };
+
+  let decoded = decoder.transform(chunk)?;
+  delegate.update_decoded(decoded.len());
+  Ok(decoded)
+}
+
+/// The size of the response body according to its `Content-Length`, or to the
+/// `Content-Range` total of a partial response starting at `offset`.
+fn expected_body_size(response: &reqwest::Response, offset: u64) -> Option<u64> {
+  response.content_length().or_else(|| {
+    (response.status() == StatusCode::PARTIAL_CONTENT)
+      .then(|| content_range_total(response.headers()))
+      .flatten()
+      .map(|total| total.saturating_sub(offset))
+  })
+}
+
+/// Fails with [`ProgressDownloadError::Incomplete`] if fewer bytes than `expected` were
+/// received, i.e. the connection was closed early.
+fn ensure_complete(expected: Option<u64>, received: u64) -> Result<(), ProgressDownloadError> {
+  match expected {
+    Some(expected) if received < expected => {
+      debug!(expected, received, "response body truncated");
+      Err(ProgressDownloadError::Incomplete { expected, received })
+    }
+    _ => Ok(()),
+  }
+}
+
+/// Parses the total size from a `Content-Range: bytes start-end/total` header.
+pub(crate) fn content_range_total(headers: &HeaderMap) -> Option<u64> {
+  let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+  let (_, total) = value.rsplit_once('/')?;
+  total.trim().parse().ok()
+}
+
+/// Number of bytes written from the start of the file up to the first incomplete segment.
+fn contiguous_prefix(segments: &[Segment]) -> u64 {
+  let mut prefix = 0;
+  for segment in segments {
+    prefix += segment.written;
+    if !segment.is_complete() {
+      break;
+    }
+  }
+  prefix
+}
+
+/// Splits `total_size` bytes into at most `count` contiguous, non-overlapping segments.
+fn split_segments(total_size: u64, count: usize) -> Vec<Segment> {
+  let count = (count as u64).clamp(1, total_size.max(1));
+  let segment_size = total_size.div_ceil(count);
+
+  (0..count)
+    .map(|index| index * segment_size)
+    .take_while(|start| *start < total_size)
+    .map(|start| Segment {
+      start,
+      end: (start + segment_size).min(total_size) - 1,
+      written: 0,
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use reqwest::header::HeaderValue;
+
+  use super::*;
+
+  #[test]
+  fn test_split_segments() {
+    let segments = split_segments(10, 3);
+    let ranges = segments
+      .iter()
+      .map(|segment| (segment.start, segment.end))
+      .collect::<Vec<_>>();
+    assert_eq!(ranges, vec![(0, 3), (4, 7), (8, 9)]);
+
+    assert_eq!(split_segments(2, 8).len(), 2);
+  }
+
+  #[test]
+  fn test_contiguous_prefix() {
+    let mut segments = split_segments(10, 3);
+    segments[0].written = 4;
+    segments[1].written = 2;
+    segments[2].written = 2;
+    assert_eq!(contiguous_prefix(&segments), 6);
+
+    segments[0].written = 1;
+    assert_eq!(contiguous_prefix(&segments), 1);
+  }
+
+  #[test]
+  fn test_content_range_total() {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_RANGE, HeaderValue::from_static("bytes 0-0/1234"));
+    assert_eq!(content_range_total(&headers), Some(1234));
+
+    headers.insert(CONTENT_RANGE, HeaderValue::from_static("bytes 0-0/*"));
+    assert_eq!(content_range_total(&headers), None);
+  }
+
+  #[test]
+  fn test_range_reply() {
+    let reply = |status, content_range: &'static str, start, end| {
+      let mut headers = HeaderMap::new();
+      if !content_range.is_empty() {
+        headers.insert(CONTENT_RANGE, HeaderValue::from_static(content_range));
+      }
+      RangeReply::of(status, &headers, start, end)
+    };
+    let partial = StatusCode::PARTIAL_CONTENT;
+
+    assert_eq!(reply(StatusCode::OK, "", 100, None), RangeReply::Restarted);
+    assert_eq!(
+      reply(StatusCode::RANGE_NOT_SATISFIABLE, "", 100, None),
+      RangeReply::Unsatisfiable
+    );
+    assert_eq!(
+      reply(partial, "bytes 100-199/200", 100, None),
+      RangeReply::Resumed
+    );
+    assert_eq!(
+      reply(partial, "bytes 100-199/*", 100, None),
+      RangeReply::Resumed
+    );
+    assert_eq!(
+      reply(partial, "bytes 100-199/200", 50, None),
+      RangeReply::Mismatched
+    );
+    // 返回 206 但内容从头开始
+    assert_eq!(
+      reply(partial, "bytes 0-199/200", 100, None),
+      RangeReply::Restarted
+    );
+    // 没有到达文件末尾，或与总大小矛盾
+    assert_eq!(
+      reply(partial, "bytes 100-149/200", 100, None),
+      RangeReply::Mismatched
+    );
+    assert_eq!(
+      reply(partial, "bytes 100-299/200", 100, None),
+      RangeReply::Mismatched
+    );
+    assert_eq!(
+      reply(partial, "bytes 100-149/200", 100, Some(149)),
+      RangeReply::Resumed
+    );
+    assert_eq!(
+      reply(partial, "bytes 100-199/200", 100, Some(249)),
+      RangeReply::Resumed
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_RANGE, HeaderValue::from_static("bytes 100-199/200"));
+    headers.insert(CONTENT_LENGTH, HeaderValue::from_static("50"));
+    assert_eq!(
+      RangeReply::of(partial, &headers, 100, None),
+      RangeReply::Mismatched
+    );
+  }
+
+  #[test]
+  fn test_ensure_complete() {
+    assert!(ensure_complete(Some(10), 10).is_ok());
+    assert!(ensure_complete(None, 3).is_ok());
+    assert!(matches!(
+      ensure_complete(Some(10), 4),
+      Err(ProgressDownloadError::Incomplete {
+        expected: 10,
+        received: 4
+      })
+    ));
+  }
 }
@@ -0,0 +1,156 @@
+use std::fmt;
+
+use typed_builder::TypedBuilder;
+
+use crate::err::ProgressDownloadError;
+
+/// A client certificate with its private key, presented to servers requiring mutual TLS.
+#[derive(Clone)]
+pub enum ClientIdentity {
+  /// A PKCS#12 archive (`.p12` or `.pfx`) and its password.
+  /// Requires the `native-tls` feature.
+  Pkcs12 { der: Vec<u8>, password: String },
+  /// A PEM certificate chain and the PEM private key belonging to its first certificate.
+  /// With the `native-tls` feature the key must be in PKCS#8 format.
+  Pem { certificate: Vec<u8>, key: Vec<u8> },
+}
+
+impl fmt::Debug for ClientIdentity {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // 不输出私钥和密码
+    match self {
+      Self::Pkcs12 { .. } => f.write_str("ClientIdentity::Pkcs12"),
+      Self::Pem { .. } => f.write_str("ClientIdentity::Pem"),
+    }
+  }
+}
+
+/// TLS options of the HTTP client, for servers requiring client certificates and
+/// internal mirrors with certificates from a private CA.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use robust_downloader::{ClientIdentity, RobustDownloader, TlsOptions};
+///
+/// // 使用客户端证书访问内部镜像，并信任公司 CA
+/// let downloader = RobustDownloader::builder()
+///   .tls(
+///     TlsOptions::builder()
+///       .identity(ClientIdentity::Pem {
+///         certificate: std::fs::read("client.crt").unwrap(),
+///         key: std::fs::read("client.key").unwrap(),
+///       })
+///       .root_certificates(vec![std::fs::read("company-ca.pem").unwrap()])
+///       .build(),
+///   )
+///   .build();
+/// ```
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct TlsOptions {
+  /// Client certificate presented to servers requiring mutual TLS.
+  /// Defaults to none.
+  #[builder(default, setter(strip_option))]
+  pub identity: Option<ClientIdentity>,
+
+  /// Root certificates trusted in addition to the system ones, each a PEM file that may
+  /// hold several certificates, or a single DER certificate.
+  /// Defaults to none.
+  #[builder(default)]
+  pub root_certificates: Vec<Vec<u8>>,
+
+  /// Whether to accept any server certificate, including expired, self-signed and ones
+  /// for other hosts. Only meant for internal mirrors on trusted networks: it makes
+  /// connections open to man-in-the-middle attacks.
+  /// Defaults to false.
+  #[builder(default = false)]
+  pub accept_invalid_certs: bool,
+}
+
+impl TlsOptions {
+  /// Applies the options to `builder`. Fails if a certificate cannot be parsed or the
+  /// options need a TLS feature that is not enabled.
+  pub(crate) fn apply(
+    &self,
+    builder: reqwest::ClientBuilder,
+  ) -> Result<reqwest::ClientBuilder, ProgressDownloadError> {
+    #[cfg(any(feature = "native-tls", feature = "openssl", feature = "rustls"))]
+    {
+      let mut builder = builder;
+      if let Some(identity) = &self.identity {
+        builder = identity.apply(builder)?;
+      }
+      for certificate in &self.root_certificates {
+        let certificates = if certificate.starts_with(b"-----BEGIN") {
+          reqwest::Certificate::from_pem_bundle(certificate)?
+        } else {
+          vec![reqwest::Certificate::from_der(certificate)?]
+        };
+        for certificate in certificates {
+          builder = builder.add_root_certificate(certificate);
+        }
+      }
+      if self.accept_invalid_certs {
+        builder = builder.danger_accept_invalid_certs(true);
+      }
+      Ok(builder)
+    }
+
+    #[cfg(not(any(feature = "native-tls", feature = "openssl", feature = "rustls")))]
+    {
+      if self.identity.is_some() || !self.root_certificates.is_empty() || self.accept_invalid_certs
+      {
+        return Err(ProgressDownloadError::Tls {
+          message: "TLS options require the `native-tls`, `openssl` or `rustls` feature"
+            .to_string(),
+        });
+      }
+      Ok(builder)
+    }
+  }
+}
+
+impl ClientIdentity {
+  /// Makes `builder` present this certificate.
+  #[cfg(any(feature = "native-tls", feature = "rustls"))]
+  fn apply(
+    &self,
+    builder: reqwest::ClientBuilder,
+  ) -> Result<reqwest::ClientBuilder, ProgressDownloadError> {
+    // 启用 native-tls 时客户端使用系统 TLS，证书格式需与其一致
+    let identity = match self {
+      #[cfg(feature = "native-tls")]
+      Self::Pkcs12 { der, password } => reqwest::Identity::from_pkcs12_der(der, password)?,
+      #[cfg(not(feature = "native-tls"))]
+      Self::Pkcs12 { .. } => {
+        return Err(ProgressDownloadError::Tls {
+          message: "PKCS#12 client certificates require the `native-tls` feature".to_string(),
+        });
+      }
+      #[cfg(feature = "native-tls")]
+      Self::Pem { certificate, key } => reqwest::Identity::from_pkcs8_pem(certificate, key)?,
+      #[cfg(not(feature = "native-tls"))]
+      Self::Pem { certificate, key } => {
+        let mut pem = certificate.clone();
+        pem.push(b'\n');
+        pem.extend_from_slice(key);
+        reqwest::Identity::from_pem(&pem)?
+      }
+    };
+    Ok(builder.identity(identity))
+  }
+
+  /// `reqwest` supports no client certificates with only the `openssl` feature.
+  #[cfg(all(
+    feature = "openssl",
+    not(any(feature = "native-tls", feature = "rustls"))
+  ))]
+  fn apply(
+    &self,
+    _builder: reqwest::ClientBuilder,
+  ) -> Result<reqwest::ClientBuilder, ProgressDownloadError> {
+    Err(ProgressDownloadError::Tls {
+      message: "client certificates require the `native-tls` or `rustls` feature".to_string(),
+    })
+  }
+}
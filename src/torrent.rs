@@ -0,0 +1,280 @@
+use std::{
+  fmt,
+  num::NonZeroU32,
+  path::{Path, PathBuf},
+  sync::Arc,
+  time::Duration,
+};
+
+use bytes::Bytes;
+use librqbit::{
+  AddTorrent, AddTorrentOptions, ManagedTorrent, Session, SessionOptions, TorrentStats,
+  limits::LimitsConfig,
+};
+use tracing::{Instrument, info_span};
+
+use crate::{DownloadInfo, ProgressDownloadError, RobustDownloader, TimeoutPhase};
+
+/// How often the swarm progress is reported.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A torrent to download with [`RobustDownloader::download_torrent`].
+///
+/// Strings starting with `magnet:`, `http://` or `https://` convert to [`Url`](Self::Url),
+/// other strings and paths to [`File`](Self::File).
+#[derive(Debug, Clone)]
+pub enum TorrentSource {
+  /// A magnet link, or the URL of a `.torrent` file.
+  Url(String),
+  /// A local `.torrent` file.
+  File(PathBuf),
+  /// The contents of a `.torrent` file.
+  Bytes(Bytes),
+}
+
+impl TorrentSource {
+  /// Describes the source in progress events and errors.
+  fn describe(&self) -> String {
+    match self {
+      Self::Url(url) => url.clone(),
+      Self::File(path) => path.display().to_string(),
+      Self::Bytes(_) => "<torrent>".to_string(),
+    }
+  }
+}
+
+impl From<&str> for TorrentSource {
+  fn from(value: &str) -> Self {
+    let is_url = ["magnet:", "http://", "https://"].iter().any(|prefix| {
+      value
+        .get(..prefix.len())
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case(prefix))
+    });
+
+    if is_url {
+      Self::Url(value.to_string())
+    } else {
+      Self::File(PathBuf::from(value))
+    }
+  }
+}
+
+impl From<String> for TorrentSource {
+  fn from(value: String) -> Self {
+    Self::from(value.as_str())
+  }
+}
+
+impl From<PathBuf> for TorrentSource {
+  fn from(value: PathBuf) -> Self {
+    Self::File(value)
+  }
+}
+
+impl From<&Path> for TorrentSource {
+  fn from(value: &Path) -> Self {
+    Self::File(value.to_path_buf())
+  }
+}
+
+impl From<Vec<u8>> for TorrentSource {
+  fn from(value: Vec<u8>) -> Self {
+    Self::Bytes(value.into())
+  }
+}
+
+impl From<Bytes> for TorrentSource {
+  fn from(value: Bytes) -> Self {
+    Self::Bytes(value)
+  }
+}
+
+impl RobustDownloader {
+  /// Downloads the files of a torrent from the BitTorrent swarm into `output_dir`.
+  ///
+  /// Progress is reported to the downloader's [`ProgressReporter`](crate::ProgressReporter)
+  /// like any other download, with the number of connected peers passed to
+  /// [`on_peers`](crate::ProgressReporter::on_peers). Files already present in `output_dir`
+  /// are verified and only their missing pieces are downloaded. `timeout` bounds resolving
+  /// the metadata of a magnet link, `max_bytes_per_sec` limits the download speed.
+  ///
+  /// # Returns
+  ///
+  /// Returns the paths of the downloaded files.
+  ///
+  /// # Example
+  ///
+  /// ```rust,no_run
+  /// use robust_downloader::RobustDownloader;
+  /// async fn example() -> Result<(), Box<dyn std::error::Error>> {
+  /// let downloader = RobustDownloader::builder().build();
+  /// let files = downloader
+  ///   .download_torrent("magnet:?xt=urn:btih:...", "datasets")
+  ///   .await?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub async fn download_torrent(
+    &self,
+    source: impl Into<TorrentSource>,
+    output_dir: impl AsRef<Path>,
+  ) -> Result<Vec<PathBuf>, ProgressDownloadError> {
+    let source = source.into();
+    let output_dir = output_dir.as_ref();
+    let info = DownloadInfo {
+      index: 0,
+      url: source.describe(),
+      target: output_dir.to_path_buf(),
+    };
+
+    let result = self
+      .run_torrent(&info, source, output_dir)
+      .instrument(info_span!("torrent", source = info.url.as_str()))
+      .await;
+
+    self.report_result(self.reporter.as_ref(), &info, &result);
+
+    result
+  }
+
+  async fn run_torrent(
+    &self,
+    info: &DownloadInfo,
+    source: TorrentSource,
+    output_dir: &Path,
+  ) -> Result<Vec<PathBuf>, ProgressDownloadError> {
+    let error = |err| torrent_error(info, err);
+
+    tokio::fs::create_dir_all(output_dir).await?;
+
+    let add = match source {
+      TorrentSource::Url(url) => AddTorrent::from_url(url),
+      TorrentSource::File(path) => AddTorrent::from_bytes(tokio::fs::read(&path).await?),
+      TorrentSource::Bytes(bytes) => AddTorrent::from_bytes(bytes),
+    };
+
+    // 不持久化会话状态，已有文件的完整性由 librqbit 校验
+    let options = SessionOptions {
+      disable_dht_persistence: true,
+      ratelimits: LimitsConfig {
+        download_bps: self
+          .max_bytes_per_sec
+          .and_then(|limit| NonZeroU32::new(limit.min(u32::MAX as u64) as u32)),
+        upload_bps: None,
+      },
+      ..Default::default()
+    };
+    let session = Session::new_with_opts(output_dir.to_path_buf(), options)
+      .await
+      .map_err(error)?;
+
+    let result = async {
+      let added = tokio::time::timeout(
+        self.timeout,
+        session.add_torrent(
+          add,
+          Some(AddTorrentOptions {
+            overwrite: true,
+            output_folder: Some(output_dir.to_string_lossy().into_owned()),
+            ..Default::default()
+          }),
+        ),
+      )
+      .await
+      .map_err(ProgressDownloadError::timed_out(
+        TimeoutPhase::Response,
+        self.timeout,
+      ))?
+      .map_err(error)?;
+
+      let handle = added
+        .into_handle()
+        .ok_or_else(|| torrent_error(info, "torrent was not started"))?;
+
+      self.wait_for_torrent(info, &handle).await?;
+
+      handle
+        .with_metadata(|metadata| {
+          metadata
+            .file_infos
+            .iter()
+            .map(|file| output_dir.join(&file.relative_filename))
+            .collect()
+        })
+        .map_err(error)
+    }
+    .await;
+
+    session.stop().await;
+    result
+  }
+
+  /// Reports the swarm progress of `handle` until all its pieces have been downloaded.
+  async fn wait_for_torrent(
+    &self,
+    info: &DownloadInfo,
+    handle: &Arc<ManagedTorrent>,
+  ) -> Result<(), ProgressDownloadError> {
+    // 初始化时校验已有文件，之后才知道已经下载了多少
+    handle
+      .wait_until_initialized()
+      .await
+      .map_err(|err| torrent_error(info, err))?;
+
+    let stats = handle.stats();
+    self
+      .reporter
+      .on_started(info, stats.progress_bytes, Some(stats.total_bytes));
+
+    let mut completed = handle.wait_until_completed();
+    let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+
+    loop {
+      tokio::select! {
+        result = &mut completed => {
+          self.report_swarm(info, &handle.stats());
+          return result.map_err(|err| torrent_error(info, err));
+        }
+        _ = ticker.tick() => self.report_swarm(info, &handle.stats()),
+      }
+    }
+  }
+
+  fn report_swarm(&self, info: &DownloadInfo, stats: &TorrentStats) {
+    self
+      .reporter
+      .on_bytes_received(info, stats.progress_bytes, Some(stats.total_bytes));
+
+    if let Some(live) = &stats.live {
+      self.reporter.on_peers(info, live.snapshot.peer_stats.live);
+    }
+  }
+}
+
+fn torrent_error(info: &DownloadInfo, err: impl fmt::Display) -> ProgressDownloadError {
+  ProgressDownloadError::Torrent {
+    torrent: info.url.clone(),
+    message: format!("{err:#}"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_torrent_source() {
+    assert!(matches!(
+      TorrentSource::from("magnet:?xt=urn:btih:abc"),
+      TorrentSource::Url(_)
+    ));
+    assert!(matches!(
+      TorrentSource::from("HTTPS://example.com/data.torrent"),
+      TorrentSource::Url(_)
+    ));
+    assert!(matches!(
+      TorrentSource::from("data/linux.torrent"),
+      TorrentSource::File(_)
+    ));
+  }
+}
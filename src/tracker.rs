@@ -1,55 +1,59 @@
-use reqwest::IntoUrl;
-use std::time::Instant;
+use tracing::debug;
 use typed_builder::TypedBuilder;
 
+use crate::reporter::{DownloadInfo, ProgressReporter};
+
 #[derive(Debug, TypedBuilder)]
-pub struct DownloadTracker<'a, U>
-where
-  U: IntoUrl + Clone,
-{
+pub struct DownloadTracker<'a> {
   #[builder]
   downloaded_size: u64,
   #[builder]
-  remaining_size: u64,
-  #[builder(default = Instant::now())]
-  start_time: Instant,
+  total_size: Option<u64>,
   #[builder]
-  url: U,
+  info: &'a DownloadInfo,
   #[builder]
-  progress_bar: &'a indicatif::ProgressBar,
+  reporter: &'a dyn ProgressReporter,
+  /// 压缩响应解压后写入的字节数
+  #[builder(default)]
+  decoded_size: u64,
 }
 
-impl<'a, U> DownloadTracker<'a, U>
-where
-  U: IntoUrl + Clone,
-{
+impl DownloadTracker<'_> {
   pub fn init_progress(&mut self) {
+    debug!(
+      downloaded = self.downloaded_size,
+      total = self.total_size,
+      "download attempt started"
+    );
     self
-      .progress_bar
-      .set_length(self.remaining_size + self.downloaded_size);
-    self.progress_bar.set_position(self.downloaded_size);
+      .reporter
+      .on_started(self.info, self.downloaded_size, self.total_size);
   }
 
   pub fn update_progress(&mut self, chunk_size: usize) {
     self.downloaded_size += chunk_size as u64;
-    self.progress_bar.set_position(self.downloaded_size);
-    self.update_speed();
+    self
+      .reporter
+      .on_bytes_received(self.info, self.downloaded_size, self.total_size);
+  }
+
+  pub fn update_decoded(&mut self, decoded_size: usize) {
+    self.decoded_size += decoded_size as u64;
+    self.reporter.on_bytes_decoded(self.info, self.decoded_size);
   }
 
-  fn update_speed(&mut self) {
-    let elapsed = self.start_time.elapsed().as_secs_f64();
-    if elapsed > 0.0 {
-      // let speed = (self.downloaded_size - self.last_downloaded_size) as f64
-      //     / elapsed
-      //     / 1024.0
-      //     / 1024.0;
-      let percentage = (self.downloaded_size as f64
-        / (self.remaining_size + self.downloaded_size) as f64
-        * 100.0) as u64;
+  /// Reports the received bytes as the total size once the body has ended, if the server
+  /// did not send the size up front.
+  pub fn finish_progress(&mut self) {
+    if self.total_size.is_none() {
+      self.total_size = Some(self.downloaded_size);
       self
-        .progress_bar
-        .set_message(format!("{}% {} ", percentage, self.url.as_str()));
-      // self.last_downloaded_size = self.downloaded_size;
+        .reporter
+        .on_bytes_received(self.info, self.downloaded_size, self.total_size);
     }
   }
+
+  pub fn downloaded_size(&self) -> u64 {
+    self.downloaded_size
+  }
 }
@@ -0,0 +1,199 @@
+use std::{fmt, io};
+
+use bytes::{Bytes, BytesMut};
+
+use crate::reporter::DownloadInfo;
+
+/// Processes the body of a download between the network and the file, one chunk at a
+/// time, e.g. to decrypt, decompress, hash or copy the data elsewhere.
+///
+/// Transforms run after a `Content-Encoding` has been decompressed, and everything
+/// downstream sees their output: the file, its `integrity`, `expected_size` and
+/// `expected_magic` checks and the decoded bytes of the progress. Combine several with a
+/// [`TransformChain`] and create them with a [`TransformFactory`], see the `transforms`
+/// option.
+///
+/// # Example
+///
+/// ```rust
+/// use std::io;
+///
+/// use bytes::Bytes;
+/// use robust_downloader::ChunkTransform;
+///
+/// // 统计经过的字节数，数据保持不变
+/// struct Count(u64);
+///
+/// impl ChunkTransform for Count {
+///   fn transform(&mut self, chunk: Bytes) -> io::Result<Bytes> {
+///     self.0 += chunk.len() as u64;
+///     Ok(chunk)
+///   }
+/// }
+/// ```
+pub trait ChunkTransform: Send {
+  /// Transforms the next chunk of the body, returning the output available so far.
+  fn transform(&mut self, chunk: Bytes) -> io::Result<Bytes>;
+
+  /// Ends the body, returning the remaining output. Failing here fails the attempt, e.g.
+  /// if the data was truncated or does not authenticate.
+  fn finish(&mut self) -> io::Result<Bytes> {
+    Ok(Bytes::new())
+  }
+}
+
+impl fmt::Debug for dyn ChunkTransform + '_ {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("ChunkTransform")
+  }
+}
+
+/// Transforms applied one after another, each to the output of the previous one.
+#[derive(Debug, Default)]
+pub struct TransformChain {
+  transforms: Vec<Box<dyn ChunkTransform>>,
+}
+
+impl TransformChain {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Appends `transform`, which receives the output of the transforms before it.
+  pub fn then(mut self, transform: impl ChunkTransform + 'static) -> Self {
+    self.transforms.push(Box::new(transform));
+    self
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.transforms.is_empty()
+  }
+
+  /// Passes `chunk` through the transforms from index `start` on.
+  fn transform_from(&mut self, start: usize, mut chunk: Bytes) -> io::Result<Bytes> {
+    for transform in &mut self.transforms[start..] {
+      if chunk.is_empty() {
+        break;
+      }
+      chunk = transform.transform(chunk)?;
+    }
+    Ok(chunk)
+  }
+}
+
+impl ChunkTransform for TransformChain {
+  fn transform(&mut self, chunk: Bytes) -> io::Result<Bytes> {
+    self.transform_from(0, chunk)
+  }
+
+  fn finish(&mut self) -> io::Result<Bytes> {
+    // 前面的处理结束时输出的数据仍要经过后面的处理
+    let mut rest = BytesMut::new();
+    for index in 0..self.transforms.len() {
+      let tail = self.transforms[index].finish()?;
+      rest.extend_from_slice(&self.transform_from(index + 1, tail)?);
+    }
+    Ok(rest.freeze())
+  }
+}
+
+/// Creates the transforms for each attempt of a download, see the `transforms` option.
+///
+/// Every attempt starts the body over with new transforms, so they need no way to
+/// resume. Closures taking a `&DownloadInfo` implement this trait.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{io, sync::Arc};
+///
+/// use bytes::Bytes;
+/// use robust_downloader::{ChunkTransform, DownloadInfo, RobustDownloader, TransformChain};
+///
+/// // 按位取反，仅作示例
+/// struct Invert;
+///
+/// impl ChunkTransform for Invert {
+///   fn transform(&mut self, chunk: Bytes) -> io::Result<Bytes> {
+///     Ok(chunk.iter().map(|byte| !byte).collect())
+///   }
+/// }
+///
+/// let downloader = RobustDownloader::builder()
+///   .transforms(Arc::new(|_: &DownloadInfo| TransformChain::new().then(Invert)))
+///   .build();
+/// ```
+pub trait TransformFactory: Send + Sync {
+  /// The transforms for the next attempt of the download described by `info`.
+  fn create(&self, info: &DownloadInfo) -> TransformChain;
+}
+
+impl<F> TransformFactory for F
+where
+  F: Fn(&DownloadInfo) -> TransformChain + Send + Sync,
+{
+  fn create(&self, info: &DownloadInfo) -> TransformChain {
+    self(info)
+  }
+}
+
+impl fmt::Debug for dyn TransformFactory + '_ {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("TransformFactory")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// 缓存所有数据，结束时一次输出
+  struct Buffer(Vec<u8>);
+
+  impl ChunkTransform for Buffer {
+    fn transform(&mut self, chunk: Bytes) -> io::Result<Bytes> {
+      self.0.extend_from_slice(&chunk);
+      Ok(Bytes::new())
+    }
+
+    fn finish(&mut self) -> io::Result<Bytes> {
+      Ok(std::mem::take(&mut self.0).into())
+    }
+  }
+
+  struct Upper;
+
+  impl ChunkTransform for Upper {
+    fn transform(&mut self, chunk: Bytes) -> io::Result<Bytes> {
+      Ok(chunk.iter().map(u8::to_ascii_uppercase).collect())
+    }
+  }
+
+  #[test]
+  fn test_transform_chain() {
+    let mut chain = TransformChain::new().then(Buffer(Vec::new())).then(Upper);
+    assert!(
+      chain
+        .transform(Bytes::from_static(b"hello, "))
+        .unwrap()
+        .is_empty()
+    );
+    assert!(
+      chain
+        .transform(Bytes::from_static(b"world"))
+        .unwrap()
+        .is_empty()
+    );
+    assert_eq!(chain.finish().unwrap(), "HELLO, WORLD");
+
+    let mut chain = TransformChain::new().then(Upper).then(Buffer(Vec::new()));
+    assert!(
+      chain
+        .transform(Bytes::from_static(b"abc"))
+        .unwrap()
+        .is_empty()
+    );
+    assert_eq!(chain.finish().unwrap(), "ABC");
+    assert!(!chain.is_empty());
+  }
+}
@@ -0,0 +1,50 @@
+use std::fmt;
+
+use reqwest::header::HeaderMap;
+
+/// Checks the headers of every successful HTTP response before its body is written, see
+/// the `response_validator` option.
+///
+/// Returning an error fails the download with
+/// [`ProgressDownloadError::InvalidResponse`](crate::ProgressDownloadError::InvalidResponse)
+/// without retrying. Closures taking `&HeaderMap` implement this trait.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use reqwest::header::{CONTENT_TYPE, HeaderMap};
+/// use robust_downloader::RobustDownloader;
+///
+/// // 拒绝以 200 状态返回的 HTML 错误页面
+/// let downloader = RobustDownloader::builder()
+///   .response_validator(Arc::new(|headers: &HeaderMap| {
+///     match headers.get(CONTENT_TYPE) {
+///       Some(value) if value.as_bytes().starts_with(b"text/html") => {
+///         Err("expected a binary file, got an HTML page".to_string())
+///       }
+///       _ => Ok(()),
+///     }
+///   }))
+///   .build();
+/// ```
+pub trait ResponseValidator: Send + Sync {
+  /// Accepts the response with `headers`, or rejects it with a message.
+  fn validate(&self, headers: &HeaderMap) -> Result<(), String>;
+}
+
+impl<F> ResponseValidator for F
+where
+  F: Fn(&HeaderMap) -> Result<(), String> + Send + Sync,
+{
+  fn validate(&self, headers: &HeaderMap) -> Result<(), String> {
+    self(headers)
+  }
+}
+
+impl fmt::Debug for dyn ResponseValidator + '_ {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("ResponseValidator")
+  }
+}
@@ -0,0 +1,48 @@
+use crate::err::ProgressDownloadError;
+
+/// Which HTTP version the client speaks, see the `http_version` option.
+///
+/// The version each download actually used is reported in
+/// [`DownloadResult::http_version`](crate::DownloadResult::http_version).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HttpVersionPolicy {
+  /// HTTP/1.1, and HTTP/2 for HTTPS servers offering it during the TLS handshake when
+  /// the `http2` feature is enabled.
+  #[default]
+  Negotiate,
+  /// Only HTTP/1.1.
+  Http1Only,
+  /// HTTP/2 without negotiation, also over plain HTTP, for servers known to support it.
+  /// Requires the `http2` feature.
+  Http2PriorKnowledge,
+  /// HTTP/3 over QUIC, for servers known to support it. Experimental: requires the `http3`
+  /// feature and building with `RUSTFLAGS="--cfg reqwest_unstable"`.
+  Http3PriorKnowledge,
+}
+
+impl HttpVersionPolicy {
+  /// Applies the policy to `builder`, failing if it needs a feature that is not enabled.
+  pub(crate) fn apply(
+    self,
+    builder: reqwest::ClientBuilder,
+  ) -> Result<reqwest::ClientBuilder, ProgressDownloadError> {
+    match self {
+      Self::Negotiate => Ok(builder),
+      Self::Http1Only => Ok(builder.http1_only()),
+      #[cfg(feature = "http2")]
+      Self::Http2PriorKnowledge => Ok(builder.http2_prior_knowledge()),
+      #[cfg(not(feature = "http2"))]
+      Self::Http2PriorKnowledge => Err(ProgressDownloadError::MissingFeature {
+        option: "HttpVersionPolicy::Http2PriorKnowledge",
+        feature: "http2",
+      }),
+      #[cfg(feature = "http3")]
+      Self::Http3PriorKnowledge => Ok(builder.http3_prior_knowledge()),
+      #[cfg(not(feature = "http3"))]
+      Self::Http3PriorKnowledge => Err(ProgressDownloadError::MissingFeature {
+        option: "HttpVersionPolicy::Http3PriorKnowledge",
+        feature: "http3",
+      }),
+    }
+  }
+}
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use crate::err::ProgressDownloadError;
+
+/// The lowest acceptable transfer speed of a connection, see the `min_speed` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MinSpeed {
+  /// Minimum average speed in bytes per second.
+  pub bytes_per_sec: u64,
+  /// Time over which the speed is averaged before it is checked.
+  pub over: Duration,
+}
+
+/// Checks the speed of one connection against a [`MinSpeed`].
+///
+/// Only the time spent waiting for data counts, so throttling by `max_bytes_per_sec` and
+/// writing to disk do not make a connection look slow.
+#[derive(Debug)]
+pub(crate) struct SpeedWatchdog {
+  min_speed: MinSpeed,
+  /// 当前窗口内等待数据的时间和收到的字节数
+  waited: Duration,
+  received: u64,
+}
+
+impl SpeedWatchdog {
+  pub fn new(min_speed: MinSpeed) -> Self {
+    Self {
+      min_speed,
+      waited: Duration::ZERO,
+      received: 0,
+    }
+  }
+
+  /// Records `bytes` received after waiting `waited` for them.
+  ///
+  /// Fails with [`ProgressDownloadError::TooSlow`] once the average speed of a window of
+  /// `over` is below the minimum.
+  pub fn record(&mut self, bytes: usize, waited: Duration) -> Result<(), ProgressDownloadError> {
+    self.waited += waited;
+    self.received += bytes as u64;
+    if self.waited < self.min_speed.over {
+      return Ok(());
+    }
+
+    let speed = (self.received as f64 / self.waited.as_secs_f64()) as u64;
+    self.waited = Duration::ZERO;
+    self.received = 0;
+
+    if speed < self.min_speed.bytes_per_sec {
+      return Err(ProgressDownloadError::TooSlow {
+        speed,
+        min_speed: self.min_speed.bytes_per_sec,
+      });
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_record() {
+    let mut watchdog = SpeedWatchdog::new(MinSpeed {
+      bytes_per_sec: 1000,
+      over: Duration::from_secs(2),
+    });
+
+    // 窗口未满时不检查
+    assert!(watchdog.record(10, Duration::from_secs(1)).is_ok());
+    assert!(watchdog.record(3000, Duration::from_secs(1)).is_ok());
+
+    assert!(watchdog.record(500, Duration::from_secs(1)).is_ok());
+    assert!(matches!(
+      watchdog.record(500, Duration::from_secs(1)),
+      Err(ProgressDownloadError::TooSlow { speed: 500, .. })
+    ));
+  }
+}
@@ -0,0 +1,135 @@
+use std::{
+  borrow::Cow,
+  path::{Component, Path},
+};
+
+/// Device names Windows reserves in every directory, with or without an extension.
+const RESERVED_NAMES: [&str; 22] = [
+  "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+  "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Length from which paths need the `\\?\` prefix, `MAX_PATH` minus the terminating NUL.
+#[cfg(windows)]
+const MAX_PATH: usize = 259;
+
+/// Whether Windows refuses or silently changes the file name `name`: a device name like
+/// `CON` or `nul.txt`, or a name ending with a dot or a space.
+pub(crate) fn is_reserved(name: &str) -> bool {
+  if name.ends_with(['.', ' ']) && name != "." && name != ".." {
+    return true;
+  }
+  let stem = name.split('.').next().unwrap_or_default().trim_end();
+  RESERVED_NAMES
+    .iter()
+    .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+}
+
+/// `name` turned into a file name that is valid on every platform: characters Windows
+/// does not allow are replaced with `_`, trailing dots and spaces are removed and `_` is
+/// appended to device names, so `con.txt` becomes `con_.txt`.
+pub(crate) fn sanitize(name: &str) -> String {
+  let mut sanitized: String = name
+    .chars()
+    .map(|char| match char {
+      '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+      char if char.is_control() => '_',
+      char => char,
+    })
+    .collect();
+  sanitized.truncate(sanitized.trim_end_matches(['.', ' ']).len());
+
+  if is_reserved(&sanitized) {
+    let stem = &sanitized[..sanitized.find('.').unwrap_or(sanitized.len())];
+    sanitized.insert(stem.trim_end().len(), '_');
+  }
+  sanitized
+}
+
+/// The first component of `path` Windows cannot create, if any.
+pub(crate) fn reserved_component(path: &Path) -> Option<String> {
+  path.components().find_map(|component| match component {
+    Component::Normal(name) => {
+      let name = name.to_string_lossy();
+      is_reserved(&name).then(|| name.into_owned())
+    }
+    _ => None,
+  })
+}
+
+/// The key under which a file name is unique in a directory of a case-insensitive file
+/// system, as used on Windows and macOS.
+pub(crate) fn case_key(name: &str) -> String {
+  let mut key = name.to_string();
+  key.make_ascii_lowercase();
+  key
+}
+
+/// `path` with the `\\?\` prefix that lifts the 260 character limit of Windows paths, if
+/// it is that long. Relative paths are made absolute first, since prefixed paths are
+/// not resolved any further. Other platforms have no such limit.
+pub(crate) fn long_path(path: &Path) -> Cow<'_, Path> {
+  #[cfg(windows)]
+  if path.as_os_str().len() > MAX_PATH {
+    if let Ok(absolute) = std::path::absolute(path) {
+      if let Some(absolute) = absolute.to_str() {
+        return Cow::Owned(verbatim(absolute).into());
+      }
+    }
+  }
+  Cow::Borrowed(path)
+}
+
+/// Prefixes the absolute Windows path `path` with `\\?\`, or a UNC path with `\\?\UNC\`.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn verbatim(path: &str) -> String {
+  if path.starts_with(r"\\?\") {
+    return path.to_string();
+  }
+  // 前缀路径不会再把 / 转换为 \
+  let path: String = path
+    .chars()
+    .map(|char| if char == '/' { '\\' } else { char })
+    .collect();
+  match path.strip_prefix(r"\\") {
+    Some(unc) => format!(r"\\?\UNC\{}", unc),
+    None => format!(r"\\?\{}", path),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_sanitize() {
+    assert_eq!(sanitize("report.pdf"), "report.pdf");
+    assert_eq!(sanitize("CON"), "CON_");
+    assert_eq!(sanitize("nul.tar.gz"), "nul_.tar.gz");
+    assert_eq!(sanitize("lpt1 .txt"), "lpt1_ .txt");
+    assert_eq!(sanitize("name. . "), "name");
+    assert_eq!(sanitize("a:b*c?.txt"), "a_b_c_.txt");
+    assert_eq!(sanitize("console.log"), "console.log");
+
+    assert_eq!(
+      reserved_component(Path::new("out/aux/file.txt")).as_deref(),
+      Some("aux")
+    );
+    assert_eq!(reserved_component(Path::new("../out/file.txt")), None);
+    assert_eq!(case_key("File.ZIP"), case_key("file.zip"));
+  }
+
+  #[test]
+  fn test_verbatim() {
+    assert_eq!(verbatim(r"C:\dir\file.txt"), r"\\?\C:\dir\file.txt");
+    assert_eq!(verbatim("C:/dir/file.txt"), r"\\?\C:\dir\file.txt");
+    assert_eq!(
+      verbatim(r"\\server\share\file.txt"),
+      r"\\?\UNC\server\share\file.txt"
+    );
+    assert_eq!(verbatim(r"\\?\C:\file.txt"), r"\\?\C:\file.txt");
+
+    let short = Path::new("dir/file.txt");
+    assert_eq!(long_path(short), short);
+  }
+}
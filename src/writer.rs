@@ -0,0 +1,534 @@
+use std::{io, path::Path};
+
+use tokio::{
+  fs::File,
+  io::{AsyncWriteExt, BufWriter},
+};
+
+/// Writes the data of a download into its temp file, through a buffer or, for large
+/// files with the `direct-io` feature on Linux, bypassing the page cache. With the
+/// `io-uring` feature on Linux the writes can be submitted through io_uring instead.
+pub(crate) enum TempWriter {
+  Buffered(BufWriter<File>),
+  #[cfg(all(feature = "direct-io", target_os = "linux"))]
+  Direct(direct::DirectWriter),
+  #[cfg(all(feature = "io-uring", target_os = "linux"))]
+  Uring(uring::UringWriter),
+}
+
+impl TempWriter {
+  /// Writes to `file` at its current position through a buffer of `capacity` bytes.
+  pub fn buffered(file: File, capacity: usize) -> Self {
+    Self::Buffered(BufWriter::with_capacity(capacity, file))
+  }
+
+  /// Opens the existing file at `path` for direct I/O writes from `offset` on, buffering
+  /// up to `capacity` bytes. Returns `None` if direct I/O is not available, e.g. on file
+  /// systems without `O_DIRECT` support.
+  pub async fn direct(path: &Path, offset: u64, capacity: usize) -> io::Result<Option<Self>> {
+    #[cfg(all(feature = "direct-io", target_os = "linux"))]
+    {
+      Ok(
+        direct::DirectWriter::open(path, offset, capacity)
+          .await?
+          .map(Self::Direct),
+      )
+    }
+
+    #[cfg(not(all(feature = "direct-io", target_os = "linux")))]
+    {
+      let _ = (path, offset, capacity);
+      Ok(None)
+    }
+  }
+
+  /// Opens the existing file at `path` for io_uring writes from `offset` on, buffering
+  /// up to `capacity` bytes. Returns `None` if the kernel does not support io_uring.
+  pub async fn uring(path: &Path, offset: u64, capacity: usize) -> io::Result<Option<Self>> {
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    {
+      Ok(
+        uring::UringWriter::open(path, offset, capacity)
+          .await?
+          .map(Self::Uring),
+      )
+    }
+
+    #[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+    {
+      let _ = (path, offset, capacity);
+      Ok(None)
+    }
+  }
+
+  pub async fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+    match self {
+      Self::Buffered(writer) => writer.write_all(data).await,
+      #[cfg(all(feature = "direct-io", target_os = "linux"))]
+      Self::Direct(writer) => writer.write_all(data).await,
+      #[cfg(all(feature = "io-uring", target_os = "linux"))]
+      Self::Uring(writer) => writer.write_all(data).await,
+    }
+  }
+
+  /// Bytes accepted but not yet written to the file.
+  pub fn pending(&self) -> usize {
+    match self {
+      Self::Buffered(writer) => writer.buffer().len(),
+      #[cfg(all(feature = "direct-io", target_os = "linux"))]
+      Self::Direct(writer) => writer.pending(),
+      #[cfg(all(feature = "io-uring", target_os = "linux"))]
+      Self::Uring(writer) => writer.pending(),
+    }
+  }
+
+  /// Writes buffered data to the file. With direct I/O a trailing partial block stays
+  /// buffered until more data arrives.
+  pub async fn flush_partial(&mut self) -> io::Result<()> {
+    match self {
+      Self::Buffered(writer) => writer.flush().await,
+      #[cfg(all(feature = "direct-io", target_os = "linux"))]
+      Self::Direct(writer) => writer.write_blocks().await,
+      #[cfg(all(feature = "io-uring", target_os = "linux"))]
+      Self::Uring(writer) => writer.flush().await,
+    }
+  }
+
+  /// Writes all buffered data and waits until it has reached the disk.
+  pub async fn finish(self) -> io::Result<()> {
+    match self {
+      Self::Buffered(mut writer) => {
+        writer.flush().await?;
+        writer.into_inner().sync_all().await
+      }
+      #[cfg(all(feature = "direct-io", target_os = "linux"))]
+      Self::Direct(writer) => writer.finish().await,
+      #[cfg(all(feature = "io-uring", target_os = "linux"))]
+      Self::Uring(writer) => writer.finish().await,
+    }
+  }
+}
+
+#[cfg(all(feature = "direct-io", target_os = "linux"))]
+mod direct {
+  use std::{
+    fs::OpenOptions,
+    io,
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    path::Path,
+    sync::Arc,
+  };
+
+  /// Alignment of buffers, offsets and lengths of direct I/O writes, a multiple of the
+  /// logical block size of common disks.
+  const ALIGN: usize = 4096;
+
+  /// Writes whole aligned blocks with `O_DIRECT`. The unaligned bytes at the start and
+  /// the end of the written range go through a regular file descriptor instead.
+  pub(crate) struct DirectWriter {
+    direct: Arc<std::fs::File>,
+    regular: Arc<std::fs::File>,
+    buffer: AlignedBuffer,
+    /// 缓冲区数据在文件中的起始位置，缓冲区不为空时总是对齐的
+    offset: u64,
+  }
+
+  impl DirectWriter {
+    pub async fn open(path: &Path, offset: u64, capacity: usize) -> io::Result<Option<Self>> {
+      let path = path.to_path_buf();
+      let files = tokio::task::spawn_blocking(move || {
+        let direct = OpenOptions::new()
+          .write(true)
+          .custom_flags(libc::O_DIRECT)
+          .open(&path);
+        let direct = match direct {
+          Ok(direct) => direct,
+          // tmpfs 等文件系统不支持 O_DIRECT
+          Err(err) if err.raw_os_error() == Some(libc::EINVAL) => return Ok(None),
+          Err(err) => return Err(err),
+        };
+        let regular = OpenOptions::new().write(true).open(&path)?;
+        Ok(Some((direct, regular)))
+      })
+      .await
+      .map_err(io::Error::other)??;
+
+      Ok(files.map(|(direct, regular)| Self {
+        direct: Arc::new(direct),
+        regular: Arc::new(regular),
+        buffer: AlignedBuffer::new(capacity),
+        offset,
+      }))
+    }
+
+    pub fn pending(&self) -> usize {
+      self.buffer.len
+    }
+
+    pub async fn write_all(&mut self, mut data: &[u8]) -> io::Result<()> {
+      // 起始位置未对齐时，先用普通写入补齐到块边界
+      let misalignment = (self.offset % ALIGN as u64) as usize;
+      if self.buffer.len == 0 && misalignment != 0 && !data.is_empty() {
+        let head = data.len().min(ALIGN - misalignment);
+        self.write_regular(data[..head].to_vec()).await?;
+        data = &data[head..];
+      }
+
+      while !data.is_empty() {
+        let copied = self.buffer.extend(data);
+        data = &data[copied..];
+        if self.buffer.is_full() {
+          self.write_blocks().await?;
+        }
+      }
+      Ok(())
+    }
+
+    /// Writes the whole blocks in the buffer with direct I/O, keeping the rest buffered.
+    pub async fn write_blocks(&mut self) -> io::Result<()> {
+      let blocks = self.buffer.len / ALIGN * ALIGN;
+      if blocks == 0 {
+        return Ok(());
+      }
+
+      // 缓冲区移入阻塞任务时堆内存不会移动，仍然保持对齐
+      let buffer = std::mem::take(&mut self.buffer);
+      let direct = self.direct.clone();
+      let offset = self.offset;
+      let (buffer, result) = tokio::task::spawn_blocking(move || {
+        let result = direct.write_all_at(&buffer.data()[..blocks], offset);
+        (buffer, result)
+      })
+      .await
+      .map_err(io::Error::other)?;
+
+      self.buffer = buffer;
+      result?;
+      self.buffer.consume(blocks);
+      self.offset += blocks as u64;
+      Ok(())
+    }
+
+    pub async fn finish(mut self) -> io::Result<()> {
+      self.write_blocks().await?;
+      // 剩余不足一个块的数据使用普通写入
+      if self.buffer.len > 0 {
+        let rest = self.buffer.data().to_vec();
+        self.buffer.consume(rest.len());
+        self.write_regular(rest).await?;
+      }
+
+      let direct = self.direct.clone();
+      tokio::task::spawn_blocking(move || direct.sync_all())
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    /// Writes `data` at the current offset through the regular file descriptor.
+    async fn write_regular(&mut self, data: Vec<u8>) -> io::Result<()> {
+      let regular = self.regular.clone();
+      let offset = self.offset;
+      let len = data.len() as u64;
+      tokio::task::spawn_blocking(move || regular.write_all_at(&data, offset))
+        .await
+        .map_err(io::Error::other)??;
+      self.offset += len;
+      Ok(())
+    }
+  }
+
+  /// A buffer whose data starts at an `ALIGN`ed address.
+  #[derive(Default)]
+  struct AlignedBuffer {
+    memory: Vec<u8>,
+    /// 对齐的起始位置在 memory 中的下标
+    start: usize,
+    capacity: usize,
+    len: usize,
+  }
+
+  impl AlignedBuffer {
+    fn new(capacity: usize) -> Self {
+      let capacity = capacity.max(ALIGN).next_multiple_of(ALIGN);
+      // 多分配一个块，从中找出对齐的起始位置
+      let memory = vec![0; capacity + ALIGN];
+      let start = memory.as_ptr().align_offset(ALIGN);
+      Self {
+        memory,
+        start,
+        capacity,
+        len: 0,
+      }
+    }
+
+    fn data(&self) -> &[u8] {
+      &self.memory[self.start..self.start + self.len]
+    }
+
+    fn is_full(&self) -> bool {
+      self.len == self.capacity
+    }
+
+    /// Appends as much of `data` as fits, returning the number of bytes copied.
+    fn extend(&mut self, data: &[u8]) -> usize {
+      let copied = data.len().min(self.capacity - self.len);
+      let end = self.start + self.len;
+      self.memory[end..end + copied].copy_from_slice(&data[..copied]);
+      self.len += copied;
+      copied
+    }
+
+    /// Drops the first `len` bytes, moving the rest to the start of the buffer.
+    fn consume(&mut self, len: usize) {
+      let start = self.start;
+      self
+        .memory
+        .copy_within(start + len..start + self.len, start);
+      self.len -= len;
+    }
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aligned_buffer() {
+      let mut buffer = AlignedBuffer::new(5000);
+      assert_eq!(buffer.capacity, 2 * ALIGN);
+      assert_eq!(buffer.data().as_ptr() as usize % ALIGN, 0);
+
+      assert_eq!(buffer.extend(&[1; 3 * ALIGN]), 2 * ALIGN);
+      assert!(buffer.is_full());
+      buffer.consume(ALIGN + 10);
+      assert_eq!(buffer.len, ALIGN - 10);
+      assert_eq!(buffer.data().as_ptr() as usize % ALIGN, 0);
+    }
+  }
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring {
+  use std::{
+    io,
+    path::{Path, PathBuf},
+  };
+
+  use tokio::sync::{OnceCell, mpsc, oneshot};
+  use tracing::debug;
+
+  /// Sender of the thread running the io_uring runtime, `None` if it could not be started.
+  static WORKER: OnceCell<Option<mpsc::UnboundedSender<Open>>> = OnceCell::const_new();
+
+  /// Asks the io_uring thread to open a file and serve the writes sent through `ops`.
+  struct Open {
+    path: PathBuf,
+    ops: mpsc::UnboundedReceiver<Op>,
+    reply: oneshot::Sender<io::Result<()>>,
+  }
+
+  /// The result of a write, with the buffer handed back for reuse.
+  type Written = (io::Result<()>, Vec<u8>);
+
+  enum Op {
+    Write {
+      data: Vec<u8>,
+      offset: u64,
+      reply: oneshot::Sender<Written>,
+    },
+    Sync {
+      reply: oneshot::Sender<io::Result<()>>,
+    },
+  }
+
+  /// Starts the io_uring thread on first use. The tokio-uring runtime is single threaded
+  /// and cannot run inside the caller's runtime, so every file is served from one thread.
+  async fn worker() -> Option<&'static mpsc::UnboundedSender<Open>> {
+    WORKER
+      .get_or_init(|| async {
+        let (started, result) = oneshot::channel();
+        let spawned = std::thread::Builder::new()
+          .name("robust-downloader-uring".to_string())
+          .spawn(move || {
+            let runtime = match tokio_uring::Runtime::new(&tokio_uring::builder()) {
+              Ok(runtime) => runtime,
+              Err(err) => {
+                let _ = started.send(Err(err));
+                return;
+              }
+            };
+            let (sender, mut receiver) = mpsc::unbounded_channel::<Open>();
+            let _ = started.send(Ok(sender));
+            runtime.block_on(async move {
+              while let Some(open) = receiver.recv().await {
+                tokio_uring::spawn(serve(open));
+              }
+            });
+          });
+        if let Err(err) = spawned {
+          debug!("failed to start the io_uring thread: {err}");
+          return None;
+        }
+
+        match result.await {
+          Ok(Ok(sender)) => Some(sender),
+          Ok(Err(err)) => {
+            debug!("io_uring is not available: {err}");
+            None
+          }
+          Err(_) => None,
+        }
+      })
+      .await
+      .as_ref()
+  }
+
+  /// Serves the writes of one file on the io_uring thread until its writer is dropped.
+  async fn serve(open: Open) {
+    let Open {
+      path,
+      mut ops,
+      reply,
+    } = open;
+    let file = match tokio_uring::fs::OpenOptions::new()
+      .write(true)
+      .open(&path)
+      .await
+    {
+      Ok(file) => {
+        let _ = reply.send(Ok(()));
+        file
+      }
+      Err(err) => {
+        let _ = reply.send(Err(err));
+        return;
+      }
+    };
+
+    while let Some(op) = ops.recv().await {
+      match op {
+        Op::Write {
+          data,
+          offset,
+          reply,
+        } => {
+          let (result, data) = file.write_all_at(data, offset).await;
+          let _ = reply.send((result, data));
+        }
+        Op::Sync { reply } => {
+          let _ = reply.send(file.sync_all().await);
+        }
+      }
+    }
+    let _ = file.close().await;
+  }
+
+  fn worker_gone() -> io::Error {
+    io::Error::other("the io_uring thread has stopped")
+  }
+
+  /// Writes through io_uring with two buffers, so data is received into one while the
+  /// other is being written.
+  pub(crate) struct UringWriter {
+    ops: mpsc::UnboundedSender<Op>,
+    buffer: Vec<u8>,
+    capacity: usize,
+    /// 缓冲区数据在文件中的起始位置
+    offset: u64,
+    /// 正在写入的缓冲区及其长度
+    in_flight: Option<(oneshot::Receiver<Written>, usize)>,
+    spare: Vec<u8>,
+  }
+
+  impl UringWriter {
+    pub async fn open(path: &Path, offset: u64, capacity: usize) -> io::Result<Option<Self>> {
+      let Some(worker) = worker().await else {
+        return Ok(None);
+      };
+
+      let (ops, receiver) = mpsc::unbounded_channel();
+      let (reply, opened) = oneshot::channel();
+      let open = Open {
+        path: path.to_path_buf(),
+        ops: receiver,
+        reply,
+      };
+      if worker.send(open).is_err() {
+        return Ok(None);
+      }
+      opened.await.map_err(|_| worker_gone())??;
+
+      Ok(Some(Self {
+        ops,
+        buffer: Vec::with_capacity(capacity),
+        capacity,
+        offset,
+        in_flight: None,
+        spare: Vec::with_capacity(capacity),
+      }))
+    }
+
+    pub fn pending(&self) -> usize {
+      self.buffer.len() + self.in_flight.as_ref().map_or(0, |(_, len)| *len)
+    }
+
+    pub async fn write_all(&mut self, mut data: &[u8]) -> io::Result<()> {
+      while !data.is_empty() {
+        let copied = data.len().min(self.capacity - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..copied]);
+        data = &data[copied..];
+        if self.buffer.len() >= self.capacity {
+          self.submit().await?;
+        }
+      }
+      Ok(())
+    }
+
+    /// Starts writing the buffer, after waiting for the previous write to complete.
+    async fn submit(&mut self) -> io::Result<()> {
+      self.wait().await?;
+      if self.buffer.is_empty() {
+        return Ok(());
+      }
+
+      let data = std::mem::replace(&mut self.buffer, std::mem::take(&mut self.spare));
+      let len = data.len();
+      let (reply, written) = oneshot::channel();
+      let op = Op::Write {
+        data,
+        offset: self.offset,
+        reply,
+      };
+      self.ops.send(op).map_err(|_| worker_gone())?;
+      self.offset += len as u64;
+      self.in_flight = Some((written, len));
+      Ok(())
+    }
+
+    /// Waits for the write in flight to complete and keeps its buffer for reuse.
+    async fn wait(&mut self) -> io::Result<()> {
+      let Some((written, _)) = self.in_flight.take() else {
+        return Ok(());
+      };
+      let (result, mut data) = written.await.map_err(|_| worker_gone())?;
+      data.clear();
+      self.spare = data;
+      result
+    }
+
+    pub async fn flush(&mut self) -> io::Result<()> {
+      self.submit().await?;
+      self.wait().await
+    }
+
+    pub async fn finish(mut self) -> io::Result<()> {
+      self.flush().await?;
+      let (reply, synced) = oneshot::channel();
+      self
+        .ops
+        .send(Op::Sync { reply })
+        .map_err(|_| worker_gone())?;
+      synced.await.map_err(|_| worker_gone())?
+    }
+  }
+}
@@ -0,0 +1,361 @@
+use std::{
+  collections::HashMap,
+  fs::File,
+  io::{self, Read},
+  path::Path,
+};
+
+use md4::{Digest, Md4};
+
+/// The block checksums of a remote file, read from its [zsync](http://zsync.moria.org.uk/)
+/// control file.
+#[derive(Debug)]
+pub(crate) struct ControlFile {
+  pub block_size: usize,
+  /// Size of the remote file in bytes.
+  pub length: u64,
+  /// Hex SHA-1 digest of the whole remote file.
+  pub sha1: Option<String>,
+  /// 连续匹配的块数，为 2 时需要下一个块也匹配才算匹配
+  seq_matches: usize,
+  /// 每个块保存的弱校验和字节数，取 4 字节校验和的最后几个字节
+  rsum_bytes: usize,
+  /// 每个块保存的 MD4 前缀长度
+  checksum_bytes: usize,
+  blocks: Vec<BlockSum>,
+}
+
+#[derive(Debug)]
+struct BlockSum {
+  rsum: u32,
+  checksum: Vec<u8>,
+}
+
+/// Where each block of the remote file can be copied from: the offset in the seed file,
+/// or `None` if it has to be downloaded.
+pub(crate) type Plan = Vec<Option<u64>>;
+
+impl ControlFile {
+  /// Parses a control file written by `zsyncmake`: `Key: value` header lines, an empty
+  /// line and the checksums of every block.
+  pub fn parse(content: &[u8]) -> Result<Self, String> {
+    let end = content
+      .windows(2)
+      .position(|window| window == b"\n\n")
+      .ok_or("missing the end of the header")?;
+    let header = std::str::from_utf8(&content[..end]).map_err(|err| err.to_string())?;
+    let headers = header
+      .lines()
+      .filter_map(|line| line.split_once(':'))
+      .map(|(key, value)| (key.trim(), value.trim()))
+      .collect::<HashMap<_, _>>();
+
+    let number = |key: &str| -> Result<u64, String> {
+      headers
+        .get(key)
+        .ok_or_else(|| format!("missing {key}"))?
+        .parse()
+        .map_err(|_| format!("invalid {key}"))
+    };
+    let block_size = number("Blocksize")? as usize;
+    let length = number("Length")?;
+
+    let (seq_matches, rsum_bytes, checksum_bytes) = match headers.get("Hash-Lengths") {
+      Some(lengths) => {
+        let lengths = lengths
+          .split(',')
+          .map(|length| length.trim().parse::<usize>())
+          .collect::<Result<Vec<_>, _>>()
+          .map_err(|_| "invalid Hash-Lengths")?;
+        match lengths[..] {
+          [seq_matches, rsum_bytes, checksum_bytes] => (seq_matches, rsum_bytes, checksum_bytes),
+          _ => return Err("invalid Hash-Lengths".to_string()),
+        }
+      }
+      None => (1, 4, 16),
+    };
+    if block_size == 0
+      || !(1..=2).contains(&seq_matches)
+      || !(1..=4).contains(&rsum_bytes)
+      || !(3..=16).contains(&checksum_bytes)
+    {
+      return Err("unsupported block size or hash lengths".to_string());
+    }
+
+    let count = length.div_ceil(block_size as u64) as usize;
+    let data = &content[end + 2..];
+    let entry = rsum_bytes + checksum_bytes;
+    if data.len() < count * entry {
+      return Err("truncated block checksums".to_string());
+    }
+    let blocks = data
+      .chunks_exact(entry)
+      .take(count)
+      .map(|entry| {
+        let (rsum, checksum) = entry.split_at(rsum_bytes);
+        BlockSum {
+          rsum: rsum
+            .iter()
+            .fold(0, |rsum, byte| (rsum << 8) | u32::from(*byte)),
+          checksum: checksum.to_vec(),
+        }
+      })
+      .collect();
+
+    Ok(Self {
+      block_size,
+      length,
+      sha1: headers.get("SHA-1").map(|sha1| sha1.to_string()),
+      seq_matches,
+      rsum_bytes,
+      checksum_bytes,
+      blocks,
+    })
+  }
+
+  /// Number of bytes of the remote file in block `index`, shorter for the last block.
+  pub fn block_len(&self, index: usize) -> u64 {
+    let start = (index * self.block_size) as u64;
+    (self.length - start).min(self.block_size as u64)
+  }
+
+  /// Finds the blocks of the remote file in `seed`, e.g. an older version of it, by
+  /// rolling the weak checksum over every offset and checking candidates with MD4.
+  pub fn match_seed(&self, seed: &Path) -> io::Result<Plan> {
+    let mut by_rsum = HashMap::<u32, Vec<usize>>::new();
+    for (index, block) in self.blocks.iter().enumerate() {
+      by_rsum.entry(block.rsum).or_default().push(index);
+    }
+
+    let mut plan = vec![None; self.blocks.len()];
+    let block_size = self.block_size;
+    let mut seed = SeedReader::new(File::open(seed)?, block_size);
+    // 当前窗口的弱校验和，匹配后跳过整个块时需要重新计算
+    let mut rsum = None;
+
+    while seed.fill(block_size * self.seq_matches)? >= block_size {
+      let window = seed.window(block_size);
+      let (a, b) = *rsum.get_or_insert_with(|| rolling_sum(window));
+
+      if let Some(candidates) = by_rsum.get(&self.masked(a, b)) {
+        let matched = self.matched(candidates, &seed, &plan);
+        if !matched.is_empty() {
+          for index in matched {
+            plan[index] = Some(seed.offset());
+          }
+          seed.advance(block_size);
+          rsum = None;
+          continue;
+        }
+      }
+
+      if seed.fill(block_size + 1)? <= block_size {
+        break;
+      }
+      let removed = u16::from(seed.window(1)[0]);
+      let added = u16::from(seed.window(block_size + 1)[block_size]);
+      let a = a.wrapping_sub(removed).wrapping_add(added);
+      let b = b
+        .wrapping_sub((block_size as u16).wrapping_mul(removed))
+        .wrapping_add(a);
+      rsum = Some((a, b));
+      seed.advance(1);
+    }
+
+    Ok(plan)
+  }
+
+  /// The blocks among `candidates` not found yet whose strong checksum matches the
+  /// window of `seed`. If `seq_matches` is 2, the following block has to match as well
+  /// unless the previous block was matched right before the window.
+  fn matched(&self, candidates: &[usize], seed: &SeedReader, plan: &Plan) -> Vec<usize> {
+    let block_size = self.block_size;
+    let checksum = strong_sum(seed.window(block_size));
+    let next = seed.window(block_size * self.seq_matches);
+
+    candidates
+      .iter()
+      .copied()
+      .filter(|index| plan[*index].is_none())
+      .filter(|index| self.blocks[*index].checksum[..] == checksum[..self.checksum_bytes])
+      .filter(|index| {
+        // 紧接在已匹配的上一个块之后时只需校验本块
+        let continues = index
+          .checked_sub(1)
+          .is_some_and(|previous| plan[previous] == seed.offset().checked_sub(block_size as u64));
+        if continues {
+          return true;
+        }
+        // 最后一个块之后没有下一个块
+        let Some(following) = self.blocks.get(index + 1).filter(|_| self.seq_matches > 1) else {
+          return true;
+        };
+        next.len() == block_size * 2 && {
+          let next = &next[block_size..];
+          let (a, b) = rolling_sum(next);
+          following.rsum == self.masked(a, b)
+            && following.checksum[..] == strong_sum(next)[..self.checksum_bytes]
+        }
+      })
+      .collect()
+  }
+
+  /// The weak checksum as stored in the control file, its last `rsum_bytes` bytes.
+  fn masked(&self, a: u16, b: u16) -> u32 {
+    let rsum = (u32::from(a) << 16) | u32::from(b);
+    match self.rsum_bytes {
+      4 => rsum,
+      bytes => rsum & ((1 << (bytes * 8)) - 1),
+    }
+  }
+}
+
+/// The rsync weak checksum of zsync: the sum of the bytes, and the sum of each byte
+/// multiplied by its distance to the end of the block.
+fn rolling_sum(block: &[u8]) -> (u16, u16) {
+  let len = block.len();
+  block
+    .iter()
+    .enumerate()
+    .fold((0u16, 0u16), |(a, b), (index, byte)| {
+      let byte = u16::from(*byte);
+      (
+        a.wrapping_add(byte),
+        b.wrapping_add(((len - index) as u16).wrapping_mul(byte)),
+      )
+    })
+}
+
+fn strong_sum(block: &[u8]) -> [u8; 16] {
+  Md4::digest(block).into()
+}
+
+/// Reads the seed file through a buffer holding the window being checked, followed by a
+/// block of zeros like the padded last block of the remote file.
+struct SeedReader {
+  file: File,
+  buffer: Vec<u8>,
+  /// 当前窗口在缓冲区中的位置
+  start: usize,
+  /// 缓冲区开头在种子文件中的偏移
+  base: u64,
+  padding: usize,
+  eof: bool,
+}
+
+impl SeedReader {
+  fn new(file: File, padding: usize) -> Self {
+    Self {
+      file,
+      buffer: Vec::new(),
+      start: 0,
+      base: 0,
+      padding,
+      eof: false,
+    }
+  }
+
+  /// Reads until at least `len` bytes follow the window start, if the file has them.
+  /// Returns the number of bytes available.
+  fn fill(&mut self, len: usize) -> io::Result<usize> {
+    while self.buffer.len() - self.start < len && !self.eof {
+      // 丢弃窗口之前的数据，避免缓冲区无限增长
+      if self.start >= 1024 * 1024 {
+        self.buffer.drain(..self.start);
+        self.base += self.start as u64;
+        self.start = 0;
+      }
+
+      let filled = self.buffer.len();
+      self.buffer.resize(filled + 1024 * 1024, 0);
+      let read = self.file.read(&mut self.buffer[filled..])?;
+      self.buffer.truncate(filled + read);
+      if read == 0 {
+        self.eof = true;
+        self.buffer.resize(filled + self.padding, 0);
+      }
+    }
+    Ok(self.buffer.len() - self.start)
+  }
+
+  /// The next `len` bytes from the window start, fewer at the end of the file.
+  fn window(&self, len: usize) -> &[u8] {
+    let end = (self.start + len).min(self.buffer.len());
+    &self.buffer[self.start..end]
+  }
+
+  /// Offset of the window start in the seed file.
+  fn offset(&self) -> u64 {
+    self.base + self.start as u64
+  }
+
+  fn advance(&mut self, len: usize) {
+    self.start += len;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// 按 zsyncmake 的格式生成 content 的控制文件，最后一个块以 0 补齐
+  fn control_file(content: &[u8], block_size: usize, lengths: (usize, usize, usize)) -> Vec<u8> {
+    let (seq_matches, rsum_bytes, checksum_bytes) = lengths;
+    let mut control = format!(
+      "zsync: 0.6.2\nBlocksize: {block_size}\nLength: {}\nHash-Lengths: {seq_matches},{rsum_bytes},{checksum_bytes}\n\n",
+      content.len()
+    )
+    .into_bytes();
+    for block in content.chunks(block_size) {
+      let mut block = block.to_vec();
+      block.resize(block_size, 0);
+      let (a, b) = rolling_sum(&block);
+      let rsum = [a.to_be_bytes(), b.to_be_bytes()].concat();
+      control.extend_from_slice(&rsum[4 - rsum_bytes..]);
+      control.extend_from_slice(&strong_sum(&block)[..checksum_bytes]);
+    }
+    control
+  }
+
+  #[test]
+  fn test_match_seed() {
+    let old = (0..10_000u32)
+      .map(|index| (index * 7 % 251) as u8)
+      .collect::<Vec<_>>();
+    // 在开头插入数据并修改中间的一部分，其余块只是偏移发生了变化
+    let mut new = b"inserted".to_vec();
+    new.extend_from_slice(&old);
+    new[5000..5100].fill(0xff);
+
+    let dir = std::env::temp_dir().join(format!("robust_downloader_zsync_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let seed = dir.join("seed");
+    std::fs::write(&seed, &old).unwrap();
+
+    for lengths in [(1, 4, 16), (2, 2, 4)] {
+      let control = ControlFile::parse(&control_file(&new, 512, lengths)).unwrap();
+      assert_eq!(control.length, new.len() as u64);
+      let plan = control.match_seed(&seed).unwrap();
+
+      let mut seed_content = old.clone();
+      seed_content.resize(old.len() + 512, 0);
+      for (index, offset) in plan.iter().enumerate() {
+        if let Some(offset) = offset {
+          let start = index * 512;
+          let len = control.block_len(index) as usize;
+          let offset = *offset as usize;
+          assert_eq!(
+            &seed_content[offset..offset + len],
+            &new[start..start + len]
+          );
+        }
+      }
+      // 只有被修改的两个块需要下载，末尾不足一块的部分也能从种子文件中找到
+      let missing = plan.iter().filter(|offset| offset.is_none()).count();
+      assert_eq!(missing, 2, "{lengths:?}");
+    }
+
+    assert!(ControlFile::parse(b"zsync: 0.6.2\nLength: 10\n\n").is_err());
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}